@@ -1,8 +1,22 @@
 use serde::{Deserialize, Serialize};
 
+use crate::protocol::Subsystem;
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Config {
     pub pages: Vec<ConfigPage>,
+    #[serde(default)]
+    pub swipes: Swipes,
+}
+
+/// What three-finger swipes on a trackpad panel do, by the direction the fingers move in.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Swipes {
+    pub up: Option<RemoteCommand>,
+    pub down: Option<RemoteCommand>,
+    pub left: Option<RemoteCommand>,
+    pub right: Option<RemoteCommand>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -25,7 +39,15 @@ pub enum ConfigPageLayout {
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum ConfigPanel {
     Trackpad,
-    ButtonGrid { rows: Vec<Vec<ConfigButton>> },
+    ButtonGrid {
+        rows: Vec<Vec<ConfigButton>>,
+    },
+    /// A text field that opens the soft keyboard and types whatever is typed into it, with a row
+    /// of keys that soft keyboards don't have.
+    Keyboard,
+    /// An Xbox-style controller, with sticks, a d-pad, face buttons, bumpers and triggers. Each
+    /// client gets its own controller on the server.
+    Gamepad,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -34,25 +56,249 @@ pub struct ConfigButton {
     pub command: RemoteCommand,
 }
 
-#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum RemoteCommand {
-    TrackpadMove { delta_x: i32, delta_y: i32 },
-    TrackpadClick { button: TrackpadButton },
-    TrackpadScroll { delta_x: i32, delta_y: i32 },
+    /// How far the finger moved, in points. The server applies pointer acceleration.
+    TrackpadMove {
+        delta_x: f32,
+        delta_y: f32,
+        /// When the move happened, in milliseconds on the client's clock, which may start
+        /// anywhere and wrap around. Without it, every move is taken as the start of a swipe.
+        #[serde(default)]
+        time: Option<u32>,
+    },
+    TrackpadClick {
+        button: TrackpadButton,
+    },
+    /// Holds `button` down, until a [`Self::TrackpadRelease`] or until the client disconnects.
+    TrackpadPress {
+        button: TrackpadButton,
+    },
+    TrackpadRelease {
+        button: TrackpadButton,
+    },
+    /// In wheel clicks, which may be fractions for smooth scrolling. Positive is down and right.
+    TrackpadScroll {
+        delta_x: f32,
+        delta_y: f32,
+    },
+    /// Zooms like Ctrl+scroll. `factor` is how much further apart the fingers got, like 1.1 for
+    /// 10% further apart, which zooms in.
+    TrackpadZoom {
+        factor: f32,
+    },
+
+    /// Presses and releases a single key.
+    KeyboardPress {
+        key: Key,
+    },
+    /// Holds `modifiers` while pressing `key`, like Alt+F4.
+    KeyboardChord {
+        modifiers: Vec<Modifier>,
+        key: Key,
+    },
+    /// Types `text`, whatever keys that takes.
+    KeyboardText {
+        text: String,
+    },
+
+    /// Holds `button` down on this client's gamepad, until a [`Self::GamepadRelease`] or until the
+    /// client disconnects.
+    GamepadPress {
+        button: GamepadButton,
+    },
+    GamepadRelease {
+        button: GamepadButton,
+    },
+    /// Where `stick` is pushed, from -1 to 1 on each axis, where positive is right and down.
+    GamepadStick {
+        stick: GamepadStick,
+        x: f32,
+        y: f32,
+    },
+    /// How far `trigger` is pulled, from 0 to 1.
+    GamepadTrigger {
+        trigger: GamepadTrigger,
+        value: f32,
+    },
 
     CecImageViewOn,
     CecActiveSourceSelf,
-    CecActiveSource { physical_address: [u8; 4] },
+    CecActiveSource {
+        physical_address: [u8; 4],
+    },
     CecStandby,
 
-    MpdPlayPause,
-    MpdNext,
-    MpdPrevious,
+    #[serde(alias = "MpdPlayPause")]
+    MediaPlayPause,
+    #[serde(alias = "MpdNext")]
+    MediaNext,
+    #[serde(alias = "MpdPrevious")]
+    MediaPrevious,
+    MediaStop,
+    /// Relative to the current position.
+    MediaSeek {
+        seconds: i32,
+    },
+    /// From 0 to 100.
+    MediaSetVolume {
+        volume: u8,
+    },
+    MediaChangeVolume {
+        delta: i8,
+    },
+    MediaToggleShuffle,
+    MediaToggleRepeat,
+    /// Replaces what's playing with a stored playlist.
+    MediaLoadPlaylist {
+        name: String,
+    },
+    /// Asks for a [`ServerMessage::MediaPlayers`](crate::protocol::ServerMessage::MediaPlayers).
+    MediaListPlayers,
+    /// Sends media commands to `player` from now on, or to the server's default with `None`.
+    MediaSelectPlayer {
+        player: Option<String>,
+    },
+
+    /// Sets the volume of the server's default audio output, from 0 to 100.
+    AudioSetVolume {
+        volume: u8,
+    },
+    /// Changes the volume by `delta` percentage points, like 5 for louder or -5 for quieter.
+    AudioChangeVolume {
+        delta: i8,
+    },
+    AudioToggleMute,
+    /// Asks for an [`AudioSinks`](crate::protocol::ServerMessage::AudioSinks).
+    AudioListSinks,
+    /// Makes `sink` the default output, by its [`AudioSink::id`](crate::protocol::AudioSink::id).
+    AudioSetDefaultSink {
+        sink: String,
+    },
+}
+
+impl RemoteCommand {
+    /// The subsystem that executes this command on the server.
+    pub const fn subsystem(&self) -> Subsystem {
+        match self {
+            Self::TrackpadMove { .. }
+            | Self::TrackpadClick { .. }
+            | Self::TrackpadPress { .. }
+            | Self::TrackpadRelease { .. }
+            | Self::TrackpadScroll { .. }
+            | Self::TrackpadZoom { .. }
+            | Self::KeyboardPress { .. }
+            | Self::KeyboardChord { .. }
+            | Self::KeyboardText { .. } => Subsystem::Input,
+            Self::GamepadPress { .. }
+            | Self::GamepadRelease { .. }
+            | Self::GamepadStick { .. }
+            | Self::GamepadTrigger { .. } => Subsystem::Gamepad,
+            Self::CecImageViewOn
+            | Self::CecActiveSourceSelf
+            | Self::CecActiveSource { .. }
+            | Self::CecStandby => Subsystem::Cec,
+            Self::MediaPlayPause
+            | Self::MediaNext
+            | Self::MediaPrevious
+            | Self::MediaStop
+            | Self::MediaSeek { .. }
+            | Self::MediaSetVolume { .. }
+            | Self::MediaChangeVolume { .. }
+            | Self::MediaToggleShuffle
+            | Self::MediaToggleRepeat
+            | Self::MediaLoadPlaylist { .. }
+            | Self::MediaListPlayers
+            | Self::MediaSelectPlayer { .. } => Subsystem::Media,
+            Self::AudioSetVolume { .. }
+            | Self::AudioChangeVolume { .. }
+            | Self::AudioToggleMute
+            | Self::AudioListSinks
+            | Self::AudioSetDefaultSink { .. } => Subsystem::Audio,
+        }
+    }
 }
 
-#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
 pub enum TrackpadButton {
     Left,
     Right,
     Middle,
 }
+
+/// A button on a gamepad, named like on an Xbox controller.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum GamepadButton {
+    A,
+    B,
+    X,
+    Y,
+    LeftBumper,
+    RightBumper,
+    /// Also called Select or View.
+    Back,
+    /// Also called Menu.
+    Start,
+    /// The big button in the middle.
+    Guide,
+    /// Pressing the left stick in.
+    LeftStick,
+    RightStick,
+    DpadUp,
+    DpadDown,
+    DpadLeft,
+    DpadRight,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum GamepadStick {
+    Left,
+    Right,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum GamepadTrigger {
+    Left,
+    Right,
+}
+
+/// A key on the server's keyboard.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Key {
+    Escape,
+    Return,
+    Tab,
+    Backspace,
+    Delete,
+    Insert,
+    Space,
+    Up,
+    Down,
+    Left,
+    Right,
+    Home,
+    End,
+    PageUp,
+    PageDown,
+    /// `F(1)` to `F(24)`.
+    F(u8),
+    PrintScreen,
+    VolumeUp,
+    VolumeDown,
+    VolumeMute,
+    MediaPlayPause,
+    MediaNext,
+    MediaPrevious,
+    MediaStop,
+    /// Whichever key types this character.
+    Char(char),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Modifier {
+    Shift,
+    Control,
+    Alt,
+    /// The Super or Windows key.
+    Meta,
+}
@@ -0,0 +1,546 @@
+use core::fmt;
+
+use serde::{Deserialize, Serialize};
+
+use crate::config::{Config, RemoteCommand};
+
+/// DNS-SD service type servers advertise themselves as over mDNS.
+///
+/// The TXT record has these properties:
+/// - `path`: where the WebSocket is, like `/ws`
+/// - `tls`: `true` if the server only speaks WSS
+/// - `caps`: the server's [`Capability`]s by [`Capability::id`], separated by commas
+pub const MDNS_SERVICE_TYPE: &str = "_dark-remote._tcp.local.";
+
+/// Bumped whenever a message changes in a way the other side can't read. Clients and servers
+/// only talk to the same version.
+pub const PROTOCOL_VERSION: u32 = 8;
+
+/// The first message a client sends, before any command.
+///
+/// This and [`ServerMessage::Welcome`] are always JSON, and only ever gain fields with defaults,
+/// so a mismatch can be told apart from a broken message.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Hello {
+    /// The client's [`PROTOCOL_VERSION`].
+    pub version: u32,
+    /// What the client is, like "dark-remote-ui 0.1.0 (android)", for the server's logs.
+    pub client: String,
+    /// Encodings the client would rather send commands in, best first.
+    #[serde(default)]
+    pub encodings: Vec<Encoding>,
+}
+
+/// What a client sends after the [`Hello`]: a command, and maybe an ID to match the reply to it.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Request {
+    /// If set, the server replies with [`ServerMessage::Ack`] or [`ServerMessage::CommandFailed`]
+    /// with the same ID. Without one, only failures are reported.
+    ///
+    /// Comes first, so it can still be read from a request whose command can't be.
+    #[serde(default)]
+    pub id: Option<u32>,
+    pub command: RemoteCommand,
+}
+
+/// How a client encodes [`Request`]s. Messages from the server are always JSON.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Encoding {
+    /// Text messages, easy to type in tools like websocat. Always understood.
+    #[default]
+    Json,
+    /// Binary messages with [postcard](https://docs.rs/postcard), which are a fraction of the
+    /// size. Mostly worth it for the stream of trackpad moves.
+    ///
+    /// Enum variants are numbered by their order, so reordering them changes the protocol.
+    Postcard,
+}
+
+/// Messages pushed from the server to connected clients.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum ServerMessage {
+    /// The reply to a [`Hello`]. Sent before anything else, and followed by the server closing
+    /// the connection if the versions don't match.
+    Welcome {
+        /// The server's [`PROTOCOL_VERSION`].
+        version: u32,
+        /// Something to show to the user, like the server's hostname.
+        name: String,
+        /// What the server can do. Commands for a [`Subsystem`] without a capability will fail
+        /// with [`CommandError::Unavailable`].
+        capabilities: Vec<Capability>,
+        /// The first of the client's [`Hello::encodings`] the server understands, or JSON. Text
+        /// messages are JSON either way.
+        #[serde(default)]
+        encoding: Encoding,
+    },
+    /// This device was just paired with a pairing code. Sent right after the welcome.
+    Paired {
+        /// How the server refers to this device, to revoke it.
+        id: String,
+        /// Pass this as the `token` query parameter on every connection from now on.
+        token: String,
+    },
+    /// The layout the client should render. Sent right after the welcome.
+    Config(Config),
+    /// The [`Request`] with this ID was executed.
+    Ack { id: u32 },
+    /// A [`Request`] from this client couldn't be executed, or not even read.
+    CommandFailed {
+        /// The request's ID, if it had one that could be read.
+        id: Option<u32>,
+        error: CommandError,
+    },
+    /// The media players on the server, in reply to [`RemoteCommand::MediaListPlayers`] and
+    /// [`RemoteCommand::MediaSelectPlayer`].
+    MediaPlayers {
+        players: Vec<MediaPlayer>,
+        /// The [`MediaPlayer::id`] media commands currently go to.
+        active: Option<String>,
+    },
+    /// Whether the TV is on. Sent when the connection is opened and whenever it changes, like the
+    /// rest of [`RemoteState`].
+    TvPower(PowerStatus),
+    /// The physical address of the input the TV is showing, if anyone announced it.
+    ActiveSource(Option<[u8; 4]>),
+    /// What the active media player is doing, or `None` if there's no player.
+    NowPlaying(Option<NowPlaying>),
+    /// The audio outputs on the server, in reply to [`RemoteCommand::AudioListSinks`] and
+    /// [`RemoteCommand::AudioSetDefaultSink`].
+    AudioSinks {
+        sinks: Vec<AudioSink>,
+        /// The [`AudioSink::id`] of the default output.
+        default: Option<String>,
+    },
+    /// The default audio output's volume, or `None` if there's no output.
+    Audio(Option<AudioState>),
+}
+
+/// Everything the server keeps its clients up to date on.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct RemoteState {
+    pub tv_power: PowerStatus,
+    pub active_source: Option<[u8; 4]>,
+    pub now_playing: Option<NowPlaying>,
+    pub audio: Option<AudioState>,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum PowerStatus {
+    On,
+    Standby,
+    TurningOn,
+    TurningOff,
+    /// There's no TV, or it hasn't told us yet.
+    #[default]
+    Unknown,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct NowPlaying {
+    /// The [`MediaPlayer::id`] this is about.
+    pub player: String,
+    pub status: PlaybackStatus,
+    pub title: Option<String>,
+    /// All artists, joined with commas.
+    pub artist: Option<String>,
+    pub album: Option<String>,
+    /// The player's own volume, from 0 to 100, if it has one.
+    pub volume: Option<u8>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum PlaybackStatus {
+    Playing,
+    Paused,
+    Stopped,
+}
+
+/// The server's default audio output.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AudioState {
+    /// The [`AudioSink::id`] of the output.
+    pub sink: String,
+    /// In percent, which can be over 100 if something turned it up that far.
+    pub volume: u8,
+    pub muted: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AudioSink {
+    /// What to pass to [`RemoteCommand::AudioSetDefaultSink`].
+    pub id: String,
+    /// Something to show to the user, like "Built-in Audio Analog Stereo".
+    pub name: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MediaPlayer {
+    /// What to pass to [`RemoteCommand::MediaSelectPlayer`].
+    pub id: String,
+    /// Something to show to the user, like "mpv".
+    pub name: String,
+}
+
+/// The parts of the server that are started independently, and may not be available.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Subsystem {
+    /// Mouse and keyboard input injection.
+    Input,
+    Cec,
+    /// Music and video playback control.
+    Media,
+    /// Virtual gamepads.
+    Gamepad,
+    /// The system's volume.
+    Audio,
+}
+
+/// Something the server can do, which started successfully.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Capability {
+    /// Mouse and keyboard input injection.
+    Input,
+    Cec,
+    /// Media control over MPRIS.
+    Mpris,
+    /// Media control through MPD.
+    Mpd,
+    /// Virtual gamepads through uinput.
+    Gamepad,
+    /// System volume control through `PulseAudio` or `PipeWire`.
+    Audio,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum CommandError {
+    /// The subsystem needed for this command isn't available on the server.
+    Unavailable(Subsystem),
+    /// The CEC device the command was sent to didn't acknowledge it.
+    CecNack,
+    /// Anything else that went wrong.
+    Failed { subsystem: Subsystem, message: String },
+    /// The request couldn't be read, likely because it's from a different protocol version.
+    Invalid(String),
+}
+
+impl Capability {
+    pub const ALL: [Self; 6] =
+        [Self::Input, Self::Cec, Self::Mpris, Self::Mpd, Self::Gamepad, Self::Audio];
+
+    /// Lowercase name, for places where the serde representation is too much, like mDNS.
+    pub const fn id(self) -> &'static str {
+        match self {
+            Self::Input => "input",
+            Self::Cec => "cec",
+            Self::Mpris => "mpris",
+            Self::Mpd => "mpd",
+            Self::Gamepad => "gamepad",
+            Self::Audio => "audio",
+        }
+    }
+
+    pub fn from_id(id: &str) -> Option<Self> {
+        Self::ALL
+            .into_iter()
+            .find(|capability| capability.id() == id)
+    }
+
+    /// The subsystem whose commands this makes work.
+    pub const fn subsystem(self) -> Subsystem {
+        match self {
+            Self::Input => Subsystem::Input,
+            Self::Cec => Subsystem::Cec,
+            Self::Mpris | Self::Mpd => Subsystem::Media,
+            Self::Gamepad => Subsystem::Gamepad,
+            Self::Audio => Subsystem::Audio,
+        }
+    }
+}
+
+impl fmt::Display for Capability {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Input => "input",
+            Self::Cec => "CEC",
+            Self::Mpris => "MPRIS",
+            Self::Mpd => "MPD",
+            Self::Gamepad => "gamepad",
+            Self::Audio => "audio",
+        })
+    }
+}
+
+impl fmt::Display for Subsystem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Input => "input",
+            Self::Cec => "CEC",
+            Self::Media => "media",
+            Self::Gamepad => "gamepad",
+            Self::Audio => "audio",
+        })
+    }
+}
+
+impl fmt::Display for CommandError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Unavailable(subsystem) => write!(f, "{subsystem} is not available"),
+            Self::CecNack => f.write_str("CEC device did not respond"),
+            Self::Failed { subsystem, message } => write!(f, "{subsystem} error: {message}"),
+            Self::Invalid(message) => write!(f, "invalid request: {message}"),
+        }
+    }
+}
+
+impl RemoteState {
+    /// The messages that bring a client that knows `self` up to date with `new`.
+    pub fn changes(&self, new: &Self) -> Vec<ServerMessage> {
+        let mut changes = Vec::new();
+        if self.tv_power != new.tv_power {
+            changes.push(ServerMessage::TvPower(new.tv_power));
+        }
+        if self.active_source != new.active_source {
+            changes.push(ServerMessage::ActiveSource(new.active_source));
+        }
+        if self.now_playing != new.now_playing {
+            changes.push(ServerMessage::NowPlaying(new.now_playing.clone()));
+        }
+        if self.audio != new.audio {
+            changes.push(ServerMessage::Audio(new.audio.clone()));
+        }
+        changes
+    }
+
+    /// Updates the state with a message from the server. Other messages are ignored.
+    pub fn apply(&mut self, msg: &ServerMessage) {
+        match msg {
+            ServerMessage::TvPower(power) => self.tv_power = *power,
+            ServerMessage::ActiveSource(address) => self.active_source = *address,
+            ServerMessage::NowPlaying(now_playing) => self.now_playing.clone_from(now_playing),
+            ServerMessage::Audio(audio) => self.audio.clone_from(audio),
+            ServerMessage::Welcome { .. }
+            | ServerMessage::Paired { .. }
+            | ServerMessage::Config(_)
+            | ServerMessage::Ack { .. }
+            | ServerMessage::CommandFailed { .. }
+            | ServerMessage::MediaPlayers { .. }
+            | ServerMessage::AudioSinks { .. } => {}
+        }
+    }
+}
+
+impl fmt::Display for PowerStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::On => "on",
+            Self::Standby => "standby",
+            Self::TurningOn => "turning on",
+            Self::TurningOff => "turning off",
+            Self::Unknown => "unknown",
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde::de::DeserializeOwned;
+
+    use super::*;
+    use crate::config::{
+        ConfigButton, ConfigPage, ConfigPageLayout, ConfigPanel, Key, Modifier, Swipes,
+    };
+
+    /// Encodes `value` both ways, and checks it comes back the same. None of the messages are
+    /// `PartialEq`, so they're compared by their debug output.
+    fn round_trip<T: Serialize + DeserializeOwned + fmt::Debug>(value: &T) {
+        let expected = format!("{value:?}");
+
+        let json = serde_json::to_string(value).unwrap();
+        let decoded: T = serde_json::from_str(&json).unwrap();
+        assert_eq!(format!("{decoded:?}"), expected, "JSON: {json}");
+
+        let bytes = postcard::to_allocvec(value).unwrap();
+        let decoded: T = postcard::from_bytes(&bytes).unwrap();
+        assert_eq!(format!("{decoded:?}"), expected, "postcard: {bytes:?}");
+    }
+
+    fn now_playing() -> NowPlaying {
+        NowPlaying {
+            player: String::from("mpd"),
+            status: PlaybackStatus::Playing,
+            title: Some(String::from("Song")),
+            artist: Some(String::from("Artist")),
+            album: None,
+            volume: Some(40),
+        }
+    }
+
+    fn audio() -> AudioState {
+        AudioState {
+            sink: String::from("alsa_output"),
+            volume: 25,
+            muted: false,
+        }
+    }
+
+    #[test]
+    fn hello() {
+        round_trip(&Hello {
+            version: PROTOCOL_VERSION,
+            client: String::from("dark-remote-ui 0.1.0 (linux)"),
+            encodings: vec![Encoding::Postcard, Encoding::Json],
+        });
+    }
+
+    #[test]
+    fn hello_without_encodings() {
+        // from before clients could pick an encoding
+        let hello: Hello = serde_json::from_str(r#"{"version":8,"client":"websocat"}"#).unwrap();
+        assert!(hello.encodings.is_empty());
+    }
+
+    #[test]
+    fn request() {
+        let commands = [
+            RemoteCommand::TrackpadMove {
+                delta_x: 1.5,
+                delta_y: -2.25,
+                time: Some(1234),
+            },
+            RemoteCommand::TrackpadScroll { delta_x: 0., delta_y: -0.5 },
+            RemoteCommand::KeyboardChord {
+                modifiers: vec![Modifier::Alt],
+                key: Key::Tab,
+            },
+            RemoteCommand::KeyboardText { text: String::from("héllo") },
+        ];
+
+        for command in commands {
+            round_trip(&Request { id: None, command: command.clone() });
+            round_trip(&Request { id: Some(u32::MAX), command });
+        }
+    }
+
+    #[test]
+    fn server_message() {
+        let config = Config {
+            pages: vec![ConfigPage {
+                name: String::from("Main"),
+                layout: ConfigPageLayout::Linear {
+                    panels: vec![
+                        (70, ConfigPanel::Trackpad),
+                        (30, ConfigPanel::ButtonGrid {
+                            rows: vec![vec![ConfigButton {
+                                label: String::from("Esc"),
+                                command: RemoteCommand::KeyboardPress { key: Key::Escape },
+                            }]],
+                        }),
+                    ],
+                },
+            }],
+            swipes: Swipes::default(),
+        };
+
+        let messages = [
+            ServerMessage::Welcome {
+                version: PROTOCOL_VERSION,
+                name: String::from("tv"),
+                capabilities: Capability::ALL.to_vec(),
+                encoding: Encoding::Postcard,
+            },
+            ServerMessage::Paired {
+                id: String::from("phone"),
+                token: String::from("secret"),
+            },
+            ServerMessage::Config(config),
+            ServerMessage::Ack { id: 3 },
+            ServerMessage::CommandFailed {
+                id: None,
+                error: CommandError::Failed {
+                    subsystem: Subsystem::Audio,
+                    message: String::from("no pactl"),
+                },
+            },
+            ServerMessage::MediaPlayers {
+                players: vec![MediaPlayer {
+                    id: String::from("mpd"),
+                    name: String::from("MPD"),
+                }],
+                active: Some(String::from("mpd")),
+            },
+            ServerMessage::TvPower(PowerStatus::TurningOn),
+            ServerMessage::ActiveSource(Some([2, 0, 0, 0])),
+            ServerMessage::NowPlaying(Some(now_playing())),
+            ServerMessage::AudioSinks { sinks: vec![], default: None },
+            ServerMessage::Audio(Some(audio())),
+        ];
+
+        for message in &messages {
+            round_trip(message);
+        }
+    }
+
+    #[test]
+    fn no_changes() {
+        let state = RemoteState {
+            audio: Some(audio()),
+            ..RemoteState::default()
+        };
+        assert!(state.changes(&state.clone()).is_empty());
+    }
+
+    #[test]
+    fn changes_apply() {
+        let old = RemoteState {
+            tv_power: PowerStatus::Standby,
+            active_source: Some([1, 0, 0, 0]),
+            now_playing: None,
+            audio: Some(audio()),
+        };
+        let new = RemoteState {
+            tv_power: PowerStatus::On,
+            active_source: Some([1, 0, 0, 0]),
+            now_playing: Some(now_playing()),
+            audio: None,
+        };
+
+        let changes = old.changes(&new);
+        assert!(matches!(changes.as_slice(), [
+            ServerMessage::TvPower(PowerStatus::On),
+            ServerMessage::NowPlaying(Some(_)),
+            ServerMessage::Audio(None),
+        ]));
+
+        let mut state = old;
+        for change in &changes {
+            state.apply(change);
+        }
+        assert_eq!(state, new);
+    }
+
+    #[test]
+    fn from_nothing() {
+        // what a client that just connected is sent
+        let new = RemoteState {
+            tv_power: PowerStatus::On,
+            active_source: Some([2, 0, 0, 0]),
+            now_playing: Some(now_playing()),
+            audio: Some(audio()),
+        };
+
+        let mut state = RemoteState::default();
+        for change in &RemoteState::default().changes(&new) {
+            state.apply(change);
+        }
+        assert_eq!(state, new);
+    }
+
+    #[test]
+    fn apply_ignores_other_messages() {
+        let mut state = RemoteState::default();
+        state.apply(&ServerMessage::Ack { id: 1 });
+        assert_eq!(state, RemoteState::default());
+    }
+}
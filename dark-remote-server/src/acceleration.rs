@@ -0,0 +1,315 @@
+//! Pointer acceleration, which turns the raw trackpad deltas clients send into pixels.
+//!
+//! Velocities are in the client's points per second, so the same profile feels the same on phones
+//! with different screens.
+
+use std::collections::HashMap;
+
+use serde::{Deserialize, Deserializer};
+
+use crate::pairing::Device;
+
+/// Deltas further apart than this, in milliseconds, are from separate swipes.
+const GESTURE_GAP: u32 = 100;
+
+/// How much the latest delta counts towards the velocity. The rest is the velocity so far, which
+/// evens out the jitter in when touch events arrive.
+const VELOCITY_SMOOTHING: f32 = 0.5;
+
+/// The `[pointer]` section of the config file.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct PointerConfig {
+    /// Used for devices that aren't in [`Self::devices`], and when pairing is turned off.
+    #[serde(flatten)]
+    pub acceleration: Acceleration,
+    /// Overrides for paired devices, by ID or name.
+    pub devices: HashMap<String, Acceleration>,
+}
+
+impl PointerConfig {
+    /// The acceleration for `device`, or the default one.
+    pub fn for_device(&self, device: Option<&Device>) -> &Acceleration {
+        device
+            .and_then(|device| {
+                self.devices
+                    .get(&device.id)
+                    .or_else(|| self.devices.get(&device.name))
+            })
+            .unwrap_or(&self.acceleration)
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct Acceleration {
+    pub profile: Profile,
+    /// Multiplies the result of the profile.
+    pub speed: f32,
+    /// For the `linear` profile: how much the factor grows per 1000 points per second.
+    pub slope: f32,
+    /// For the `linear` and `adaptive` profiles: the largest factor, before `speed`.
+    pub max: f32,
+    /// For the `custom` profile: `[velocity, factor]` pairs, interpolated in between and flat past
+    /// either end. Sorted by velocity when the config is read.
+    #[serde(deserialize_with = "sorted")]
+    pub points: Vec<(f32, f32)>,
+}
+
+impl Default for Acceleration {
+    fn default() -> Self {
+        Self {
+            profile: Profile::Adaptive,
+            speed: 1.,
+            slope: 1.5,
+            max: 5.,
+            points: Vec::new(),
+        }
+    }
+}
+
+#[derive(Debug, Default, Clone, Copy, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Profile {
+    /// The pointer moves as far as the finger, times `speed`.
+    Flat,
+    /// The factor grows steadily with velocity, up to `max`.
+    Linear,
+    /// Like libinput's adaptive profile: slower than the finger when moving slowly, for
+    /// precision, then one to one, then speeding up past a threshold, up to `max`.
+    #[default]
+    Adaptive,
+    /// The factor comes from `points`.
+    Custom,
+}
+
+impl Acceleration {
+    /// How much to multiply a delta by at `velocity`, in points per second.
+    pub fn factor(&self, velocity: f32) -> f32 {
+        let factor = match self.profile {
+            Profile::Flat => 1.,
+            Profile::Linear => (1. + velocity * self.slope / 1000.).min(self.max),
+            Profile::Adaptive => adaptive(velocity).min(self.max),
+            Profile::Custom => interpolate(&self.points, velocity),
+        };
+        factor * self.speed
+    }
+}
+
+/// The adaptive curve, before `max` and `speed`.
+fn adaptive(velocity: f32) -> f32 {
+    /// Below this the pointer is slowed down.
+    const PRECISE: f32 = 100.;
+    /// Above this the pointer is sped up.
+    const THRESHOLD: f32 = 300.;
+    /// Per 1000 points per second above the threshold.
+    const INCLINE: f32 = 2.;
+
+    if velocity < PRECISE {
+        0.5 + 0.5 * velocity / PRECISE
+    } else if velocity <= THRESHOLD {
+        1.
+    } else {
+        1. + (velocity - THRESHOLD) * INCLINE / 1000.
+    }
+}
+
+/// The factor at `velocity` on the line through `points`, which are sorted by velocity. Without
+/// points the factor is 1.
+fn interpolate(points: &[(f32, f32)], velocity: f32) -> f32 {
+    let (Some(&first), Some(&last)) = (points.first(), points.last()) else {
+        return 1.;
+    };
+
+    if velocity <= first.0 {
+        return first.1;
+    }
+
+    points
+        .windows(2)
+        .find_map(|pair| {
+            let [(v0, f0), (v1, f1)] = *pair else {
+                return None;
+            };
+            (velocity <= v1).then(|| f0 + (f1 - f0) * (velocity - v0) / (v1 - v0))
+        })
+        .unwrap_or(last.1)
+}
+
+/// Reads `[velocity, factor]` pairs sorted by velocity, so [`interpolate`] doesn't have to sort them
+/// on every move.
+fn sorted<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<(f32, f32)>, D::Error> {
+    let mut points = Vec::<(f32, f32)>::deserialize(deserializer)?;
+    points.sort_by(|a, b| a.0.total_cmp(&b.0));
+    Ok(points)
+}
+
+/// Applies an [`Acceleration`] to one client's deltas, keeping track of how fast it's moving.
+pub struct Accelerator {
+    acceleration: Acceleration,
+    /// Client time of the last delta, in milliseconds.
+    last_time: Option<u32>,
+    /// In points per second.
+    velocity: f32,
+}
+
+impl Accelerator {
+    pub fn new(acceleration: Acceleration) -> Self {
+        Self {
+            acceleration,
+            last_time: None,
+            velocity: 0.,
+        }
+    }
+
+    /// Turns a delta in points, sent at `time` in milliseconds on the client's clock, into
+    /// pixels. A delta without a time is taken as the first of a swipe.
+    pub fn accelerate(&mut self, delta_x: f32, delta_y: f32, time: Option<u32>) -> (f32, f32) {
+        let distance = delta_x.hypot(delta_y);
+
+        // the clock may wrap around, it's only ever compared to itself
+        let elapsed = time
+            .zip(self.last_time)
+            .map(|(time, last)| time.wrapping_sub(last));
+        self.last_time = time;
+
+        match elapsed {
+            Some(elapsed) if elapsed <= GESTURE_GAP => {
+                let velocity = distance / elapsed.max(1) as f32 * 1000.;
+                self.velocity += (velocity - self.velocity) * VELOCITY_SMOOTHING;
+            }
+            // the first delta of a swipe doesn't say how long it took
+            _ => self.velocity = distance / GESTURE_GAP as f32 * 1000.,
+        }
+
+        let factor = self.acceleration.factor(self.velocity);
+        (delta_x * factor, delta_y * factor)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const POINTS: [(f32, f32); 3] = [(100., 0.5), (500., 1.), (1500., 3.)];
+
+    #[test]
+    fn below_the_points() {
+        assert_eq!(interpolate(&POINTS, 0.), 0.5);
+        assert_eq!(interpolate(&POINTS, 100.), 0.5);
+    }
+
+    #[test]
+    fn between_the_points() {
+        assert_eq!(interpolate(&POINTS, 300.), 0.75);
+        assert_eq!(interpolate(&POINTS, 500.), 1.);
+        assert_eq!(interpolate(&POINTS, 1000.), 2.);
+    }
+
+    #[test]
+    fn above_the_points() {
+        assert_eq!(interpolate(&POINTS, 1500.), 3.);
+        assert_eq!(interpolate(&POINTS, 10_000.), 3.);
+    }
+
+    #[test]
+    fn single_point() {
+        assert_eq!(interpolate(&[(200., 2.)], 0.), 2.);
+        assert_eq!(interpolate(&[(200., 2.)], 1000.), 2.);
+    }
+
+    #[test]
+    fn no_points() {
+        assert_eq!(interpolate(&[], 300.), 1.);
+    }
+
+    #[test]
+    fn sorted_when_read() {
+        let acceleration: Acceleration = toml::from_str(
+            "profile = \"custom\"\nspeed = 2\npoints = [[1500, 3], [100, 0.5], [500, 1]]",
+        )
+        .unwrap();
+        assert_eq!(acceleration.points, POINTS);
+        assert_eq!(acceleration.factor(300.), 1.5);
+    }
+
+    /// Speeds deltas up by 1 per 1000 points per second, so the factor gives away the velocity.
+    fn accelerator() -> Accelerator {
+        Accelerator::new(Acceleration {
+            profile: Profile::Linear,
+            slope: 1.,
+            max: f32::MAX,
+            ..Acceleration::default()
+        })
+    }
+
+    fn assert_moved(moved: (f32, f32), expected: (f32, f32)) {
+        assert!(
+            (moved.0 - expected.0).abs() < 1e-3 && (moved.1 - expected.1).abs() < 1e-3,
+            "moved {moved:?}, expected {expected:?}"
+        );
+    }
+
+    #[test]
+    fn first_move() {
+        // 10 points taken to have been moved in GESTURE_GAP, so at 100 points per second
+        assert_moved(accelerator().accelerate(6., 8., Some(1000)), (6.6, 8.8));
+    }
+
+    #[test]
+    fn velocity_is_smoothed() {
+        let mut accelerator = accelerator();
+        accelerator.accelerate(10., 0., Some(0));
+        // 1000 points per second now, halfway there from 100
+        assert_moved(accelerator.accelerate(10., 0., Some(10)), (15.5, 0.));
+        // from 550 to 1000
+        assert_moved(accelerator.accelerate(10., 0., Some(20)), (17.75, 0.));
+    }
+
+    #[test]
+    fn slowing_down() {
+        let mut accelerator = accelerator();
+        accelerator.accelerate(10., 0., Some(0));
+        accelerator.accelerate(10., 0., Some(10));
+        // from 550 to 0
+        assert_moved(accelerator.accelerate(0., 0., Some(20)), (0., 0.));
+        assert_moved(accelerator.accelerate(10., 0., Some(30)), (16.375, 0.));
+    }
+
+    #[test]
+    fn new_swipe_after_a_gap() {
+        let mut accelerator = accelerator();
+        accelerator.accelerate(10., 0., Some(0));
+        accelerator.accelerate(10., 0., Some(10));
+        // slow again, instead of carrying on from 550
+        assert_moved(accelerator.accelerate(10., 0., Some(10 + GESTURE_GAP + 1)), (11., 0.));
+    }
+
+    #[test]
+    fn clock_wraps_around() {
+        let mut accelerator = accelerator();
+        accelerator.accelerate(10., 0., Some(u32::MAX - 4));
+        assert_moved(accelerator.accelerate(10., 0., Some(5)), (15.5, 0.));
+    }
+
+    #[test]
+    fn clock_goes_back() {
+        let mut accelerator = accelerator();
+        accelerator.accelerate(10., 0., Some(1000));
+        // a new clock, as far as anyone can tell
+        assert_moved(accelerator.accelerate(10., 0., Some(990)), (11., 0.));
+    }
+
+    #[test]
+    fn without_time() {
+        let mut accelerator = accelerator();
+        // every move is the first of a swipe, rather than all of them at once
+        assert_moved(accelerator.accelerate(10., 0., None), (11., 0.));
+        assert_moved(accelerator.accelerate(10., 0., None), (11., 0.));
+
+        // and so is the next one with a time
+        accelerator.accelerate(10., 0., Some(0));
+        accelerator.accelerate(10., 0., None);
+        assert_moved(accelerator.accelerate(10., 0., Some(10)), (11., 0.));
+    }
+}
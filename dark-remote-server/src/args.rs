@@ -0,0 +1,62 @@
+use std::{net::SocketAddr, path::PathBuf};
+
+use clap::{Parser, Subcommand};
+
+use crate::config::ConfigFile;
+
+/// Server for dark remote. Options given here override the ones in the config file.
+#[derive(Debug, Parser)]
+#[command(version)]
+pub struct Args {
+    #[command(subcommand)]
+    pub command: Option<Command>,
+
+    /// Config file to use [default: ~/.config/dark-remote/config.toml]
+    #[arg(short, long)]
+    pub config: Option<PathBuf>,
+
+    /// Address to listen on, can be given multiple times [default: 0.0.0.0:3000]
+    #[arg(short, long)]
+    pub listen: Vec<SocketAddr>,
+
+    /// CEC device to use [default: /dev/cec0]
+    #[arg(long)]
+    pub cec_device: Option<PathBuf>,
+
+    /// Directory containing the built web UI
+    #[arg(long)]
+    pub assets_dir: Option<PathBuf>,
+
+    /// Log filter
+    #[arg(long, env = "RUST_LOG")]
+    pub log: Option<String>,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum Command {
+    /// List the devices paired with this server
+    Devices,
+    /// Unpair a device, so it has to be paired again to connect
+    Revoke {
+        /// ID or name of the device, as shown by `devices`
+        device: String,
+    },
+}
+
+impl Args {
+    /// Overrides everything in `config` that was given on the command line.
+    pub fn apply(self, config: &mut ConfigFile) {
+        if !self.listen.is_empty() {
+            config.server.listen = self.listen;
+        }
+        if let Some(cec_device) = self.cec_device {
+            config.cec.device = cec_device;
+        }
+        if let Some(assets_dir) = self.assets_dir {
+            config.server.assets_dir = Some(assets_dir);
+        }
+        if let Some(log) = self.log {
+            config.server.log = log;
+        }
+    }
+}
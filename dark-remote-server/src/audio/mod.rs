@@ -0,0 +1,76 @@
+//! The system's sound output, through a pluggable [`AudioBackend`].
+
+pub use dark_remote_common::protocol::{AudioSink, AudioState};
+use thiserror::Error;
+
+pub use self::{
+    pulse::Pulse,
+    simulated::{SimulatedAudio, SimulatedSink},
+};
+
+mod pulse;
+mod simulated;
+
+/// Something that controls the volume of the server's audio outputs, which are called sinks.
+///
+/// Volume changes go to the default sink.
+///
+/// # Errors
+///
+/// Every method fails if the sound server can't be reached, or if there's no sink to change.
+#[expect(clippy::missing_errors_doc)] // documented once, above
+pub trait AudioBackend: Send {
+    /// The outputs that can be made the default.
+    fn sinks(&mut self) -> Result<Vec<AudioSink>, Error>;
+
+    /// The [`AudioSink::id`] of the default sink, if there is one.
+    fn default_sink(&mut self) -> Result<Option<String>, Error>;
+
+    /// The default sink's volume, or `None` if there's no sink.
+    fn state(&mut self) -> Result<Option<AudioState>, Error>;
+
+    /// Sets the volume, in percent, even past 100%.
+    fn set_volume_uncapped(&mut self, volume: u8) -> Result<(), Error>;
+
+    /// Sets the volume, in percent. Past 100% the sound distorts, so that's as far as it goes.
+    fn set_volume(&mut self, volume: u8) -> Result<(), Error> {
+        self.set_volume_uncapped(volume.min(100))
+    }
+
+    /// Changes the volume by `delta` percentage points, as far as [`changed_volume`] goes. That's
+    /// past the cap of [`Self::set_volume`] if the volume already was, so a sink something else
+    /// turned up can be turned down a step at a time.
+    fn change_volume(&mut self, delta: i8) -> Result<(), Error> {
+        let volume = self.state()?.ok_or(Error::NoSink)?.volume;
+        self.set_volume_uncapped(changed_volume(volume, delta))
+    }
+
+    fn toggle_mute(&mut self) -> Result<(), Error>;
+
+    /// Makes the sink with this [`AudioSink::id`] the default.
+    fn set_default_sink(&mut self, id: &str) -> Result<(), Error>;
+}
+
+#[derive(Error, Debug)]
+pub enum Error {
+    #[error("Failed to run {command}: {source}")]
+    Spawn { command: String, source: std::io::Error },
+    #[error("{command} failed: {message}")]
+    Command { command: String, message: String },
+    #[error("{0} did not finish in time")]
+    Timeout(String),
+    #[error("Unexpected answer from the sound server: {0}")]
+    Parse(#[from] serde_json::Error),
+    #[error("There is no audio output")]
+    NoSink,
+    #[error("No audio output called {0:?}")]
+    UnknownSink(String),
+}
+
+/// `volume` changed by `delta`, like volume keys do it: turning it up stops at 100%, unless it was
+/// already above that, and turning it down stops at 0%.
+pub fn changed_volume(volume: u8, delta: i8) -> u8 {
+    let max = volume.max(100);
+    let changed = volume.saturating_add_signed(delta);
+    if delta > 0 { changed.min(max) } else { changed }
+}
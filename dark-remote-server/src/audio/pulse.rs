@@ -0,0 +1,230 @@
+use core::time::Duration;
+use std::{
+    collections::BTreeMap,
+    io::Read,
+    process::{Command, Stdio},
+    thread::JoinHandle,
+    time::Instant,
+};
+
+use serde::Deserialize;
+
+use super::{AudioBackend, AudioSink, AudioState, Error};
+
+/// The sink `pactl` changes when it's given this instead of a name.
+const DEFAULT_SINK: &str = "@DEFAULT_SINK@";
+
+/// `pactl`'s volume for 100%.
+const VOLUME_NORM: u64 = 0x10000;
+
+/// How long `pactl` gets, so a hung sound server doesn't hang the service with it.
+const TIMEOUT: Duration = Duration::from_secs(2);
+
+/// How often to check whether `pactl` is done.
+const WAIT_INTERVAL: Duration = Duration::from_millis(5);
+
+/// `PulseAudio`, or `PipeWire` through `pipewire-pulse`, by running `pactl`. Needs version 16 or
+/// later, for JSON output.
+pub struct Pulse;
+
+/// The parts of a sink in `pactl --format=json list sinks` we care about.
+#[derive(Deserialize)]
+struct Sink {
+    name: String,
+    description: String,
+    mute: bool,
+    /// By channel, like `front-left`.
+    volume: BTreeMap<String, Channel>,
+}
+
+#[derive(Deserialize)]
+struct Channel {
+    value: u64,
+}
+
+impl Pulse {
+    /// Checks that the sound server can be reached.
+    ///
+    /// # Errors
+    ///
+    /// Fails if `pactl` isn't installed, or can't connect to the sound server.
+    pub fn connect() -> Result<Self, Error> {
+        pactl(&["info"])?;
+        Ok(Self)
+    }
+
+    fn list_sinks() -> Result<Vec<Sink>, Error> {
+        Ok(serde_json::from_str(&pactl(&["--format=json", "list", "sinks"])?)?)
+    }
+}
+
+impl AudioBackend for Pulse {
+    fn sinks(&mut self) -> Result<Vec<AudioSink>, Error> {
+        Ok(Self::list_sinks()?
+            .into_iter()
+            .map(|sink| AudioSink {
+                id: sink.name,
+                name: sink.description,
+            })
+            .collect())
+    }
+
+    fn default_sink(&mut self) -> Result<Option<String>, Error> {
+        let name = pactl(&["get-default-sink"])?;
+        Ok(Some(name.trim().to_owned()).filter(|name| !name.is_empty()))
+    }
+
+    fn state(&mut self) -> Result<Option<AudioState>, Error> {
+        let Some(default) = self.default_sink()? else {
+            return Ok(None);
+        };
+
+        Ok(Self::list_sinks()?
+            .into_iter()
+            .find(|sink| sink.name == default)
+            .map(|sink| AudioState {
+                volume: percent(&sink.volume),
+                muted: sink.mute,
+                sink: sink.name,
+            }))
+    }
+
+    fn set_volume_uncapped(&mut self, volume: u8) -> Result<(), Error> {
+        pactl(&["set-sink-volume", DEFAULT_SINK, &format!("{volume}%")]).map(drop)
+    }
+
+    fn toggle_mute(&mut self) -> Result<(), Error> {
+        pactl(&["set-sink-mute", DEFAULT_SINK, "toggle"]).map(drop)
+    }
+
+    fn set_default_sink(&mut self, id: &str) -> Result<(), Error> {
+        if !Self::list_sinks()?.iter().any(|sink| sink.name == id) {
+            return Err(Error::UnknownSink(id.to_owned()));
+        }
+        pactl(&["set-default-sink", id]).map(drop)
+    }
+}
+
+/// Runs `pactl` with `args`, and returns what it printed. It's killed if it takes longer than
+/// [`TIMEOUT`].
+fn pactl(args: &[&str]) -> Result<String, Error> {
+    let description = format!("pactl {}", args.join(" "));
+    run(Command::new("pactl").args(args), &description, TIMEOUT)
+}
+
+/// Runs `command`, which is called `description` in errors, for at most `timeout`.
+fn run(command: &mut Command, description: &str, timeout: Duration) -> Result<String, Error> {
+    let spawn_error = |source| Error::Spawn {
+        command: description.to_owned(),
+        source,
+    };
+    let mut child = command
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(spawn_error)?;
+
+    // read while it runs, or it blocks once the pipe is full
+    let stdout = read_to_end(child.stdout.take());
+    let stderr = read_to_end(child.stderr.take());
+
+    let deadline = Instant::now() + timeout;
+    let status = loop {
+        if let Some(status) = child.try_wait().map_err(spawn_error)? {
+            break status;
+        }
+        if Instant::now() >= deadline {
+            child.kill().ok();
+            child.wait().ok();
+            return Err(Error::Timeout(description.to_owned()));
+        }
+        std::thread::sleep(WAIT_INTERVAL);
+    };
+
+    let stdout = stdout.join().unwrap_or_default();
+    let stderr = stderr.join().unwrap_or_default();
+
+    if !status.success() {
+        return Err(Error::Command {
+            command: description.to_owned(),
+            message: String::from_utf8_lossy(&stderr).trim().to_owned(),
+        });
+    }
+    Ok(String::from_utf8_lossy(&stdout).into_owned())
+}
+
+/// Reads `pipe` on another thread, until the other end closes it.
+fn read_to_end(pipe: Option<impl Read + Send + 'static>) -> JoinHandle<Vec<u8>> {
+    std::thread::spawn(move || {
+        let mut bytes = Vec::new();
+        if let Some(mut pipe) = pipe {
+            pipe.read_to_end(&mut bytes).ok();
+        }
+        bytes
+    })
+}
+
+/// The average volume of the channels, in percent.
+fn percent(channels: &BTreeMap<String, Channel>) -> u8 {
+    let total = channels.values().map(|channel| channel.value).sum::<u64>();
+    let count = (channels.len() as u64).max(1);
+    let percent = (total * 100 / count + VOLUME_NORM / 2) / VOLUME_NORM;
+    u8::try_from(percent).unwrap_or(u8::MAX)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sh(script: &str, timeout: Duration) -> Result<String, Error> {
+        run(Command::new("sh").args(["-c", script]), script, timeout)
+    }
+
+    #[test]
+    fn output() {
+        assert_eq!(sh("echo 42", TIMEOUT).unwrap(), "42\n");
+    }
+
+    #[test]
+    fn more_output_than_a_pipe_holds() {
+        let output = sh("head -c 1000000 /dev/zero", TIMEOUT).unwrap();
+        assert_eq!(output.len(), 1_000_000);
+    }
+
+    #[test]
+    fn failure() {
+        let error = sh("echo 'No such entity' >&2; exit 1", TIMEOUT).unwrap_err();
+        assert!(matches!(error, Error::Command { message, .. } if message == "No such entity"));
+    }
+
+    #[test]
+    fn timeout() {
+        let start = Instant::now();
+        let error = sh("sleep 10", Duration::from_millis(100)).unwrap_err();
+        assert!(matches!(error, Error::Timeout(_)));
+        assert!(start.elapsed() < Duration::from_secs(5), "not killed");
+    }
+
+    #[test]
+    fn not_installed() {
+        let result = run(&mut Command::new("dark-remote-no-such-program"), "", TIMEOUT);
+        assert!(matches!(result, Err(Error::Spawn { .. })));
+    }
+
+    #[test]
+    fn percent_of_channels() {
+        let channels = |values: &[u64]| {
+            values
+                .iter()
+                .enumerate()
+                .map(|(i, &value)| (i.to_string(), Channel { value }))
+                .collect::<BTreeMap<_, _>>()
+        };
+
+        assert_eq!(percent(&channels(&[VOLUME_NORM, VOLUME_NORM])), 100);
+        assert_eq!(percent(&channels(&[VOLUME_NORM / 2, VOLUME_NORM])), 75);
+        assert_eq!(percent(&channels(&[VOLUME_NORM * 3])), u8::MAX);
+        assert_eq!(percent(&channels(&[])), 0);
+    }
+}
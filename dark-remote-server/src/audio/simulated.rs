@@ -0,0 +1,122 @@
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+
+use super::{AudioBackend, AudioSink, AudioState, Error};
+
+/// Audio outputs that only exist in memory, for trying things out without a sound server.
+///
+/// Clones share the same outputs, so one handle can be given to the service while another is kept
+/// around to look at what it did.
+#[derive(Clone, Default)]
+pub struct SimulatedAudio(Arc<Mutex<Outputs>>);
+
+#[derive(Default)]
+struct Outputs {
+    sinks: Vec<SimulatedSink>,
+    /// The [`SimulatedSink::id`] of the default sink.
+    default: Option<String>,
+}
+
+/// A fake output of [`SimulatedAudio`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SimulatedSink {
+    pub id: String,
+    pub name: String,
+    /// In percent.
+    pub volume: u8,
+    pub muted: bool,
+}
+
+impl SimulatedSink {
+    /// An output at 50%, not muted.
+    pub fn new(id: &str, name: &str) -> Self {
+        Self {
+            id: id.to_owned(),
+            name: name.to_owned(),
+            volume: 50,
+            muted: false,
+        }
+    }
+}
+
+impl SimulatedAudio {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds `sink`, which becomes the default if it's the first one.
+    #[must_use]
+    pub fn with_sink(self, sink: SimulatedSink) -> Self {
+        {
+            let mut outputs = self.lock();
+            outputs.default.get_or_insert_with(|| sink.id.clone());
+            outputs.sinks.push(sink);
+        }
+        self
+    }
+
+    /// The current state of the sink called `id`.
+    pub fn sink(&self, id: &str) -> Option<SimulatedSink> {
+        self.lock().sinks.iter().find(|sink| sink.id == id).cloned()
+    }
+
+    fn lock(&self) -> MutexGuard<'_, Outputs> {
+        self.0.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+impl Outputs {
+    fn default_sink(&mut self) -> Result<&mut SimulatedSink, Error> {
+        let default = self.default.as_ref().ok_or(Error::NoSink)?;
+        self.sinks
+            .iter_mut()
+            .find(|sink| sink.id == *default)
+            .ok_or(Error::NoSink)
+    }
+}
+
+impl AudioBackend for SimulatedAudio {
+    fn sinks(&mut self) -> Result<Vec<AudioSink>, Error> {
+        Ok(self
+            .lock()
+            .sinks
+            .iter()
+            .map(|sink| AudioSink {
+                id: sink.id.clone(),
+                name: sink.name.clone(),
+            })
+            .collect())
+    }
+
+    fn default_sink(&mut self) -> Result<Option<String>, Error> {
+        Ok(self.lock().default.clone())
+    }
+
+    fn state(&mut self) -> Result<Option<AudioState>, Error> {
+        Ok(self.lock().default_sink().ok().map(|sink| AudioState {
+            sink: sink.id.clone(),
+            volume: sink.volume,
+            muted: sink.muted,
+        }))
+    }
+
+    fn set_volume_uncapped(&mut self, volume: u8) -> Result<(), Error> {
+        self.lock().default_sink()?.volume = volume;
+        Ok(())
+    }
+
+    fn toggle_mute(&mut self) -> Result<(), Error> {
+        let mut outputs = self.lock();
+        let sink = outputs.default_sink()?;
+        sink.muted = !sink.muted;
+        Ok(())
+    }
+
+    fn set_default_sink(&mut self, id: &str) -> Result<(), Error> {
+        let mut outputs = self.lock();
+        if !outputs.sinks.iter().any(|sink| sink.id == id) {
+            return Err(Error::UnknownSink(id.to_owned()));
+        }
+        outputs.default = Some(id.to_owned());
+        Ok(())
+    }
+}
@@ -0,0 +1,96 @@
+//! Just enough EDID parsing to find the physical address the sink assigned to us.
+
+use super::{PhysicalAddress, decode_physical_address};
+
+const BLOCK_SIZE: usize = 128;
+const CEA_EXTENSION_TAG: u8 = 0x02;
+const VENDOR_SPECIFIC_DATA_BLOCK: u8 = 3;
+/// HDMI Licensing, LLC, little endian like in the EDID.
+const HDMI_OUI: [u8; 3] = [0x03, 0x0c, 0x00];
+
+/// Finds the physical address in the HDMI vendor-specific data block of a CEA-861 extension.
+///
+/// Returns `None` if `edid` is truncated, has no CEA extension, or the sink isn't HDMI.
+pub fn physical_address(edid: &[u8]) -> Option<PhysicalAddress> {
+    let extensions = usize::from(*edid.get(126)?);
+
+    edid.as_chunks::<BLOCK_SIZE>()
+        .0
+        .iter()
+        .skip(1)
+        .take(extensions)
+        .filter(|block| block[0] == CEA_EXTENSION_TAG)
+        .find_map(cea_physical_address)
+}
+
+fn cea_physical_address(block: &[u8; BLOCK_SIZE]) -> Option<PhysicalAddress> {
+    // data blocks run from byte 4 up to the first detailed timing descriptor
+    let end = usize::from(block[2]).min(BLOCK_SIZE);
+    let mut data_blocks = block.get(4..end)?;
+
+    while let Some((&header, rest)) = data_blocks.split_first() {
+        let tag = header >> 5;
+        let len = usize::from(header & 0x1f);
+        let (payload, rest) = rest.split_at_checked(len)?;
+
+        if tag == VENDOR_SPECIFIC_DATA_BLOCK
+            && let Some((&oui, &[ab, cd, ..])) = payload.split_first_chunk::<3>()
+            && oui == HDMI_OUI
+        {
+            // f.f.f.f means the sink doesn't know either
+            return ([ab, cd] != [0xff, 0xff]).then(|| decode_physical_address([ab, cd]));
+        }
+
+        data_blocks = rest;
+    }
+
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A base block and a CEA-861 extension, with an audio data block before the HDMI
+    /// vendor-specific one.
+    fn edid(vendor_specific: [u8; 5]) -> Vec<u8> {
+        let mut edid = vec![0; BLOCK_SIZE * 2];
+        edid[..8].copy_from_slice(&[0x00, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0x00]);
+        edid[126] = 1;
+
+        let data_blocks = [&[0x23, 0x09, 0x07, 0x07][..], &[0x65], &vendor_specific].concat();
+        let extension = &mut edid[BLOCK_SIZE..];
+        extension[..4].copy_from_slice(&[CEA_EXTENSION_TAG, 0x03, 4 + data_blocks.len() as u8, 0]);
+        extension[4..][..data_blocks.len()].copy_from_slice(&data_blocks);
+        edid
+    }
+
+    #[test]
+    fn hdmi_sink() {
+        assert_eq!(physical_address(&edid([0x03, 0x0c, 0x00, 0x21, 0x00])), Some([2, 1, 0, 0]));
+    }
+
+    #[test]
+    fn unknown_address() {
+        assert_eq!(physical_address(&edid([0x03, 0x0c, 0x00, 0xff, 0xff])), None);
+    }
+
+    #[test]
+    fn other_vendor() {
+        assert_eq!(physical_address(&edid([0xd8, 0x5d, 0xc4, 0x21, 0x00])), None);
+    }
+
+    #[test]
+    fn no_extension() {
+        let mut edid = edid([0x03, 0x0c, 0x00, 0x21, 0x00]);
+        edid[126] = 0;
+        assert_eq!(physical_address(&edid), None);
+    }
+
+    #[test]
+    fn truncated() {
+        let edid = edid([0x03, 0x0c, 0x00, 0x21, 0x00]);
+        assert_eq!(physical_address(&edid[..BLOCK_SIZE + 64]), None);
+        assert_eq!(physical_address(&edid[..64]), None);
+    }
+}
@@ -0,0 +1,181 @@
+use core::time::Duration;
+use std::{io, path::Path, time::Instant};
+
+use cec_linux::{
+    Capabilities, CecDevice, CecLogAddrType, CecLogAddrs, CecModeFollower, CecModeInitiator,
+    CecMsg, CecPhysicalAddress, CecPrimDevType, VendorID, Version,
+};
+
+use super::{
+    Addresses, CecBackend, CecFrame, DeviceType, Error, PhysicalAddress, decode_physical_address,
+    encode_physical_address,
+};
+
+/// A kernel CEC adapter, usually `/dev/cec0`.
+pub struct LinuxCec(CecDevice);
+
+impl LinuxCec {
+    /// Opens the adapter as a follower, so it receives everything sent to us and broadcast, not
+    /// only replies to our own frames. The kernel still answers core messages itself, like
+    /// requests for our OSD name.
+    ///
+    /// # Errors
+    ///
+    /// Fails if the device can't be opened, or can't be made a follower.
+    pub fn open(path: &Path) -> Result<Self, Error> {
+        let device = CecDevice::open(path)?;
+        device.set_mode(CecModeInitiator::Send, CecModeFollower::All)?;
+        Ok(Self(device))
+    }
+}
+
+impl CecBackend for LinuxCec {
+    fn claim(
+        &mut self,
+        device_type: DeviceType,
+        physical_address: Option<PhysicalAddress>,
+        osd_name: &str,
+    ) -> Result<Addresses, Error> {
+        let osd_name = osd_name
+            .to_owned()
+            .try_into()
+            .ok()
+            .filter(|_| osd_name.len() < 15)
+            .ok_or_else(|| Error::InvalidOsdName(osd_name.to_owned()))?;
+
+        let (prim_dev_type, log_addr_type) = match device_type {
+            DeviceType::Playback => (CecPrimDevType::PLAYBACK, CecLogAddrType::PLAYBACK),
+            DeviceType::Tuner => (CecPrimDevType::TUNER, CecLogAddrType::TUNER),
+            DeviceType::Recording => (CecPrimDevType::RECORD, CecLogAddrType::RECORD),
+        };
+
+        let capabilities = self.0.get_capas()?.capabilities();
+
+        if let Some(physical_address) = physical_address {
+            if capabilities.contains(Capabilities::PHYS_ADDR) {
+                self.0
+                    .set_phys(CecPhysicalAddress::from_bytes(encode_physical_address(
+                        physical_address,
+                    )))?;
+            } else {
+                // e.g. vc4 on the Raspberry Pi, which reads the EDID itself
+                tracing::info!("CEC adapter manages its own physical address");
+            }
+        }
+
+        if capabilities.contains(Capabilities::LOG_ADDRS) {
+            // setting logical addresses fails if some are already configured
+            self.0.set_log(CecLogAddrs::default())?;
+            // blocks until the address is claimed, as long as the physical address is valid
+            self.0.set_log(CecLogAddrs::new(
+                VendorID::NONE,
+                Version::V1_4,
+                osd_name,
+                &[prim_dev_type],
+                &[log_addr_type],
+            ))?;
+        }
+
+        let logical_address = *self
+            .0
+            .get_log()?
+            .addresses()
+            .first()
+            .ok_or(Error::NoLogicalAddress(device_type))?;
+
+        let physical_address = match self.0.get_phys()? {
+            CecPhysicalAddress::INVALID => physical_address,
+            actual => Some(decode_physical_address(actual.to_bytes())),
+        };
+
+        Ok(Addresses { logical_address, physical_address })
+    }
+
+    fn transmit(&mut self, frame: &CecFrame) -> Result<(), Error> {
+        let res = if frame.parameters.is_empty() {
+            self.0
+                .transmit(frame.initiator, frame.destination, frame.opcode)
+        } else {
+            self.0.transmit_data(
+                frame.initiator,
+                frame.destination,
+                frame.opcode,
+                &frame.parameters,
+            )
+        };
+
+        res.map_err(|error| {
+            if is_transmit_failure(&error) && !frame.is_broadcast() {
+                Error::Nack(frame.destination)
+            } else {
+                Error::Io(error)
+            }
+        })
+    }
+
+    fn receive(&mut self, timeout: Duration) -> Result<Option<CecFrame>, Error> {
+        let deadline = Instant::now() + timeout;
+
+        // skipping what we don't understand, so `None` means there's nothing left
+        loop {
+            // a timeout of 0 would block forever
+            let timeout = u32::try_from(
+                deadline
+                    .saturating_duration_since(Instant::now())
+                    .as_millis(),
+            )
+            .unwrap_or(u32::MAX)
+            .max(1);
+
+            match self.0.rec_for(timeout) {
+                Ok(msg) => {
+                    if let Some(frame) = frame_from_msg(&msg) {
+                        return Ok(Some(frame));
+                    }
+                }
+                Err(error) if error.kind() == io::ErrorKind::TimedOut => return Ok(None),
+                Err(error) => return Err(error.into()),
+            }
+        }
+    }
+}
+
+/// Whether the frame went out but wasn't delivered, as opposed to the ioctl failing.
+///
+/// The kernel retries lost arbitration and low drive by itself, so when a directed frame still
+/// fails, nobody acknowledged it. `cec_linux` doesn't expose the transmit status, but reports it
+/// without an errno, while the ioctl's own errors have one.
+fn is_transmit_failure(error: &io::Error) -> bool {
+    error.kind() == io::ErrorKind::Other
+        && error.raw_os_error().is_none()
+        && error.get_ref().is_some()
+}
+
+/// Returns `None` for polls and opcodes we don't know.
+fn frame_from_msg(msg: &CecMsg) -> Option<CecFrame> {
+    let opcode = match msg.opcode()? {
+        Ok(opcode) => opcode,
+        Err(error) => {
+            tracing::debug!(?error, "ignoring CEC message with unknown opcode");
+            return None;
+        }
+    };
+
+    Some(CecFrame::new(msg.initiator(), msg.destination(), opcode, msg.parameters()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn transmit_failure() {
+        // what cec_linux returns when the transmit status isn't OK
+        let nack = io::Error::other("NAck: 1 ");
+        assert!(is_transmit_failure(&nack));
+
+        // ENONET, when the adapter isn't configured
+        assert!(!is_transmit_failure(&io::Error::from_raw_os_error(64)));
+        assert!(!is_transmit_failure(&io::ErrorKind::TimedOut.into()));
+    }
+}
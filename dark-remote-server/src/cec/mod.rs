@@ -0,0 +1,364 @@
+//! HDMI-CEC, through a pluggable [`CecBackend`].
+
+use core::time::Duration;
+
+pub use cec_linux::{CecLogicalAddress, CecOpcode, CecPowerStatus};
+use serde::Deserialize;
+use thiserror::Error;
+
+pub use self::{
+    linux::LinuxCec,
+    simulated::{SimulatedBus, SimulatedDevice},
+};
+
+pub mod edid;
+mod linux;
+mod simulated;
+
+/// A physical address like `2.0.0.0`, one nibble per element.
+pub type PhysicalAddress = [u8; 4];
+
+/// A single message on the CEC bus.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CecFrame {
+    pub initiator: CecLogicalAddress,
+    pub destination: CecLogicalAddress,
+    pub opcode: CecOpcode,
+    pub parameters: Vec<u8>,
+}
+
+impl CecFrame {
+    pub fn new(
+        initiator: CecLogicalAddress,
+        destination: CecLogicalAddress,
+        opcode: CecOpcode,
+        parameters: &[u8],
+    ) -> Self {
+        Self {
+            initiator,
+            destination,
+            opcode,
+            parameters: parameters.to_vec(),
+        }
+    }
+
+    pub fn is_broadcast(&self) -> bool {
+        self.destination == CecLogicalAddress::UnregisteredBroadcast
+    }
+}
+
+/// The kind of device we announce ourselves as, which decides the logical addresses we can claim.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DeviceType {
+    Playback,
+    Tuner,
+    Recording,
+}
+
+impl DeviceType {
+    /// The logical addresses for this type, in the order they should be tried.
+    pub fn logical_addresses(self) -> &'static [CecLogicalAddress] {
+        match self {
+            Self::Playback => &[
+                CecLogicalAddress::Playback1,
+                CecLogicalAddress::Playback2,
+                CecLogicalAddress::Playback3,
+            ],
+            Self::Tuner => &[
+                CecLogicalAddress::Tuner1,
+                CecLogicalAddress::Tuner2,
+                CecLogicalAddress::Tuner3,
+                CecLogicalAddress::Tuner4,
+            ],
+            Self::Recording => &[
+                CecLogicalAddress::Record1,
+                CecLogicalAddress::Record2,
+                CecLogicalAddress::Record3,
+            ],
+        }
+    }
+}
+
+/// The addresses we ended up with after [`CecBackend::claim`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Addresses {
+    pub logical_address: CecLogicalAddress,
+    /// `None` if neither we nor the adapter know where we're plugged in.
+    pub physical_address: Option<PhysicalAddress>,
+}
+
+/// Something that can put frames on a CEC bus.
+pub trait CecBackend: Send {
+    /// Sets our physical address, if given, and claims a free logical address of `device_type`.
+    ///
+    /// # Errors
+    ///
+    /// Fails if the adapter fails, `osd_name` is invalid, or every address of `device_type` is
+    /// taken.
+    fn claim(
+        &mut self,
+        device_type: DeviceType,
+        physical_address: Option<PhysicalAddress>,
+        osd_name: &str,
+    ) -> Result<Addresses, Error>;
+
+    /// Sends `frame`, returning once it was acknowledged.
+    ///
+    /// # Errors
+    ///
+    /// Fails if the adapter fails, or with [`Error::Nack`] if nobody acknowledged a directed frame.
+    fn transmit(&mut self, frame: &CecFrame) -> Result<(), Error>;
+
+    /// Waits at most `timeout` for a frame sent by another device. Frames that can't be
+    /// represented, like polls, are skipped, so `None` means nothing else arrived in time.
+    ///
+    /// The backend has to be a follower on the bus, which hears broadcasts and frames other
+    /// devices send it unprompted, like a TV announcing it's going to standby.
+    ///
+    /// # Errors
+    ///
+    /// Fails if the adapter fails.
+    fn receive(&mut self, timeout: Duration) -> Result<Option<CecFrame>, Error>;
+}
+
+#[derive(Error, Debug)]
+pub enum Error {
+    #[error("CEC device error: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("{0:?} did not acknowledge the message")]
+    Nack(CecLogicalAddress),
+    #[error("OSD name {0:?} is not at most 14 ASCII characters")]
+    InvalidOsdName(String),
+    #[error("No free {0:?} logical address")]
+    NoLogicalAddress(DeviceType),
+    #[error("Our physical address is unknown")]
+    UnknownPhysicalAddress,
+}
+
+/// The commands we send over CEC, on top of any [`CecBackend`].
+pub struct Cec {
+    backend: Box<dyn CecBackend>,
+    addresses: Addresses,
+}
+
+impl Cec {
+    /// Claims addresses on `backend`. See [`CecBackend::claim`].
+    ///
+    /// # Errors
+    ///
+    /// Fails if no logical address could be claimed.
+    pub fn claim(
+        mut backend: Box<dyn CecBackend>,
+        device_type: DeviceType,
+        physical_address: Option<PhysicalAddress>,
+        osd_name: &str,
+    ) -> Result<Self, Error> {
+        let addresses = backend.claim(device_type, physical_address, osd_name)?;
+        tracing::info!(?addresses, "claimed CEC addresses");
+
+        Ok(Self { backend, addresses })
+    }
+
+    pub fn addresses(&self) -> Addresses {
+        self.addresses
+    }
+
+    /// Asks the TV to turn on its display.
+    ///
+    /// # Errors
+    ///
+    /// Fails if the frame couldn't be sent.
+    pub fn image_view_on(&mut self) -> Result<(), Error> {
+        self.broadcast(CecOpcode::ImageViewOn, &[])
+    }
+
+    /// Tells every device on the bus to go to standby.
+    ///
+    /// # Errors
+    ///
+    /// Fails if the frame couldn't be sent.
+    pub fn standby(&mut self) -> Result<(), Error> {
+        self.broadcast(CecOpcode::Standby, &[])
+    }
+
+    /// Announces `physical_address` as the active source, making the TV switch to that input.
+    ///
+    /// # Errors
+    ///
+    /// Fails if the frame couldn't be sent.
+    pub fn active_source(&mut self, physical_address: PhysicalAddress) -> Result<(), Error> {
+        self.broadcast(CecOpcode::ActiveSource, &encode_physical_address(physical_address))
+    }
+
+    /// Announces ourselves as the active source.
+    ///
+    /// # Errors
+    ///
+    /// Fails if our physical address is unknown, or the frame couldn't be sent.
+    pub fn active_source_self(&mut self) -> Result<(), Error> {
+        let physical_address = self
+            .addresses
+            .physical_address
+            .ok_or(Error::UnknownPhysicalAddress)?;
+
+        self.active_source(physical_address)
+    }
+
+    /// Asks `device` for its power status. The answer is a [`CecOpcode::ReportPowerStatus`] frame
+    /// that can be [received](Self::receive) later.
+    ///
+    /// # Errors
+    ///
+    /// Fails if the frame couldn't be sent, or `device` didn't acknowledge it.
+    pub fn request_power_status(&mut self, device: CecLogicalAddress) -> Result<(), Error> {
+        self.backend.transmit(&CecFrame::new(
+            self.addresses.logical_address,
+            device,
+            CecOpcode::GiveDevicePowerStatus,
+            &[],
+        ))
+    }
+
+    /// Asks the active source to announce itself with a [`CecOpcode::ActiveSource`] frame.
+    ///
+    /// # Errors
+    ///
+    /// Fails if the frame couldn't be sent.
+    pub fn request_active_source(&mut self) -> Result<(), Error> {
+        self.broadcast(CecOpcode::RequestActiveSource, &[])
+    }
+
+    /// Waits at most `timeout` for a frame sent by another device.
+    ///
+    /// # Errors
+    ///
+    /// Fails if the adapter fails.
+    pub fn receive(&mut self, timeout: Duration) -> Result<Option<CecFrame>, Error> {
+        self.backend.receive(timeout)
+    }
+
+    fn broadcast(&mut self, opcode: CecOpcode, parameters: &[u8]) -> Result<(), Error> {
+        self.backend.transmit(&CecFrame::new(
+            self.addresses.logical_address,
+            CecLogicalAddress::UnregisteredBroadcast,
+            opcode,
+            parameters,
+        ))
+    }
+}
+
+/// `[1, 2, 3, 4]` -> `[0x12, 0x34]`
+pub fn encode_physical_address([a, b, c, d]: PhysicalAddress) -> [u8; 2] {
+    [((a & 0xf) << 4) | (b & 0xf), ((c & 0xf) << 4) | (d & 0xf)]
+}
+
+/// `[0x12, 0x34]` -> `[1, 2, 3, 4]`
+pub fn decode_physical_address([ab, cd]: [u8; 2]) -> PhysicalAddress {
+    [ab >> 4, ab & 0xf, cd >> 4, cd & 0xf]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn claim(bus: &SimulatedBus) -> Cec {
+        Cec::claim(Box::new(bus.clone()), DeviceType::Playback, Some([1, 0, 0, 0]), "test").unwrap()
+    }
+
+    fn broadcast(opcode: CecOpcode, parameters: &[u8]) -> CecFrame {
+        CecFrame::new(
+            CecLogicalAddress::Playback1,
+            CecLogicalAddress::UnregisteredBroadcast,
+            opcode,
+            parameters,
+        )
+    }
+
+    #[test]
+    fn image_view_on() {
+        let bus = SimulatedBus::new().with_device(SimulatedDevice::tv());
+        let mut cec = claim(&bus);
+
+        cec.image_view_on().unwrap();
+
+        assert_eq!(bus.transmitted(), [broadcast(CecOpcode::ImageViewOn, &[])]);
+        assert_eq!(bus.device(CecLogicalAddress::Tv).unwrap().power, CecPowerStatus::On);
+    }
+
+    #[test]
+    fn standby() {
+        let bus = SimulatedBus::new()
+            .with_device(SimulatedDevice::tv())
+            .with_device(SimulatedDevice::audio_system());
+        let mut cec = claim(&bus);
+
+        cec.image_view_on().unwrap();
+        cec.standby().unwrap();
+
+        assert_eq!(bus.transmitted(), [
+            broadcast(CecOpcode::ImageViewOn, &[]),
+            broadcast(CecOpcode::Standby, &[]),
+        ]);
+        for device in [CecLogicalAddress::Tv, CecLogicalAddress::Audiosystem] {
+            assert_eq!(bus.device(device).unwrap().power, CecPowerStatus::Standby);
+        }
+    }
+
+    #[test]
+    fn active_source() {
+        let bus = SimulatedBus::new().with_device(SimulatedDevice::tv());
+        let mut cec = claim(&bus);
+
+        cec.active_source([2, 1, 0, 0]).unwrap();
+        assert_eq!(bus.device(CecLogicalAddress::Tv).unwrap().active_source, Some([2, 1, 0, 0]));
+
+        cec.active_source_self().unwrap();
+        assert_eq!(bus.device(CecLogicalAddress::Tv).unwrap().active_source, Some([1, 0, 0, 0]));
+
+        assert_eq!(bus.transmitted(), [
+            broadcast(CecOpcode::ActiveSource, &[0x21, 0x00]),
+            broadcast(CecOpcode::ActiveSource, &[0x10, 0x00]),
+        ]);
+    }
+
+    #[test]
+    fn claims_a_free_address() {
+        let bus = SimulatedBus::new()
+            .with_device(SimulatedDevice::new(CecLogicalAddress::Playback1, [2, 0, 0, 0]));
+
+        let cec = claim(&bus);
+
+        assert_eq!(cec.addresses().logical_address, CecLogicalAddress::Playback2);
+    }
+
+    #[test]
+    fn nack_without_destination() {
+        let bus = SimulatedBus::new().with_device(SimulatedDevice::audio_system());
+        let mut cec = claim(&bus);
+
+        let result = cec.request_power_status(CecLogicalAddress::Tv);
+
+        assert!(matches!(result, Err(Error::Nack(CecLogicalAddress::Tv))));
+        assert_eq!(bus.transmitted().len(), 1);
+        assert_eq!(cec.receive(Duration::ZERO).unwrap(), None);
+    }
+
+    #[test]
+    fn power_status_reply() {
+        let bus = SimulatedBus::new().with_device(SimulatedDevice::tv());
+        let mut cec = claim(&bus);
+
+        cec.request_power_status(CecLogicalAddress::Tv).unwrap();
+
+        assert_eq!(
+            cec.receive(Duration::ZERO).unwrap(),
+            Some(CecFrame::new(
+                CecLogicalAddress::Tv,
+                CecLogicalAddress::Playback1,
+                CecOpcode::ReportPowerStatus,
+                &[CecPowerStatus::Standby.into()],
+            )),
+        );
+    }
+}
@@ -0,0 +1,219 @@
+use core::time::Duration;
+use std::{
+    collections::VecDeque,
+    sync::{Arc, Mutex, MutexGuard, PoisonError},
+};
+
+use cec_linux::{CecAbortReason, CecLogicalAddress, CecOpcode, CecPowerStatus, CecPrimDevType};
+
+use super::{
+    Addresses, CecBackend, CecFrame, DeviceType, Error, PhysicalAddress, decode_physical_address,
+};
+
+/// A CEC bus that only exists in memory, with fake devices that react to what's sent to them.
+///
+/// Clones share the same bus, so one handle can be given to [`Cec`](super::Cec) while another is
+/// kept around to look at what was sent, or to inject frames from the other devices.
+#[derive(Clone, Default)]
+pub struct SimulatedBus(Arc<Mutex<Bus>>);
+
+#[derive(Default)]
+struct Bus {
+    devices: Vec<SimulatedDevice>,
+    transmitted: Vec<CecFrame>,
+    incoming: VecDeque<CecFrame>,
+}
+
+/// A fake TV, audio system or other device on a [`SimulatedBus`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SimulatedDevice {
+    pub logical_address: CecLogicalAddress,
+    pub physical_address: PhysicalAddress,
+    pub power: CecPowerStatus,
+    /// The active source as last announced on the bus.
+    pub active_source: Option<PhysicalAddress>,
+    /// Only reported by audio systems.
+    pub volume: u8,
+    pub muted: bool,
+}
+
+impl SimulatedDevice {
+    pub fn new(logical_address: CecLogicalAddress, physical_address: PhysicalAddress) -> Self {
+        Self {
+            logical_address,
+            physical_address,
+            power: CecPowerStatus::Standby,
+            active_source: None,
+            volume: 30,
+            muted: false,
+        }
+    }
+
+    /// A TV in standby, at `0.0.0.0`.
+    pub fn tv() -> Self {
+        Self::new(CecLogicalAddress::Tv, [0, 0, 0, 0])
+    }
+
+    /// An AVR in standby, connected to the TV's first input.
+    pub fn audio_system() -> Self {
+        Self::new(CecLogicalAddress::Audiosystem, [1, 0, 0, 0])
+    }
+
+    /// Updates the device's state for `frame`, returning its reply if it has one.
+    fn handle(&mut self, frame: &CecFrame) -> Option<CecFrame> {
+        let reply = |opcode, parameters: &[u8]| {
+            Some(CecFrame::new(self.logical_address, frame.initiator, opcode, parameters))
+        };
+
+        match frame.opcode {
+            CecOpcode::ImageViewOn | CecOpcode::TextViewOn
+                if self.logical_address == CecLogicalAddress::Tv =>
+            {
+                self.power = CecPowerStatus::On;
+                None
+            }
+            CecOpcode::Standby => {
+                self.power = CecPowerStatus::Standby;
+                None
+            }
+            CecOpcode::ActiveSource => {
+                if let &[ab, cd] = frame.parameters.as_slice() {
+                    self.active_source = Some(decode_physical_address([ab, cd]));
+                }
+                None
+            }
+            CecOpcode::RequestActiveSource if self.active_source == Some(self.physical_address) => {
+                Some(CecFrame::new(
+                    self.logical_address,
+                    CecLogicalAddress::UnregisteredBroadcast,
+                    CecOpcode::ActiveSource,
+                    &super::encode_physical_address(self.physical_address),
+                ))
+            }
+            CecOpcode::GiveDevicePowerStatus => {
+                reply(CecOpcode::ReportPowerStatus, &[self.power.into()])
+            }
+            CecOpcode::GivePhysicalAddr => {
+                let [ab, cd] = super::encode_physical_address(self.physical_address);
+                reply(CecOpcode::ReportPhysicalAddr, &[
+                    ab,
+                    cd,
+                    device_type(self.logical_address).into(),
+                ])
+            }
+            CecOpcode::GiveAudioStatus
+                if self.logical_address == CecLogicalAddress::Audiosystem =>
+            {
+                reply(CecOpcode::ReportAudioStatus, &[(u8::from(self.muted) << 7) | self.volume])
+            }
+            _ if frame.is_broadcast() => None,
+            opcode => reply(CecOpcode::FeatureAbort, &[
+                opcode.into(),
+                CecAbortReason::Unrecognized.into(),
+            ]),
+        }
+    }
+}
+
+impl SimulatedBus {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    #[must_use]
+    pub fn with_device(self, device: SimulatedDevice) -> Self {
+        self.lock().devices.push(device);
+        self
+    }
+
+    /// The current state of the device at `logical_address`.
+    pub fn device(&self, logical_address: CecLogicalAddress) -> Option<SimulatedDevice> {
+        self.lock()
+            .devices
+            .iter()
+            .find(|d| d.logical_address == logical_address)
+            .cloned()
+    }
+
+    /// Every frame transmitted on the bus so far, in order.
+    pub fn transmitted(&self) -> Vec<CecFrame> {
+        self.lock().transmitted.clone()
+    }
+
+    /// Queues `frame` to be received, as if another device sent it.
+    pub fn inject(&self, frame: CecFrame) {
+        self.lock().incoming.push_back(frame);
+    }
+
+    fn lock(&self) -> MutexGuard<'_, Bus> {
+        self.0.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+impl CecBackend for SimulatedBus {
+    fn claim(
+        &mut self,
+        device_type: DeviceType,
+        physical_address: Option<PhysicalAddress>,
+        _osd_name: &str,
+    ) -> Result<Addresses, Error> {
+        let bus = self.lock();
+        let logical_address = device_type
+            .logical_addresses()
+            .iter()
+            .copied()
+            .find(|&address| !bus.devices.iter().any(|d| d.logical_address == address))
+            .ok_or(Error::NoLogicalAddress(device_type))?;
+
+        Ok(Addresses { logical_address, physical_address })
+    }
+
+    fn transmit(&mut self, frame: &CecFrame) -> Result<(), Error> {
+        tracing::debug!(?frame, "simulated CEC transmit");
+
+        let mut bus = self.lock();
+        bus.transmitted.push(frame.clone());
+
+        let mut acked = frame.is_broadcast();
+        let mut replies = Vec::new();
+        for device in &mut bus.devices {
+            if frame.is_broadcast() || device.logical_address == frame.destination {
+                acked = true;
+                replies.extend(device.handle(frame));
+            }
+        }
+        bus.incoming.extend(replies);
+
+        if acked {
+            Ok(())
+        } else {
+            Err(Error::Nack(frame.destination))
+        }
+    }
+
+    fn receive(&mut self, _timeout: Duration) -> Result<Option<CecFrame>, Error> {
+        Ok(self.lock().incoming.pop_front())
+    }
+}
+
+/// The primary device type reported along with a physical address.
+fn device_type(logical_address: CecLogicalAddress) -> CecPrimDevType {
+    match logical_address {
+        CecLogicalAddress::Tv => CecPrimDevType::TV,
+        CecLogicalAddress::Record1 | CecLogicalAddress::Record2 | CecLogicalAddress::Record3 => {
+            CecPrimDevType::RECORD
+        }
+        CecLogicalAddress::Tuner1
+        | CecLogicalAddress::Tuner2
+        | CecLogicalAddress::Tuner3
+        | CecLogicalAddress::Tuner4 => CecPrimDevType::TUNER,
+        CecLogicalAddress::Playback1
+        | CecLogicalAddress::Playback2
+        | CecLogicalAddress::Playback3 => CecPrimDevType::PLAYBACK,
+        CecLogicalAddress::Audiosystem => CecPrimDevType::AUDIOSYSTEM,
+        CecLogicalAddress::Backup1
+        | CecLogicalAddress::Backup2
+        | CecLogicalAddress::Specific
+        | CecLogicalAddress::UnregisteredBroadcast => CecPrimDevType::PROCESSOR,
+    }
+}
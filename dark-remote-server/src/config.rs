@@ -0,0 +1,333 @@
+use std::{
+    net::{Ipv4Addr, SocketAddr},
+    path::{Path, PathBuf},
+};
+
+use dark_remote_common::config::{
+    Config, ConfigButton, ConfigPage, ConfigPageLayout, ConfigPanel, Key, Modifier, RemoteCommand,
+    Swipes,
+};
+use dark_remote_server::{
+    cec::{DeviceType, PhysicalAddress},
+    media::mpd,
+};
+use serde::Deserialize;
+use thiserror::Error;
+
+use crate::acceleration::PointerConfig;
+
+/// Contents of the config file. Every section is optional.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct ConfigFile {
+    pub server: ServerConfig,
+    pub cec: CecConfig,
+    pub media: MediaConfig,
+    pub mpd: MpdConfig,
+    pub input: InputConfig,
+    pub audio: AudioConfig,
+    pub pointer: PointerConfig,
+
+    /// The layout sent to clients. The built-in layout is used if this is missing.
+    pub pages: Option<Vec<ConfigPage>>,
+    /// What three-finger swipes on the trackpad do. Nothing by default.
+    pub swipes: Swipes,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub struct ServerConfig {
+    /// Shown in the app, and advertised over mDNS. Defaults to the hostname.
+    pub name: Option<String>,
+    /// Addresses to listen on for HTTP and WebSocket connections.
+    pub listen: Vec<SocketAddr>,
+    /// Directory containing the built web UI. Nothing but `/ws` is served if unset.
+    pub assets_dir: Option<PathBuf>,
+    /// Log filter, using the same syntax as `RUST_LOG`.
+    pub log: String,
+    /// Only let paired devices connect. See [`crate::pairing`].
+    pub pairing: bool,
+    /// Where paired devices and the self-signed certificate are kept.
+    pub data_dir: PathBuf,
+    /// Serve HTTPS and WSS instead of HTTP and WS.
+    pub tls: Option<TlsConfig>,
+    /// Advertise every `listen` address on the network over mDNS, so the app can find them. Each
+    /// port gets its own service.
+    pub mdns: bool,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct TlsConfig {
+    /// PEM file with the certificate chain. A self-signed certificate is generated and kept in
+    /// the data directory if neither this nor `key` is set.
+    pub cert: Option<PathBuf>,
+    /// PEM file with the private key.
+    pub key: Option<PathBuf>,
+    /// Names to put in the self-signed certificate, besides the hostname and `localhost`.
+    pub hostnames: Vec<String>,
+}
+
+impl Default for ServerConfig {
+    fn default() -> Self {
+        Self {
+            name: None,
+            listen: vec![SocketAddr::from((Ipv4Addr::UNSPECIFIED, 3000))],
+            assets_dir: None,
+            log: format!("{}=debug,tower_http=debug", env!("CARGO_CRATE_NAME")),
+            pairing: true,
+            data_dir: dirs::data_dir().unwrap_or_default().join("dark-remote"),
+            tls: None,
+            mdns: true,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub struct CecConfig {
+    pub backend: CecBackendKind,
+    /// The kernel CEC device, used by the `linux` backend.
+    pub device: PathBuf,
+    /// What we announce ourselves as on the bus.
+    pub device_type: DeviceType,
+    /// Name shown in the TV's source list, at most 14 ASCII characters.
+    pub osd_name: String,
+    /// DRM connector to read the EDID from, like `card1-HDMI-A-1`. See `/sys/class/drm`.
+    pub connector: Option<String>,
+    /// Overrides the physical address from the EDID, like `[2, 0, 0, 0]`.
+    pub physical_address: Option<PhysicalAddress>,
+}
+
+impl Default for CecConfig {
+    fn default() -> Self {
+        Self {
+            backend: CecBackendKind::Linux,
+            device: PathBuf::from("/dev/cec0"),
+            device_type: DeviceType::Playback,
+            osd_name: "dark-remote".to_owned(),
+            connector: None,
+            physical_address: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CecBackendKind {
+    /// A kernel CEC device.
+    Linux,
+    /// An in-memory bus with a fake TV and audio system, for trying things out without CEC
+    /// hardware.
+    Simulated,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct MediaConfig {
+    pub backend: MediaBackendKind,
+    /// MPRIS player to control, like `mpv` or `org.mpris.MediaPlayer2.mpv`. Without this, the one
+    /// that's playing is controlled.
+    pub player: Option<String>,
+}
+
+#[derive(Debug, Default, Clone, Copy, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum MediaBackendKind {
+    /// Whatever players are on the D-Bus session bus.
+    #[default]
+    Mpris,
+    /// MPD, configured in the `[mpd]` section.
+    Mpd,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct InputConfig {
+    pub backend: InputBackendKind,
+}
+
+#[derive(Debug, Default, Clone, Copy, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum InputBackendKind {
+    /// Through the display server, which needs a graphical session.
+    #[default]
+    Enigo,
+    /// A virtual mouse and keyboard made with `/dev/uinput`, which works anywhere the server can
+    /// open it, and scrolls smoothly.
+    Uinput,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct AudioConfig {
+    pub backend: AudioBackendKind,
+}
+
+#[derive(Debug, Default, Clone, Copy, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AudioBackendKind {
+    /// `PulseAudio`, or `PipeWire` with its `PulseAudio` server, through `pactl`.
+    #[default]
+    Pulse,
+    /// Two outputs that only exist in memory, for trying things out without a sound server.
+    Simulated,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub struct MpdConfig {
+    /// Hostname, or the path to a unix socket if it starts with `/`.
+    pub host: String,
+    pub port: u16,
+    pub password: Option<String>,
+}
+
+impl Default for MpdConfig {
+    fn default() -> Self {
+        Self {
+            host: "localhost".to_owned(),
+            port: 6600,
+            password: None,
+        }
+    }
+}
+
+impl MpdConfig {
+    pub fn address(&self) -> mpd::Address {
+        if self.host.starts_with('/') {
+            mpd::Address::Unix(PathBuf::from(&self.host))
+        } else {
+            mpd::Address::Tcp {
+                host: self.host.clone(),
+                port: self.port,
+            }
+        }
+    }
+}
+
+impl ServerConfig {
+    pub fn name(&self) -> String {
+        self.name
+            .clone()
+            .or_else(hostname)
+            .unwrap_or_else(|| "dark-remote".to_owned())
+    }
+
+    pub fn devices_path(&self) -> PathBuf {
+        self.data_dir.join("devices.toml")
+    }
+}
+
+impl ConfigFile {
+    /// The layout to send to clients.
+    pub fn layout(&self) -> Config {
+        Config {
+            pages: self.pages.clone().unwrap_or_else(builtin),
+            swipes: self.swipes.clone(),
+        }
+    }
+}
+
+/// Default location of the config file, usually `~/.config/dark-remote/config.toml`.
+pub fn default_path() -> Option<PathBuf> {
+    Some(dirs::config_dir()?.join("dark-remote").join("config.toml"))
+}
+
+/// This machine's hostname, without a domain.
+pub fn hostname() -> Option<String> {
+    let hostname = std::fs::read_to_string("/proc/sys/kernel/hostname").ok()?;
+    Some(hostname.trim().to_owned()).filter(|hostname| !hostname.is_empty())
+}
+
+/// Reads the config file at `path`. Returns `None` if the file doesn't exist.
+pub fn load(path: &Path) -> Result<Option<ConfigFile>, Error> {
+    let contents = match std::fs::read_to_string(path) {
+        Ok(contents) => contents,
+        Err(error) if error.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+        Err(error) => return Err(Error::Read(path.to_owned(), error)),
+    };
+
+    toml::from_str(&contents)
+        .map(Some)
+        .map_err(|error| Error::Parse(path.to_owned(), error))
+}
+
+#[derive(Error, Debug)]
+pub enum Error {
+    #[error("Failed to read {0:?}: {1}")]
+    Read(PathBuf, std::io::Error),
+    #[error("Failed to parse {0:?}: {1}")]
+    Parse(PathBuf, toml::de::Error),
+}
+
+/// The pages used when the config file doesn't have any.
+fn builtin() -> Vec<ConfigPage> {
+    fn button(label: &str, command: RemoteCommand) -> ConfigButton {
+        ConfigButton { label: label.to_owned(), command }
+    }
+
+    fn chord(label: &str, modifiers: &[Modifier], key: Key) -> ConfigButton {
+        button(label, RemoteCommand::KeyboardChord { modifiers: modifiers.to_vec(), key })
+    }
+
+    fn hdmi(port: u8) -> ConfigButton {
+        button(&format!("HDMI {port}"), RemoteCommand::CecActiveSource {
+            physical_address: [port, 0, 0, 0],
+        })
+    }
+
+    vec![
+        ConfigPage {
+            name: "Remote".to_owned(),
+            layout: ConfigPageLayout::Linear {
+                panels: vec![
+                    (60, ConfigPanel::Trackpad),
+                    (40, ConfigPanel::ButtonGrid {
+                        rows: vec![
+                            vec![
+                                button("Turn on", RemoteCommand::CecImageViewOn),
+                                button("Switch", RemoteCommand::CecActiveSourceSelf),
+                                button("Standby", RemoteCommand::CecStandby),
+                            ],
+                            vec![hdmi(1), hdmi(2), hdmi(3), hdmi(4)],
+                            vec![
+                                button("Prev", RemoteCommand::MediaPrevious),
+                                button("Pause", RemoteCommand::MediaPlayPause),
+                                button("Next", RemoteCommand::MediaNext),
+                            ],
+                            vec![
+                                button("Vol -", RemoteCommand::AudioChangeVolume { delta: -5 }),
+                                button("Mute", RemoteCommand::AudioToggleMute),
+                                button("Vol +", RemoteCommand::AudioChangeVolume { delta: 5 }),
+                            ],
+                        ],
+                    }),
+                ],
+            },
+        },
+        ConfigPage {
+            name: "Keyboard".to_owned(),
+            layout: ConfigPageLayout::Linear {
+                panels: vec![
+                    (25, ConfigPanel::Keyboard),
+                    (15, ConfigPanel::ButtonGrid {
+                        rows: vec![vec![
+                            chord("Alt+Tab", &[Modifier::Alt], Key::Tab),
+                            chord("Alt+F4", &[Modifier::Alt], Key::F(4)),
+                            chord("Copy", &[Modifier::Control], Key::Char('c')),
+                            chord("Paste", &[Modifier::Control], Key::Char('v')),
+                        ]],
+                    }),
+                    (60, ConfigPanel::Trackpad),
+                ],
+            },
+        },
+        ConfigPage {
+            name: "Gamepad".to_owned(),
+            layout: ConfigPageLayout::Linear {
+                panels: vec![(100, ConfigPanel::Gamepad)],
+            },
+        },
+    ]
+}
@@ -1,44 +1,178 @@
-use axum::extract::ws::{self, WebSocket};
-use cec_linux::{CecDevice, CecLogicalAddress, CecOpcode};
 use core::time::Duration;
-use dark_remote_common::config::{RemoteCommand, TrackpadButton};
-use enigo::{Enigo, Mouse as _};
-use std::net::SocketAddr;
+use std::{net::SocketAddr, sync::Arc};
+
+use axum::extract::ws::{self, WebSocket, close_code};
+use dark_remote_common::{
+    config::{Config, RemoteCommand, TrackpadButton},
+    protocol::{
+        CommandError, Encoding, Hello, PROTOCOL_VERSION, RemoteState, Request, ServerMessage,
+    },
+};
+use serde::Deserialize;
 use thiserror::Error;
 
+use crate::{acceleration::Accelerator, pairing::Authorized, service::ServiceHandle};
+
+/// How long a client gets to say hello before it's disconnected.
+const HELLO_TIMEOUT: Duration = Duration::from_secs(10);
+
 pub struct Connection {
     client: SocketAddr,
-
-    enigo: Enigo,
-    cec: CecDevice,
+    /// `None` if pairing is turned off.
+    authorized: Option<Authorized>,
+    /// The server's name, for the welcome.
+    name: Arc<str>,
+    config: Arc<Config>,
+    /// For this client's device.
+    accelerator: Accelerator,
+    service: ServiceHandle,
+    /// Buttons the client pressed and didn't release yet.
+    held: Vec<TrackpadButton>,
 }
 
-impl Connection {
-    pub fn new(client: SocketAddr) -> Self {
-        let enigo = Enigo::new(&enigo::Settings::default()).expect("failed to initialize Enigo");
+#[derive(Error, Debug)]
+pub enum Error {
+    #[error(transparent)]
+    WebSocket(#[from] axum::Error),
+    #[error("Client didn't say hello within {HELLO_TIMEOUT:?}")]
+    NoHello,
+    #[error("Client's first message wasn't a hello: {0}")]
+    BadHello(serde_json::Error),
+    #[error("Client speaks protocol version {0}, but we speak {PROTOCOL_VERSION}")]
+    Version(u32),
+}
 
-        let cec = CecDevice::open("/dev/cec0").expect("failed to open CEC device");
+/// A request that couldn't be read. The connection stays open.
+#[derive(Error, Debug)]
+enum DecodeError {
+    #[error(transparent)]
+    Json(#[from] serde_json::Error),
+    #[error(transparent)]
+    Postcard(#[from] postcard::Error),
+    #[error("Binary messages need the postcard encoding")]
+    Binary,
+}
 
-        Self { client, enigo, cec }
+impl Connection {
+    pub fn new(
+        client: SocketAddr,
+        authorized: Option<Authorized>,
+        name: Arc<str>,
+        config: Arc<Config>,
+        accelerator: Accelerator,
+        service: ServiceHandle,
+    ) -> Self {
+        Self {
+            client,
+            authorized,
+            name,
+            config,
+            accelerator,
+            service,
+            held: Vec::new(),
+        }
     }
 
-    pub async fn handle(self, socket: WebSocket) {
+    pub async fn handle(mut self, socket: WebSocket) {
         let client = self.client;
         match self.handle_inner(socket).await {
             Ok(()) => tracing::info!(?client, "disconnected by user"),
             Err(error) => tracing::error!(?client, ?error, "error occurred"),
         }
+
+        self.release_buttons().await;
+        self.service.disconnect(client);
+    }
+
+    /// Releases whatever the client was still holding down, so no button stays stuck.
+    async fn release_buttons(&mut self) {
+        for button in core::mem::take(&mut self.held) {
+            tracing::info!(?self.client, ?button, "releasing held button");
+
+            let command = RemoteCommand::TrackpadRelease { button };
+            if let Err(error) = self.service.execute(self.client, command).await {
+                tracing::warn!(?self.client, ?button, ?error, "failed to release button");
+            }
+        }
+    }
+
+    /// Keeps track of the buttons the client is holding down, after `command` was executed.
+    fn track_buttons(&mut self, command: &RemoteCommand) {
+        match *command {
+            RemoteCommand::TrackpadPress { button } if !self.held.contains(&button) => {
+                self.held.push(button);
+            }
+            RemoteCommand::TrackpadRelease { button } => self.held.retain(|&held| held != button),
+            _ => {}
+        }
     }
 
-    pub async fn handle_inner(mut self, mut socket: WebSocket) -> Result<(), axum::Error> {
-        while let Some(msg) = socket.recv().await {
-            let msg = match msg? {
-                ws::Message::Text(utf8_bytes) => serde_json::from_str::<RemoteCommand>(&utf8_bytes),
-                ws::Message::Binary(bytes) => {
-                    // might use some binary format in the future
-                    tracing::info!(?self.client, "received binary message of {} bytes", bytes.len());
+    pub async fn handle_inner(&mut self, mut socket: WebSocket) -> Result<(), Error> {
+        let hello = match tokio::time::timeout(HELLO_TIMEOUT, receive_hello(&mut socket)).await {
+            Ok(Ok(hello)) => hello,
+            Ok(Err(error)) => {
+                close(&mut socket, "expected a hello").await?;
+                return Err(error);
+            }
+            Err(_elapsed) => {
+                close(&mut socket, "no hello").await?;
+                return Err(Error::NoHello);
+            }
+        };
+        tracing::info!(?self.client, ?hello, "client said hello");
+
+        // we understand every encoding there is
+        let encoding = hello.encodings.first().copied().unwrap_or_default();
+
+        send(&mut socket, &ServerMessage::Welcome {
+            version: PROTOCOL_VERSION,
+            name: self.name.to_string(),
+            capabilities: self.service.capabilities().to_vec(),
+            encoding,
+        })
+        .await?;
+
+        if hello.version != PROTOCOL_VERSION {
+            // the client can tell why from the welcome
+            close(&mut socket, "unsupported protocol version").await?;
+            return Err(Error::Version(hello.version));
+        }
+
+        if let Some(Authorized { device, new: true }) = &self.authorized {
+            send(&mut socket, &ServerMessage::Paired {
+                id: device.id.clone(),
+                token: device.token.clone(),
+            })
+            .await?;
+        }
+
+        send(&mut socket, &ServerMessage::Config((*self.config).clone())).await?;
+
+        // the client starts out knowing the default state, like we do
+        let mut known = RemoteState::default();
+        let mut state = self.service.state();
+        state.mark_changed();
+
+        loop {
+            let msg = tokio::select! {
+                msg = socket.recv() => msg,
+                Ok(()) = state.changed() => {
+                    let new = state.borrow_and_update().clone();
+                    for msg in known.changes(&new) {
+                        send(&mut socket, &msg).await?;
+                    }
+                    known = new;
                     continue;
                 }
+            };
+
+            let Some(msg) = msg else {
+                break;
+            };
+            let request = match msg? {
+                // JSON is always allowed, for debugging
+                ws::Message::Text(utf8_bytes) => decode_json(&utf8_bytes),
+                ws::Message::Binary(bytes) => decode_postcard(&bytes, encoding),
                 ws::Message::Ping(_) | ws::Message::Pong(_) => continue,
                 ws::Message::Close(close_frame) => {
                     tracing::info!(?self.client, ?close_frame, "received close frame");
@@ -46,115 +180,165 @@ impl Connection {
                 }
             };
 
-            let cmd = match msg {
-                Ok(cmd) => cmd,
-                Err(error) => {
+            let Request { id, mut command } = match request {
+                Ok(request) => request,
+                Err((id, error)) => {
                     tracing::warn!(?self.client, ?error, "failed to parse message");
+
+                    let error = CommandError::Invalid(error.to_string());
+                    send(&mut socket, &ServerMessage::CommandFailed { id, error }).await?;
                     continue;
                 }
             };
 
-            match self.execute(cmd).await {
-                Ok(()) => {}
+            if let RemoteCommand::TrackpadMove { delta_x, delta_y, time } = &mut command {
+                (*delta_x, *delta_y) = self.accelerator.accelerate(*delta_x, *delta_y, *time);
+            }
+
+            match self.service.execute(self.client, command.clone()).await {
+                Ok(reply) => {
+                    self.track_buttons(&command);
+
+                    if let Some(reply) = reply {
+                        send(&mut socket, &reply).await?;
+                    }
+                    if let Some(id) = id {
+                        send(&mut socket, &ServerMessage::Ack { id }).await?;
+                    }
+                }
                 Err(error) => {
-                    tracing::warn!(?self.client, ?cmd, ?error, "failed to execute command");
+                    tracing::warn!(?self.client, ?command, ?error, "failed to execute command");
+
+                    let error = error.to_client();
+                    send(&mut socket, &ServerMessage::CommandFailed { id, error }).await?;
                 }
             }
         }
 
         Ok(())
     }
+}
 
-    async fn execute(&mut self, cmd: RemoteCommand) -> Result<(), Error> {
-        tracing::debug!(?self.client, ?cmd, "executing command");
-        match cmd {
-            RemoteCommand::TrackpadMove { delta_x, delta_y } => {
-                let half_x = delta_x / 2;
-                let rem_x = delta_x % 2;
-                let half_y = delta_y / 2;
-                let rem_y = delta_y % 2;
+/// Reads a request. If that fails, its ID is still read if possible, to reply to.
+fn decode_json(text: &str) -> Result<Request, (Option<u32>, DecodeError)> {
+    #[derive(Deserialize)]
+    struct Id {
+        id: Option<u32>,
+    }
 
-                self.enigo
-                    .move_mouse(half_x + rem_x, half_y + rem_y, enigo::Coordinate::Rel)?;
+    serde_json::from_str(text).map_err(|error| {
+        let id = serde_json::from_str::<Id>(text)
+            .ok()
+            .and_then(|request| request.id);
+        (id, error.into())
+    })
+}
 
-                std::thread::sleep(Duration::from_millis(1000 / 120));
+/// Like [`decode_json`], for the postcard encoding.
+fn decode_postcard(
+    bytes: &[u8],
+    encoding: Encoding,
+) -> Result<Request, (Option<u32>, DecodeError)> {
+    if encoding != Encoding::Postcard {
+        return Err((None, DecodeError::Binary));
+    }
 
-                self.enigo
-                    .move_mouse(half_x, half_y, enigo::Coordinate::Rel)?;
-            }
-            RemoteCommand::TrackpadClick { button } => self
-                .enigo
-                .button(enigo_button(button), enigo::Direction::Click)?,
-            RemoteCommand::TrackpadScroll { delta_x, delta_y } => {
-                if delta_y != 0 {
-                    self.enigo.scroll(delta_y, enigo::Axis::Vertical)?;
-                }
-                if delta_x != 0 {
-                    self.enigo.scroll(delta_x, enigo::Axis::Horizontal)?;
-                }
-            }
-            RemoteCommand::CecImageViewOn => {
-                tracing::info!(?self.client, "sending CEC image view on command");
-                self.cec
-                    .transmit(
-                        CecLogicalAddress::Playback2,
-                        CecLogicalAddress::UnregisteredBroadcast,
-                        CecOpcode::ImageViewOn,
-                    )
-                    .expect("failed to send CEC image view on command");
-            }
-            RemoteCommand::CecActiveSourceSelf => {
-                tracing::info!(?self.client, "setting active source to self");
-                self.cec
-                    .transmit_data(
-                        CecLogicalAddress::Playback2,
-                        CecLogicalAddress::UnregisteredBroadcast,
-                        CecOpcode::ActiveSource,
-                        &[0x20, 0x00], // 2.0.0.0
-                    )
-                    .expect("failed to send CEC active source command");
-            }
-            RemoteCommand::CecActiveSource { physical_address: [a, b, c, d] } => {
-                tracing::info!(?self.client, "setting active source to self");
-                self.cec
-                    .transmit_data(
-                        CecLogicalAddress::Playback2,
-                        CecLogicalAddress::UnregisteredBroadcast,
-                        CecOpcode::ActiveSource,
-                        // 1, 2, 3, 4 -> 0x12, 0x34
-                        &[((a & 0xf) << 4) | (b & 0xf), ((c & 0xf) << 4) | (d & 0xf)],
-                    )
-                    .expect("failed to send CEC active source command");
-            }
-            RemoteCommand::CecStandby => {
-                tracing::info!(?self.client, "sending CEC standby command");
-                self.cec
-                    .transmit(
-                        CecLogicalAddress::Playback2,
-                        CecLogicalAddress::UnregisteredBroadcast,
-                        CecOpcode::Standby,
-                    )
-                    .expect("failed to send CEC standby command");
-            }
-            RemoteCommand::MpdPlayPause | RemoteCommand::MpdNext | RemoteCommand::MpdPrevious => {
-                tracing::error!(?cmd, "unimplemented");
-            }
-        }
+    postcard::from_bytes(bytes).map_err(|error| {
+        // the ID comes first
+        let id = postcard::take_from_bytes::<Option<u32>>(bytes)
+            .ok()
+            .and_then(|(id, _)| id);
+        (id, error.into())
+    })
+}
 
-        Ok(())
+/// Waits for the first message, which should be a [`Hello`].
+async fn receive_hello(socket: &mut WebSocket) -> Result<Hello, Error> {
+    while let Some(msg) = socket.recv().await {
+        match msg? {
+            ws::Message::Text(text) => return serde_json::from_str(&text).map_err(Error::BadHello),
+            ws::Message::Ping(_) | ws::Message::Pong(_) => {}
+            ws::Message::Binary(_) | ws::Message::Close(_) => break,
+        }
     }
+
+    Err(Error::NoHello)
 }
 
-#[derive(Error, Debug)]
-enum Error {
-    #[error("Input error: {0}")]
-    Input(#[from] enigo::InputError),
+/// Closes the connection because the client broke the protocol.
+async fn close(socket: &mut WebSocket, reason: &str) -> Result<(), axum::Error> {
+    socket
+        .send(ws::Message::Close(Some(ws::CloseFrame {
+            code: close_code::PROTOCOL,
+            reason: reason.into(),
+        })))
+        .await
+}
+
+async fn send(socket: &mut WebSocket, msg: &ServerMessage) -> Result<(), axum::Error> {
+    let msg = serde_json::to_string(msg).expect("Failed to serialize ServerMessage");
+    socket.send(ws::Message::Text(msg.into())).await
 }
 
-fn enigo_button(button: TrackpadButton) -> enigo::Button {
-    match button {
-        TrackpadButton::Left => enigo::Button::Left,
-        TrackpadButton::Right => enigo::Button::Right,
-        TrackpadButton::Middle => enigo::Button::Middle,
+#[cfg(test)]
+mod tests {
+    use dark_remote_common::config::{Key, RemoteCommand};
+
+    use super::*;
+
+    fn request(id: Option<u32>) -> Request {
+        Request {
+            id,
+            command: RemoteCommand::KeyboardPress { key: Key::Return },
+        }
+    }
+
+    #[test]
+    fn json() {
+        let json = serde_json::to_string(&request(Some(5))).unwrap();
+        let request = decode_json(&json).unwrap();
+        assert_eq!(request.id, Some(5));
+        assert!(matches!(request.command, RemoteCommand::KeyboardPress { key: Key::Return }));
+    }
+
+    #[test]
+    fn json_without_id() {
+        let request = decode_json(r#"{"command":{"KeyboardPress":{"key":"Return"}}}"#).unwrap();
+        assert_eq!(request.id, None);
+    }
+
+    #[test]
+    fn json_unknown_command() {
+        let result = decode_json(r#"{"id":9,"command":{"Teleport":{}}}"#);
+        assert!(matches!(result, Err((Some(9), DecodeError::Json(_)))));
+    }
+
+    #[test]
+    fn json_garbage() {
+        assert!(matches!(decode_json("{"), Err((None, DecodeError::Json(_)))));
+    }
+
+    #[test]
+    fn postcard() {
+        let bytes = postcard::to_allocvec(&request(Some(300))).unwrap();
+        let request = decode_postcard(&bytes, Encoding::Postcard).unwrap();
+        assert_eq!(request.id, Some(300));
+        assert!(matches!(request.command, RemoteCommand::KeyboardPress { key: Key::Return }));
+    }
+
+    #[test]
+    fn postcard_unknown_command() {
+        let mut bytes = postcard::to_allocvec(&Some(300_u32)).unwrap();
+        // a variant from a newer version
+        bytes.push(0x7f);
+        let result = decode_postcard(&bytes, Encoding::Postcard);
+        assert!(matches!(result, Err((Some(300), DecodeError::Postcard(_)))));
+    }
+
+    #[test]
+    fn postcard_without_agreeing_on_it() {
+        let bytes = postcard::to_allocvec(&request(Some(1))).unwrap();
+        let result = decode_postcard(&bytes, Encoding::Json);
+        assert!(matches!(result, Err((None, DecodeError::Binary))));
     }
 }
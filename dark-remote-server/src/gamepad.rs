@@ -0,0 +1,291 @@
+//! Virtual Xbox-style gamepads made with `/dev/uinput`, one per player.
+//!
+//! Like [`crate::input::uinput`], the events are put together by an [`Encoder`], which doesn't
+//! need the kernel device, and then written to it by [`Gamepad`].
+
+pub use dark_remote_common::config::{GamepadButton, GamepadStick, GamepadTrigger};
+use evdev::{
+    AbsInfo, AbsoluteAxisCode, AbsoluteAxisEvent, AttributeSet, BusType, InputId, KeyCode,
+    KeyEvent, UinputAbsSetup, uinput::VirtualDevice,
+};
+
+use crate::input::uinput::Report;
+
+/// The IDs of a wired Xbox 360 controller, which games, Steam and SDL all know how to map.
+const INPUT_ID: (u16, u16, u16) = (0x045e, 0x028e, 0x0110);
+
+/// How far a stick axis goes each way.
+pub const STICK_MAX: i32 = i16::MAX as i32;
+
+/// How far a trigger axis goes.
+pub const TRIGGER_MAX: i32 = u8::MAX as i32;
+
+/// A virtual gamepad, which goes away when dropped.
+pub struct Gamepad {
+    device: VirtualDevice,
+    encoder: Encoder,
+    player: u8,
+}
+
+impl Gamepad {
+    /// Creates a gamepad called "dark-remote gamepad `player`".
+    ///
+    /// # Errors
+    ///
+    /// Fails if `/dev/uinput` can't be opened, or the device can't be created.
+    pub fn new(player: u8) -> std::io::Result<Self> {
+        let mut keys = AttributeSet::<KeyCode>::new();
+        for button in BUTTONS {
+            keys.insert(button);
+        }
+
+        let stick = AbsInfo::new(0, -STICK_MAX - 1, STICK_MAX, 16, 128, 0);
+        let trigger = AbsInfo::new(0, 0, TRIGGER_MAX, 0, 0, 0);
+        let hat = AbsInfo::new(0, -1, 1, 0, 0, 0);
+
+        let name = format!("dark-remote gamepad {player}");
+        let (vendor, product, version) = INPUT_ID;
+        let mut builder = VirtualDevice::builder()?
+            .name(&name)
+            .input_id(InputId::new(BusType::BUS_USB, vendor, product, version))
+            .with_keys(&keys)?;
+        for (axis, info) in [
+            (AbsoluteAxisCode::ABS_X, stick),
+            (AbsoluteAxisCode::ABS_Y, stick),
+            (AbsoluteAxisCode::ABS_RX, stick),
+            (AbsoluteAxisCode::ABS_RY, stick),
+            (AbsoluteAxisCode::ABS_Z, trigger),
+            (AbsoluteAxisCode::ABS_RZ, trigger),
+            (AbsoluteAxisCode::ABS_HAT0X, hat),
+            (AbsoluteAxisCode::ABS_HAT0Y, hat),
+        ] {
+            builder = builder.with_absolute_axis(&UinputAbsSetup::new(axis, info))?;
+        }
+
+        Ok(Self {
+            device: builder.build()?,
+            encoder: Encoder::default(),
+            player,
+        })
+    }
+
+    /// Which player this gamepad is for, starting at 1.
+    pub fn player(&self) -> u8 {
+        self.player
+    }
+
+    /// # Errors
+    ///
+    /// Fails if the event couldn't be written.
+    pub fn button(&mut self, button: GamepadButton, pressed: bool) -> std::io::Result<()> {
+        let report = self.encoder.button(button, pressed);
+        self.device.emit(&report)
+    }
+
+    /// # Errors
+    ///
+    /// Fails if the event couldn't be written.
+    pub fn stick(&mut self, stick: GamepadStick, x: f32, y: f32) -> std::io::Result<()> {
+        self.device.emit(&Encoder::stick(stick, x, y))
+    }
+
+    /// # Errors
+    ///
+    /// Fails if the event couldn't be written.
+    pub fn trigger(&mut self, trigger: GamepadTrigger, value: f32) -> std::io::Result<()> {
+        self.device.emit(&Encoder::trigger(trigger, value))
+    }
+}
+
+/// Checks whether gamepads can be created, without creating one.
+///
+/// # Errors
+///
+/// Fails if `/dev/uinput` can't be opened.
+pub fn probe() -> std::io::Result<()> {
+    VirtualDevice::builder().map(drop)
+}
+
+/// Buttons the gamepad has, as the kernel's Xbox controller driver reports them. The d-pad is a
+/// hat instead.
+const BUTTONS: [KeyCode; 11] = [
+    KeyCode::BTN_SOUTH,
+    KeyCode::BTN_EAST,
+    KeyCode::BTN_NORTH,
+    KeyCode::BTN_WEST,
+    KeyCode::BTN_TL,
+    KeyCode::BTN_TR,
+    KeyCode::BTN_SELECT,
+    KeyCode::BTN_START,
+    KeyCode::BTN_MODE,
+    KeyCode::BTN_THUMBL,
+    KeyCode::BTN_THUMBR,
+];
+
+/// Turns gamepad input into evdev events.
+#[derive(Debug, Default)]
+pub struct Encoder {
+    dpad: Dpad,
+}
+
+/// Which d-pad directions are held. Opposite ones cancel out.
+#[derive(Debug, Default, Clone, Copy)]
+struct Dpad {
+    up: bool,
+    down: bool,
+    left: bool,
+    right: bool,
+}
+
+impl Encoder {
+    pub fn button(&mut self, button: GamepadButton, pressed: bool) -> Report {
+        let code = match button {
+            GamepadButton::A => KeyCode::BTN_SOUTH,
+            GamepadButton::B => KeyCode::BTN_EAST,
+            // the kernel's names for these are swapped from where they are on an Xbox controller,
+            // but this is what its driver sends
+            GamepadButton::X => KeyCode::BTN_NORTH,
+            GamepadButton::Y => KeyCode::BTN_WEST,
+            GamepadButton::LeftBumper => KeyCode::BTN_TL,
+            GamepadButton::RightBumper => KeyCode::BTN_TR,
+            GamepadButton::Back => KeyCode::BTN_SELECT,
+            GamepadButton::Start => KeyCode::BTN_START,
+            GamepadButton::Guide => KeyCode::BTN_MODE,
+            GamepadButton::LeftStick => KeyCode::BTN_THUMBL,
+            GamepadButton::RightStick => KeyCode::BTN_THUMBR,
+            GamepadButton::DpadUp => return self.dpad(|dpad| dpad.up = pressed),
+            GamepadButton::DpadDown => return self.dpad(|dpad| dpad.down = pressed),
+            GamepadButton::DpadLeft => return self.dpad(|dpad| dpad.left = pressed),
+            GamepadButton::DpadRight => return self.dpad(|dpad| dpad.right = pressed),
+        };
+        vec![*KeyEvent::new(code, i32::from(pressed))]
+    }
+
+    /// From -1 to 1 on each axis, where positive is right and down, like the axes themselves.
+    pub fn stick(stick: GamepadStick, x: f32, y: f32) -> Report {
+        let (axis_x, axis_y) = match stick {
+            GamepadStick::Left => (AbsoluteAxisCode::ABS_X, AbsoluteAxisCode::ABS_Y),
+            GamepadStick::Right => (AbsoluteAxisCode::ABS_RX, AbsoluteAxisCode::ABS_RY),
+        };
+        vec![
+            *AbsoluteAxisEvent::new(axis_x, scale(x, -1., STICK_MAX)),
+            *AbsoluteAxisEvent::new(axis_y, scale(y, -1., STICK_MAX)),
+        ]
+    }
+
+    /// From 0 to 1.
+    pub fn trigger(trigger: GamepadTrigger, value: f32) -> Report {
+        let axis = match trigger {
+            GamepadTrigger::Left => AbsoluteAxisCode::ABS_Z,
+            GamepadTrigger::Right => AbsoluteAxisCode::ABS_RZ,
+        };
+        vec![*AbsoluteAxisEvent::new(axis, scale(value, 0., TRIGGER_MAX))]
+    }
+
+    /// Changes which directions are held, and sends where the hat points now.
+    fn dpad(&mut self, change: impl FnOnce(&mut Dpad)) -> Report {
+        change(&mut self.dpad);
+        let Dpad { up, down, left, right } = self.dpad;
+        vec![
+            *AbsoluteAxisEvent::new(
+                AbsoluteAxisCode::ABS_HAT0X,
+                i32::from(right) - i32::from(left),
+            ),
+            *AbsoluteAxisEvent::new(AbsoluteAxisCode::ABS_HAT0Y, i32::from(down) - i32::from(up)),
+        ]
+    }
+}
+
+/// Scales `value`, clamped to between `min` and 1, to an axis that goes up to `max`.
+fn scale(value: f32, min: f32, max: i32) -> i32 {
+    (value.clamp(min, 1.) * max as f32).round() as i32
+}
+
+#[cfg(test)]
+mod tests {
+    use evdev::InputEvent;
+
+    use super::*;
+
+    fn abs(axis: AbsoluteAxisCode, value: i32) -> InputEvent {
+        *AbsoluteAxisEvent::new(axis, value)
+    }
+
+    fn hat(x: i32, y: i32) -> Report {
+        vec![abs(AbsoluteAxisCode::ABS_HAT0X, x), abs(AbsoluteAxisCode::ABS_HAT0Y, y)]
+    }
+
+    #[test]
+    fn buttons() {
+        let mut encoder = Encoder::default();
+        assert_eq!(encoder.button(GamepadButton::A, true), [*KeyEvent::new(KeyCode::BTN_SOUTH, 1)]);
+        assert_eq!(encoder.button(GamepadButton::A, false), [*KeyEvent::new(
+            KeyCode::BTN_SOUTH,
+            0
+        )]);
+    }
+
+    #[test]
+    fn dpad() {
+        let mut encoder = Encoder::default();
+        assert_eq!(encoder.button(GamepadButton::DpadUp, true), hat(0, -1));
+        assert_eq!(encoder.button(GamepadButton::DpadRight, true), hat(1, -1));
+        assert_eq!(encoder.button(GamepadButton::DpadUp, false), hat(1, 0));
+        assert_eq!(encoder.button(GamepadButton::DpadRight, false), hat(0, 0));
+    }
+
+    #[test]
+    fn dpad_opposites_cancel_out() {
+        let mut encoder = Encoder::default();
+        assert_eq!(encoder.button(GamepadButton::DpadLeft, true), hat(-1, 0));
+        assert_eq!(encoder.button(GamepadButton::DpadRight, true), hat(0, 0));
+        // the one still held takes over
+        assert_eq!(encoder.button(GamepadButton::DpadLeft, false), hat(1, 0));
+
+        assert_eq!(encoder.button(GamepadButton::DpadDown, true), hat(1, 1));
+        assert_eq!(encoder.button(GamepadButton::DpadUp, true), hat(1, 0));
+        assert_eq!(encoder.button(GamepadButton::DpadDown, false), hat(1, -1));
+    }
+
+    #[test]
+    fn stick() {
+        assert_eq!(Encoder::stick(GamepadStick::Left, 1., -1.), [
+            abs(AbsoluteAxisCode::ABS_X, STICK_MAX),
+            abs(AbsoluteAxisCode::ABS_Y, -STICK_MAX),
+        ]);
+        assert_eq!(Encoder::stick(GamepadStick::Right, 0.5, 0.), [
+            abs(AbsoluteAxisCode::ABS_RX, 16384),
+            abs(AbsoluteAxisCode::ABS_RY, 0),
+        ]);
+    }
+
+    #[test]
+    fn stick_is_clamped() {
+        assert_eq!(Encoder::stick(GamepadStick::Left, 1.5, -3.), [
+            abs(AbsoluteAxisCode::ABS_X, STICK_MAX),
+            abs(AbsoluteAxisCode::ABS_Y, -STICK_MAX),
+        ]);
+    }
+
+    #[test]
+    fn trigger() {
+        assert_eq!(Encoder::trigger(GamepadTrigger::Left, 1.), [abs(
+            AbsoluteAxisCode::ABS_Z,
+            TRIGGER_MAX
+        )]);
+        assert_eq!(Encoder::trigger(GamepadTrigger::Right, 0.5), [abs(
+            AbsoluteAxisCode::ABS_RZ,
+            128
+        )]);
+    }
+
+    #[test]
+    fn trigger_is_clamped() {
+        assert_eq!(Encoder::trigger(GamepadTrigger::Left, 2.), [abs(
+            AbsoluteAxisCode::ABS_Z,
+            TRIGGER_MAX
+        )]);
+        // triggers don't go the other way
+        assert_eq!(Encoder::trigger(GamepadTrigger::Left, -0.5), [abs(AbsoluteAxisCode::ABS_Z, 0)]);
+    }
+}
@@ -0,0 +1,134 @@
+use ::enigo::{Axis, Coordinate, Keyboard as _, Mouse as _, Settings};
+
+use super::{Direction, Error, InputBackend, Key, Modifier, TrackpadButton, take_whole};
+
+/// Input through the display server, with `enigo`. On Wayland this needs a compositor with the
+/// virtual pointer and keyboard protocols, and a session to find it in.
+pub struct Enigo {
+    enigo: ::enigo::Enigo,
+    /// Fractions of wheel clicks that weren't scrolled yet, horizontally and vertically.
+    scroll: (f32, f32),
+}
+
+impl Enigo {
+    /// # Errors
+    ///
+    /// Fails if there's no display server to connect to.
+    pub fn new() -> Result<Self, Error> {
+        Ok(Self {
+            enigo: ::enigo::Enigo::new(&Settings::default())?,
+            scroll: (0., 0.),
+        })
+    }
+}
+
+impl InputBackend for Enigo {
+    fn move_pointer(&mut self, delta_x: i32, delta_y: i32) -> Result<(), Error> {
+        Ok(self.enigo.move_mouse(delta_x, delta_y, Coordinate::Rel)?)
+    }
+
+    fn button(&mut self, button: TrackpadButton, direction: Direction) -> Result<(), Error> {
+        Ok(self
+            .enigo
+            .button(enigo_button(button), enigo_direction(direction))?)
+    }
+
+    fn scroll(&mut self, delta_x: f32, delta_y: f32) -> Result<(), Error> {
+        let x = take_whole(&mut self.scroll.0, delta_x);
+        let y = take_whole(&mut self.scroll.1, delta_y);
+
+        if y != 0 {
+            self.enigo.scroll(y, Axis::Vertical)?;
+        }
+        if x != 0 {
+            self.enigo.scroll(x, Axis::Horizontal)?;
+        }
+        Ok(())
+    }
+
+    fn key(&mut self, key: Key, direction: Direction) -> Result<(), Error> {
+        Ok(self
+            .enigo
+            .key(enigo_key(key)?, enigo_direction(direction))?)
+    }
+
+    fn modifier(&mut self, modifier: Modifier, direction: Direction) -> Result<(), Error> {
+        Ok(self
+            .enigo
+            .key(enigo_modifier(modifier), enigo_direction(direction))?)
+    }
+
+    fn text(&mut self, text: &str) -> Result<(), Error> {
+        Ok(self.enigo.text(text)?)
+    }
+}
+
+fn enigo_direction(direction: Direction) -> ::enigo::Direction {
+    match direction {
+        Direction::Press => ::enigo::Direction::Press,
+        Direction::Release => ::enigo::Direction::Release,
+        Direction::Click => ::enigo::Direction::Click,
+    }
+}
+
+fn enigo_button(button: TrackpadButton) -> ::enigo::Button {
+    match button {
+        TrackpadButton::Left => ::enigo::Button::Left,
+        TrackpadButton::Right => ::enigo::Button::Right,
+        TrackpadButton::Middle => ::enigo::Button::Middle,
+    }
+}
+
+fn enigo_key(key: Key) -> Result<::enigo::Key, Error> {
+    use ::enigo::Key as K;
+
+    Ok(match key {
+        Key::Escape => K::Escape,
+        Key::Return => K::Return,
+        Key::Tab => K::Tab,
+        Key::Backspace => K::Backspace,
+        Key::Delete => K::Delete,
+        Key::Insert => K::Insert,
+        Key::Space => K::Space,
+        Key::Up => K::UpArrow,
+        Key::Down => K::DownArrow,
+        Key::Left => K::LeftArrow,
+        Key::Right => K::RightArrow,
+        Key::Home => K::Home,
+        Key::End => K::End,
+        Key::PageUp => K::PageUp,
+        Key::PageDown => K::PageDown,
+        Key::F(n) => function_key(n).ok_or(Error::FunctionKey(n))?,
+        Key::PrintScreen => K::PrintScr,
+        Key::VolumeUp => K::VolumeUp,
+        Key::VolumeDown => K::VolumeDown,
+        Key::VolumeMute => K::VolumeMute,
+        Key::MediaPlayPause => K::MediaPlayPause,
+        Key::MediaNext => K::MediaNextTrack,
+        Key::MediaPrevious => K::MediaPrevTrack,
+        Key::MediaStop => K::MediaStop,
+        Key::Char(c) => K::Unicode(c),
+    })
+}
+
+fn function_key(n: u8) -> Option<::enigo::Key> {
+    use ::enigo::Key::{
+        F1, F2, F3, F4, F5, F6, F7, F8, F9, F10, F11, F12, F13, F14, F15, F16, F17, F18, F19, F20,
+        F21, F22, F23, F24,
+    };
+
+    let keys = [
+        F1, F2, F3, F4, F5, F6, F7, F8, F9, F10, F11, F12, F13, F14, F15, F16, F17, F18, F19, F20,
+        F21, F22, F23, F24,
+    ];
+    keys.get(usize::from(n).checked_sub(1)?).copied()
+}
+
+fn enigo_modifier(modifier: Modifier) -> ::enigo::Key {
+    match modifier {
+        Modifier::Shift => ::enigo::Key::Shift,
+        Modifier::Control => ::enigo::Key::Control,
+        Modifier::Alt => ::enigo::Key::Alt,
+        Modifier::Meta => ::enigo::Key::Meta,
+    }
+}
@@ -0,0 +1,138 @@
+//! Mouse and keyboard input, through a pluggable [`InputBackend`].
+
+pub use dark_remote_common::config::{Key, Modifier, TrackpadButton};
+use thiserror::Error;
+
+pub use self::{enigo::Enigo, uinput::Uinput};
+
+mod enigo;
+pub mod uinput;
+
+/// What to do with a button or key.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Direction {
+    Press,
+    Release,
+    /// Press and release.
+    Click,
+}
+
+/// Something that injects mouse and keyboard input.
+///
+/// # Errors
+///
+/// Every method fails if the input can't be injected, or if the backend can't do what's asked,
+/// like typing a character that isn't on its keyboard.
+#[expect(clippy::missing_errors_doc)] // documented once, above
+pub trait InputBackend: Send {
+    /// Moves the pointer by this many pixels.
+    fn move_pointer(&mut self, delta_x: i32, delta_y: i32) -> Result<(), Error>;
+
+    fn button(&mut self, button: TrackpadButton, direction: Direction) -> Result<(), Error>;
+
+    /// Scrolls by this many wheel clicks, where positive is down and right. Backends that can't
+    /// scroll by a fraction of a click save fractions up for the next scroll.
+    fn scroll(&mut self, delta_x: f32, delta_y: f32) -> Result<(), Error>;
+
+    fn key(&mut self, key: Key, direction: Direction) -> Result<(), Error>;
+
+    fn modifier(&mut self, modifier: Modifier, direction: Direction) -> Result<(), Error>;
+
+    /// Types `text`, whatever keys that takes.
+    fn text(&mut self, text: &str) -> Result<(), Error>;
+}
+
+#[derive(Error, Debug)]
+pub enum Error {
+    #[error(transparent)]
+    Enigo(#[from] ::enigo::InputError),
+    #[error("Failed to connect to the display server: {0}")]
+    Connect(#[from] ::enigo::NewConError),
+    #[error("uinput error: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("There is no F{0} key, function keys go from F1 to F24")]
+    FunctionKey(u8),
+    #[error("There is no key for {0:?}")]
+    NoKey(char),
+}
+
+/// Adds `delta` to `remainder`, and takes the whole part back out of it, for what can only be done
+/// in whole steps. What's left over has the same sign as the total, so it's never more than a step.
+pub fn take_whole(remainder: &mut f32, delta: f32) -> i32 {
+    *remainder += delta;
+    let whole = remainder.trunc();
+    *remainder -= whole;
+    whole as i32
+}
+
+/// Presses `modifiers` in order, does `action`, and releases whichever modifiers were pressed, even
+/// if something failed halfway.
+///
+/// # Errors
+///
+/// Fails if any modifier couldn't be pressed or released, or if `action` failed.
+pub fn chord(
+    input: &mut dyn InputBackend,
+    modifiers: &[Modifier],
+    action: impl FnOnce(&mut dyn InputBackend) -> Result<(), Error>,
+) -> Result<(), Error> {
+    let mut held = Vec::new();
+    let mut result = Ok(());
+
+    for &modifier in modifiers {
+        result = input.modifier(modifier, Direction::Press);
+        if result.is_err() {
+            break;
+        }
+        held.push(modifier);
+    }
+
+    if result.is_ok() {
+        result = action(input);
+    }
+
+    for modifier in held.into_iter().rev() {
+        let released = input.modifier(modifier, Direction::Release);
+        result = result.and(released);
+    }
+
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn whole() {
+        let mut remainder = 0.;
+        assert_eq!(take_whole(&mut remainder, 2.), 2);
+        assert_eq!(remainder, 0.);
+    }
+
+    #[test]
+    fn fractions_add_up() {
+        let mut remainder = 0.;
+        assert_eq!(take_whole(&mut remainder, 0.75), 0);
+        assert_eq!(take_whole(&mut remainder, 0.75), 1);
+        assert_eq!(remainder, 0.5);
+    }
+
+    #[test]
+    fn negative() {
+        let mut remainder = 0.;
+        assert_eq!(take_whole(&mut remainder, -1.75), -1);
+        assert_eq!(remainder, -0.75);
+        assert_eq!(take_whole(&mut remainder, -0.5), -1);
+        assert_eq!(remainder, -0.25);
+    }
+
+    #[test]
+    fn changing_direction() {
+        // a leftover fraction one way is used up going the other way first
+        let mut remainder = 0.;
+        assert_eq!(take_whole(&mut remainder, -0.5), 0);
+        assert_eq!(take_whole(&mut remainder, 1.), 0);
+        assert_eq!(remainder, 0.5);
+    }
+}
@@ -0,0 +1,518 @@
+//! A virtual mouse and keyboard made with `/dev/uinput`.
+//!
+//! The events are put together by an [`Encoder`], which doesn't need the kernel device, and then
+//! written to it by [`Uinput`].
+
+use evdev::{
+    AttributeSet, InputEvent, KeyCode, KeyEvent, RelativeAxisCode, RelativeAxisEvent,
+    uinput::VirtualDevice,
+};
+
+use super::{Direction, Error, InputBackend, Key, Modifier, TrackpadButton, take_whole};
+
+/// What the device is called, in `libinput list-devices` and the like.
+const DEVICE_NAME: &str = "dark-remote";
+
+/// High-resolution scroll units in a wheel click, as the kernel defines them.
+pub const HI_RES_PER_CLICK: i32 = 120;
+
+/// Events that happen at the same time. The device ends each one with a `SYN_REPORT`.
+pub type Report = Vec<InputEvent>;
+
+/// Input through a virtual device, which works without a display server or a session, like under
+/// systemd. Needs write access to `/dev/uinput`, usually through the `input` group or a udev rule.
+pub struct Uinput {
+    device: VirtualDevice,
+    encoder: Encoder,
+}
+
+impl Uinput {
+    /// Creates the virtual device.
+    ///
+    /// # Errors
+    ///
+    /// Fails if `/dev/uinput` can't be opened, or the device can't be created.
+    pub fn new() -> Result<Self, Error> {
+        let mut keys = AttributeSet::<KeyCode>::new();
+        // every key on a keyboard, since we don't know what'll be typed
+        for code in 1..=KeyCode::KEY_MICMUTE.0 {
+            keys.insert(KeyCode::new(code));
+        }
+        for button in [KeyCode::BTN_LEFT, KeyCode::BTN_RIGHT, KeyCode::BTN_MIDDLE] {
+            keys.insert(button);
+        }
+
+        let mut axes = AttributeSet::<RelativeAxisCode>::new();
+        for axis in [
+            RelativeAxisCode::REL_X,
+            RelativeAxisCode::REL_Y,
+            RelativeAxisCode::REL_WHEEL,
+            RelativeAxisCode::REL_HWHEEL,
+            RelativeAxisCode::REL_WHEEL_HI_RES,
+            RelativeAxisCode::REL_HWHEEL_HI_RES,
+        ] {
+            axes.insert(axis);
+        }
+
+        let device = VirtualDevice::builder()?
+            .name(DEVICE_NAME)
+            .with_keys(&keys)?
+            .with_relative_axes(&axes)?
+            .build()?;
+
+        Ok(Self { device, encoder: Encoder::default() })
+    }
+
+    fn emit(&mut self, reports: &[Report]) -> Result<(), Error> {
+        for report in reports {
+            self.device.emit(report)?;
+        }
+        Ok(())
+    }
+}
+
+impl InputBackend for Uinput {
+    fn move_pointer(&mut self, delta_x: i32, delta_y: i32) -> Result<(), Error> {
+        let reports = Encoder::move_pointer(delta_x, delta_y);
+        self.emit(&reports)
+    }
+
+    fn button(&mut self, button: TrackpadButton, direction: Direction) -> Result<(), Error> {
+        let reports = Encoder::button(button, direction);
+        self.emit(&reports)
+    }
+
+    fn scroll(&mut self, delta_x: f32, delta_y: f32) -> Result<(), Error> {
+        let reports = self.encoder.scroll(delta_x, delta_y);
+        self.emit(&reports)
+    }
+
+    fn key(&mut self, key: Key, direction: Direction) -> Result<(), Error> {
+        let reports = Encoder::key(key, direction)?;
+        self.emit(&reports)
+    }
+
+    fn modifier(&mut self, modifier: Modifier, direction: Direction) -> Result<(), Error> {
+        let reports = Encoder::modifier(modifier, direction);
+        self.emit(&reports)
+    }
+
+    fn text(&mut self, text: &str) -> Result<(), Error> {
+        let reports = Encoder::text(text)?;
+        self.emit(&reports)
+    }
+}
+
+/// Turns input into evdev events.
+#[derive(Debug, Default)]
+pub struct Encoder {
+    /// Fractions of high-resolution units that weren't scrolled yet, horizontally and vertically.
+    hi_res: (f32, f32),
+    /// High-resolution units scrolled since the last whole click, for programs that only read
+    /// `REL_WHEEL` and `REL_HWHEEL`.
+    clicks: (i32, i32),
+}
+
+impl Encoder {
+    pub fn move_pointer(delta_x: i32, delta_y: i32) -> Vec<Report> {
+        let report = [(RelativeAxisCode::REL_X, delta_x), (RelativeAxisCode::REL_Y, delta_y)]
+            .into_iter()
+            .filter(|&(_, value)| value != 0)
+            .map(|(axis, value)| *RelativeAxisEvent::new(axis, value))
+            .collect::<Report>();
+
+        if report.is_empty() {
+            Vec::new()
+        } else {
+            vec![report]
+        }
+    }
+
+    pub fn button(button: TrackpadButton, direction: Direction) -> Vec<Report> {
+        let code = match button {
+            TrackpadButton::Left => KeyCode::BTN_LEFT,
+            TrackpadButton::Right => KeyCode::BTN_RIGHT,
+            TrackpadButton::Middle => KeyCode::BTN_MIDDLE,
+        };
+        press(&[code], direction)
+    }
+
+    /// In wheel clicks, where positive is down and right. Sends both high-resolution events and,
+    /// once they add up to a click, the classic ones.
+    pub fn scroll(&mut self, delta_x: f32, delta_y: f32) -> Vec<Report> {
+        let x = take_whole(&mut self.hi_res.0, delta_x * HI_RES_PER_CLICK as f32);
+        let y = take_whole(&mut self.hi_res.1, delta_y * HI_RES_PER_CLICK as f32);
+
+        self.clicks.0 += x;
+        self.clicks.1 += y;
+        let clicks_x = self.clicks.0 / HI_RES_PER_CLICK;
+        let clicks_y = self.clicks.1 / HI_RES_PER_CLICK;
+        self.clicks.0 -= clicks_x * HI_RES_PER_CLICK;
+        self.clicks.1 -= clicks_y * HI_RES_PER_CLICK;
+
+        // the wheel axes are positive for up, and the horizontal ones for right
+        let report = [
+            (RelativeAxisCode::REL_WHEEL, -clicks_y),
+            (RelativeAxisCode::REL_WHEEL_HI_RES, -y),
+            (RelativeAxisCode::REL_HWHEEL, clicks_x),
+            (RelativeAxisCode::REL_HWHEEL_HI_RES, x),
+        ]
+        .into_iter()
+        .filter(|&(_, value)| value != 0)
+        .map(|(axis, value)| *RelativeAxisEvent::new(axis, value))
+        .collect::<Report>();
+
+        if report.is_empty() {
+            Vec::new()
+        } else {
+            vec![report]
+        }
+    }
+
+    /// # Errors
+    ///
+    /// Fails for function keys past F24, and characters that aren't on a US keyboard.
+    pub fn key(key: Key, direction: Direction) -> Result<Vec<Report>, Error> {
+        Ok(match key_code(key)? {
+            (code, true) => press(&[KeyCode::KEY_LEFTSHIFT, code], direction),
+            (code, false) => press(&[code], direction),
+        })
+    }
+
+    pub fn modifier(modifier: Modifier, direction: Direction) -> Vec<Report> {
+        let code = match modifier {
+            Modifier::Shift => KeyCode::KEY_LEFTSHIFT,
+            Modifier::Control => KeyCode::KEY_LEFTCTRL,
+            Modifier::Alt => KeyCode::KEY_LEFTALT,
+            Modifier::Meta => KeyCode::KEY_LEFTMETA,
+        };
+        press(&[code], direction)
+    }
+
+    /// Nothing is typed if any character can't be.
+    ///
+    /// # Errors
+    ///
+    /// Fails for characters that aren't on a US keyboard.
+    pub fn text(text: &str) -> Result<Vec<Report>, Error> {
+        let mut reports = Vec::new();
+        for c in text.chars() {
+            reports.extend(Self::key(Key::Char(c), Direction::Click)?);
+        }
+        Ok(reports)
+    }
+}
+
+/// Presses `codes` in order and releases them in reverse, one per report, so a modifier is down
+/// before the key it modifies.
+fn press(codes: &[KeyCode], direction: Direction) -> Vec<Report> {
+    let down = codes.iter().map(|&code| vec![*KeyEvent::new(code, 1)]);
+    let up = codes
+        .iter()
+        .rev()
+        .map(|&code| vec![*KeyEvent::new(code, 0)]);
+
+    match direction {
+        Direction::Press => down.collect(),
+        Direction::Release => up.collect(),
+        Direction::Click => down.chain(up).collect(),
+    }
+}
+
+/// The key for `key`, and whether it needs Shift.
+fn key_code(key: Key) -> Result<(KeyCode, bool), Error> {
+    Ok((
+        match key {
+            Key::Escape => KeyCode::KEY_ESC,
+            Key::Return => KeyCode::KEY_ENTER,
+            Key::Tab => KeyCode::KEY_TAB,
+            Key::Backspace => KeyCode::KEY_BACKSPACE,
+            Key::Delete => KeyCode::KEY_DELETE,
+            Key::Insert => KeyCode::KEY_INSERT,
+            Key::Space => KeyCode::KEY_SPACE,
+            Key::Up => KeyCode::KEY_UP,
+            Key::Down => KeyCode::KEY_DOWN,
+            Key::Left => KeyCode::KEY_LEFT,
+            Key::Right => KeyCode::KEY_RIGHT,
+            Key::Home => KeyCode::KEY_HOME,
+            Key::End => KeyCode::KEY_END,
+            Key::PageUp => KeyCode::KEY_PAGEUP,
+            Key::PageDown => KeyCode::KEY_PAGEDOWN,
+            Key::F(n) => function_key(n).ok_or(Error::FunctionKey(n))?,
+            Key::PrintScreen => KeyCode::KEY_SYSRQ,
+            Key::VolumeUp => KeyCode::KEY_VOLUMEUP,
+            Key::VolumeDown => KeyCode::KEY_VOLUMEDOWN,
+            Key::VolumeMute => KeyCode::KEY_MUTE,
+            Key::MediaPlayPause => KeyCode::KEY_PLAYPAUSE,
+            Key::MediaNext => KeyCode::KEY_NEXTSONG,
+            Key::MediaPrevious => KeyCode::KEY_PREVIOUSSONG,
+            Key::MediaStop => KeyCode::KEY_STOPCD,
+            Key::Char(c) => return char_key(c).ok_or(Error::NoKey(c)),
+        },
+        false,
+    ))
+}
+
+fn function_key(n: u8) -> Option<KeyCode> {
+    use evdev::KeyCode as K;
+
+    let keys = [
+        K::KEY_F1,
+        K::KEY_F2,
+        K::KEY_F3,
+        K::KEY_F4,
+        K::KEY_F5,
+        K::KEY_F6,
+        K::KEY_F7,
+        K::KEY_F8,
+        K::KEY_F9,
+        K::KEY_F10,
+        K::KEY_F11,
+        K::KEY_F12,
+        K::KEY_F13,
+        K::KEY_F14,
+        K::KEY_F15,
+        K::KEY_F16,
+        K::KEY_F17,
+        K::KEY_F18,
+        K::KEY_F19,
+        K::KEY_F20,
+        K::KEY_F21,
+        K::KEY_F22,
+        K::KEY_F23,
+        K::KEY_F24,
+    ];
+    keys.get(usize::from(n).checked_sub(1)?).copied()
+}
+
+/// The key that types `c` on a US keyboard, and whether it needs Shift.
+fn char_key(c: char) -> Option<(KeyCode, bool)> {
+    use evdev::KeyCode as K;
+
+    const LETTERS: [KeyCode; 26] = [
+        K::KEY_A,
+        K::KEY_B,
+        K::KEY_C,
+        K::KEY_D,
+        K::KEY_E,
+        K::KEY_F,
+        K::KEY_G,
+        K::KEY_H,
+        K::KEY_I,
+        K::KEY_J,
+        K::KEY_K,
+        K::KEY_L,
+        K::KEY_M,
+        K::KEY_N,
+        K::KEY_O,
+        K::KEY_P,
+        K::KEY_Q,
+        K::KEY_R,
+        K::KEY_S,
+        K::KEY_T,
+        K::KEY_U,
+        K::KEY_V,
+        K::KEY_W,
+        K::KEY_X,
+        K::KEY_Y,
+        K::KEY_Z,
+    ];
+    const DIGITS: [KeyCode; 10] = [
+        K::KEY_0,
+        K::KEY_1,
+        K::KEY_2,
+        K::KEY_3,
+        K::KEY_4,
+        K::KEY_5,
+        K::KEY_6,
+        K::KEY_7,
+        K::KEY_8,
+        K::KEY_9,
+    ];
+    /// With Shift, in the order of [`DIGITS`].
+    const SHIFTED_DIGITS: &str = ")!@#$%^&*(";
+
+    if c.is_ascii_lowercase() {
+        return LETTERS
+            .get(usize::from(c as u8 - b'a'))
+            .map(|&key| (key, false));
+    }
+    if c.is_ascii_uppercase() {
+        return LETTERS
+            .get(usize::from(c as u8 - b'A'))
+            .map(|&key| (key, true));
+    }
+    if let Some(digit) = c.to_digit(10) {
+        return DIGITS.get(digit as usize).map(|&key| (key, false));
+    }
+    if let Some(index) = SHIFTED_DIGITS.find(c) {
+        return DIGITS.get(index).map(|&key| (key, true));
+    }
+
+    Some(match c {
+        ' ' => (K::KEY_SPACE, false),
+        '\n' => (K::KEY_ENTER, false),
+        '\t' => (K::KEY_TAB, false),
+        '-' => (K::KEY_MINUS, false),
+        '_' => (K::KEY_MINUS, true),
+        '=' => (K::KEY_EQUAL, false),
+        '+' => (K::KEY_EQUAL, true),
+        '[' => (K::KEY_LEFTBRACE, false),
+        '{' => (K::KEY_LEFTBRACE, true),
+        ']' => (K::KEY_RIGHTBRACE, false),
+        '}' => (K::KEY_RIGHTBRACE, true),
+        '\\' => (K::KEY_BACKSLASH, false),
+        '|' => (K::KEY_BACKSLASH, true),
+        ';' => (K::KEY_SEMICOLON, false),
+        ':' => (K::KEY_SEMICOLON, true),
+        '\'' => (K::KEY_APOSTROPHE, false),
+        '"' => (K::KEY_APOSTROPHE, true),
+        '`' => (K::KEY_GRAVE, false),
+        '~' => (K::KEY_GRAVE, true),
+        ',' => (K::KEY_COMMA, false),
+        '<' => (K::KEY_COMMA, true),
+        '.' => (K::KEY_DOT, false),
+        '>' => (K::KEY_DOT, true),
+        '/' => (K::KEY_SLASH, false),
+        '?' => (K::KEY_SLASH, true),
+        _ => return None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rel(axis: RelativeAxisCode, value: i32) -> InputEvent {
+        *RelativeAxisEvent::new(axis, value)
+    }
+
+    fn key(code: KeyCode, value: i32) -> InputEvent {
+        *KeyEvent::new(code, value)
+    }
+
+    #[test]
+    fn move_in_one_report() {
+        assert_eq!(Encoder::move_pointer(3, -4), [[
+            rel(RelativeAxisCode::REL_X, 3),
+            rel(RelativeAxisCode::REL_Y, -4),
+        ]]);
+        assert_eq!(Encoder::move_pointer(0, 2), [[rel(RelativeAxisCode::REL_Y, 2)]]);
+        assert!(Encoder::move_pointer(0, 0).is_empty());
+    }
+
+    #[test]
+    fn hi_res_adds_up_to_a_click() {
+        let mut encoder = Encoder::default();
+        for _ in 0..3 {
+            // down is negative on the wheel
+            assert_eq!(encoder.scroll(0., 0.25), [[rel(RelativeAxisCode::REL_WHEEL_HI_RES, -30)]]);
+        }
+        assert_eq!(encoder.scroll(0., 0.25), [[
+            rel(RelativeAxisCode::REL_WHEEL, -1),
+            rel(RelativeAxisCode::REL_WHEEL_HI_RES, -30),
+        ]]);
+    }
+
+    #[test]
+    fn hi_res_fractions_add_up() {
+        let mut encoder = Encoder::default();
+        // a tenth of a unit up each
+        for _ in 0..9 {
+            assert!(encoder.scroll(0., -0.1 / 120.).is_empty());
+        }
+        assert_eq!(encoder.scroll(0., -0.2 / 120.), [[rel(RelativeAxisCode::REL_WHEEL_HI_RES, 1)]]);
+    }
+
+    #[test]
+    fn detents_every_120_units() {
+        let mut encoder = Encoder::default();
+        assert_eq!(encoder.scroll(0., -2.5), [[
+            rel(RelativeAxisCode::REL_WHEEL, 2),
+            rel(RelativeAxisCode::REL_WHEEL_HI_RES, 300),
+        ]]);
+        // the half click left over makes a whole one with this
+        assert_eq!(encoder.scroll(0., -0.5), [[
+            rel(RelativeAxisCode::REL_WHEEL, 1),
+            rel(RelativeAxisCode::REL_WHEEL_HI_RES, 60),
+        ]]);
+    }
+
+    #[test]
+    fn horizontal() {
+        let mut encoder = Encoder::default();
+        assert_eq!(encoder.scroll(1., 0.), [[
+            rel(RelativeAxisCode::REL_HWHEEL, 1),
+            rel(RelativeAxisCode::REL_HWHEEL_HI_RES, 120),
+        ]]);
+        assert_eq!(encoder.scroll(-0.5, 0.), [[rel(RelativeAxisCode::REL_HWHEEL_HI_RES, -60)]]);
+    }
+
+    #[test]
+    fn click() {
+        assert_eq!(Encoder::button(TrackpadButton::Right, Direction::Click), [
+            [key(KeyCode::BTN_RIGHT, 1)],
+            [key(KeyCode::BTN_RIGHT, 0)],
+        ]);
+    }
+
+    #[test]
+    fn press_and_release() {
+        assert_eq!(Encoder::button(TrackpadButton::Left, Direction::Press), [[key(
+            KeyCode::BTN_LEFT,
+            1
+        )]]);
+        assert_eq!(Encoder::button(TrackpadButton::Left, Direction::Release), [[key(
+            KeyCode::BTN_LEFT,
+            0
+        )]]);
+    }
+
+    #[test]
+    fn shifted_character() {
+        // Shift goes down first and comes up last, each in its own report
+        assert_eq!(Encoder::key(Key::Char('?'), Direction::Click).unwrap(), [
+            [key(KeyCode::KEY_LEFTSHIFT, 1)],
+            [key(KeyCode::KEY_SLASH, 1)],
+            [key(KeyCode::KEY_SLASH, 0)],
+            [key(KeyCode::KEY_LEFTSHIFT, 0)],
+        ]);
+        assert_eq!(Encoder::key(Key::Char('A'), Direction::Release).unwrap(), [
+            [key(KeyCode::KEY_A, 0)],
+            [key(KeyCode::KEY_LEFTSHIFT, 0)],
+        ]);
+    }
+
+    #[test]
+    fn text() {
+        assert_eq!(Encoder::text("a!").unwrap(), [
+            [key(KeyCode::KEY_A, 1)],
+            [key(KeyCode::KEY_A, 0)],
+            [key(KeyCode::KEY_LEFTSHIFT, 1)],
+            [key(KeyCode::KEY_1, 1)],
+            [key(KeyCode::KEY_1, 0)],
+            [key(KeyCode::KEY_LEFTSHIFT, 0)],
+        ]);
+    }
+
+    #[test]
+    fn one_key_per_report() {
+        let reports = Encoder::text("Hello, World!\n").unwrap();
+        assert!(reports.iter().all(|report| report.len() == 1));
+    }
+
+    #[test]
+    fn nothing_typed_for_missing_character() {
+        assert!(matches!(Encoder::text("abc€"), Err(Error::NoKey('€'))));
+    }
+
+    #[test]
+    fn function_keys() {
+        assert_eq!(Encoder::key(Key::F(24), Direction::Press).unwrap(), [[key(
+            KeyCode::KEY_F24,
+            1
+        )]]);
+        assert!(matches!(Encoder::key(Key::F(0), Direction::Press), Err(Error::FunctionKey(0))));
+        assert!(matches!(Encoder::key(Key::F(25), Direction::Press), Err(Error::FunctionKey(25))));
+    }
+}
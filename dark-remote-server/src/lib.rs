@@ -0,0 +1,7 @@
+//! Backends for the things the dark remote server controls.
+
+pub mod audio;
+pub mod cec;
+pub mod gamepad;
+pub mod input;
+pub mod media;
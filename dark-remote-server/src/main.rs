@@ -1,49 +1,183 @@
-use axum::extract::connect_info::ConnectInfo;
-use axum::{Router, extract::ws::WebSocketUpgrade, response::IntoResponse, routing::any};
-use axum_extra::{TypedHeader, headers};
-use std::{net::SocketAddr, path::PathBuf};
-use tower_http::{
-    services::ServeDir,
-    trace::{DefaultMakeSpan, TraceLayer},
+use std::{
+    future::IntoFuture as _,
+    net::SocketAddr,
+    sync::Arc,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use axum::{
+    Router,
+    extract::{Query, State, connect_info::ConnectInfo, ws::WebSocketUpgrade},
+    http::StatusCode,
+    response::{IntoResponse as _, Response},
+    routing::any,
 };
+use axum_extra::{TypedHeader, headers};
+use axum_server::tls_rustls::RustlsConfig;
+use clap::Parser as _;
+use dark_remote_common::config::Config;
+use futures_util::FutureExt as _;
+use serde::Deserialize;
+use tower_http::{services::ServeDir, trace::TraceLayer};
 use tracing_subscriber::{layer::SubscriberExt as _, util::SubscriberInitExt as _};
 
-use self::connection::Connection;
+use self::{
+    acceleration::{Accelerator, PointerConfig},
+    args::{Args, Command},
+    config::ServerConfig,
+    connection::Connection,
+    pairing::{Authorized, Pairing},
+    service::ServiceHandle,
+    tls::Tls,
+};
 
+mod acceleration;
+mod args;
+mod config;
 mod connection;
+mod mdns;
+mod pairing;
+mod service;
+mod tls;
+
+#[derive(Clone)]
+struct AppState {
+    /// Sent to clients in the welcome.
+    name: Arc<str>,
+    config: Arc<Config>,
+    pointer: Arc<PointerConfig>,
+    service: ServiceHandle,
+    /// `None` if pairing is turned off, and anyone can connect.
+    pairing: Option<Arc<Pairing>>,
+}
+
+/// Query parameters of `/ws`.
+#[derive(Debug, Deserialize)]
+struct WsParams {
+    /// Token of a paired device.
+    token: Option<String>,
+    /// Pairing code, to pair a new device.
+    pair: Option<String>,
+    /// What to call the new device. Defaults to its user agent.
+    name: Option<String>,
+}
 
 #[tokio::main]
-async fn main() -> Result<(), std::io::Error> {
+async fn main() -> Result<(), Box<dyn core::error::Error>> {
+    let mut args = Args::parse();
+    let command = args.command.take();
+
+    let config_path = args.config.clone().or_else(config::default_path);
+    let config_file = match &config_path {
+        Some(path) => config::load(path)?,
+        None => None,
+    };
+    let found_config = config_file.is_some();
+    let mut config = config_file.unwrap_or_default();
+    args.apply(&mut config);
+
+    if let Some(command) = command {
+        return run_command(command, &config.server);
+    }
+
     tracing_subscriber::registry()
-        .with(tracing_subscriber::EnvFilter::try_from_default_env().unwrap_or_else(|_| {
-            format!("{}=debug,tower_http=debug", env!("CARGO_CRATE_NAME")).into()
-        }))
+        .with(tracing_subscriber::EnvFilter::try_new(&config.server.log)?)
         .with(tracing_subscriber::fmt::layer())
         .init();
 
-    let assets_dir = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("assets");
+    if !found_config {
+        tracing::warn!(?config_path, "config file not found, using defaults");
+    }
+
+    let service = ServiceHandle::spawn(&config).await;
+    let capabilities = service.capabilities().to_vec();
+    let name = config.server.name();
+
+    let pairing = if config.server.pairing {
+        Some(Arc::new(Pairing::new(config.server.devices_path())?))
+    } else {
+        tracing::warn!("pairing is turned off, anyone on the network can connect");
+        None
+    };
+
+    let mut app = Router::new().route("/ws", any(ws_handler));
+
+    if let Some(assets_dir) = &config.server.assets_dir {
+        app =
+            app.fallback_service(ServeDir::new(assets_dir).append_index_html_on_directories(true));
+    } else {
+        tracing::info!("no assets directory configured, not serving the web UI");
+    }
+
+    let app = app
+        .layer(TraceLayer::new_for_http().make_span_with(make_span))
+        .with_state(AppState {
+            name: Arc::from(name.as_str()),
+            config: Arc::new(config.layout()),
+            pointer: Arc::new(config.pointer),
+            service,
+            pairing,
+        });
 
-    let app = Router::new()
-        .fallback_service(ServeDir::new(assets_dir).append_index_html_on_directories(true))
-        .route("/ws", any(ws_handler))
-        .layer(
-            TraceLayer::new_for_http()
-                .make_span_with(DefaultMakeSpan::default().include_headers(true)),
-        );
+    let tls = match &config.server.tls {
+        Some(tls) => {
+            let tls = Tls::load(tls, &config.server.data_dir)?;
+            tracing::info!("TLS certificate fingerprint (SHA-256): {}", tls.fingerprint);
+            Some(RustlsConfig::from_config(tls.config))
+        }
+        None => None,
+    };
+
+    // the daemon runs in its own thread, this is only its handle
+    let _mdns = if config.server.mdns {
+        mdns::advertise(&config.server.listen, &name, tls.is_some(), &capabilities)
+            .inspect_err(|error| tracing::warn!(?error, "failed to advertise over mDNS"))
+            .ok()
+    } else {
+        None
+    };
+
+    let mut servers = Vec::new();
+    for addr in &config.server.listen {
+        let listener = tokio::net::TcpListener::bind(addr).await?;
+
+        let local_addr = listener.local_addr()?;
+        tracing::debug!(?local_addr, tls = tls.is_some(), "webserver listening");
 
-    let listener = tokio::net::TcpListener::bind("0.0.0.0:3000").await?;
+        let service = app
+            .clone()
+            .into_make_service_with_connect_info::<SocketAddr>();
+        servers.push(match &tls {
+            Some(tls) => axum_server::from_tcp_rustls(listener.into_std()?, tls.clone())?
+                .serve(service)
+                .boxed(),
+            None => axum::serve(listener, service).into_future().boxed(),
+        });
+    }
 
-    let local_addr = listener.local_addr()?;
+    futures_util::future::try_join_all(servers).await?;
 
-    tracing::debug!(?local_addr, "webserver listening");
-    axum::serve(listener, app.into_make_service_with_connect_info::<SocketAddr>()).await
+    Ok(())
+}
+
+/// Like tower-http's default span, but without the query, since that has tokens and pairing codes
+/// in it.
+fn make_span(request: &axum::extract::Request) -> tracing::Span {
+    tracing::debug_span!(
+        "request",
+        method = %request.method(),
+        path = request.uri().path(),
+        version = ?request.version(),
+    )
 }
 
 async fn ws_handler(
     ws: WebSocketUpgrade,
+    State(state): State<AppState>,
+    Query(params): Query<WsParams>,
     user_agent: Option<TypedHeader<headers::UserAgent>>,
     ConnectInfo(client): ConnectInfo<SocketAddr>,
-) -> impl IntoResponse {
+) -> Response {
     let user_agent = if let Some(TypedHeader(user_agent)) = user_agent {
         user_agent.to_string()
     } else {
@@ -51,5 +185,72 @@ async fn ws_handler(
     };
     tracing::info!("`{user_agent}` at {client} connected.");
 
-    ws.on_upgrade(move |socket| Connection::new(client).handle(socket))
+    let authorized = match &state.pairing {
+        Some(pairing) => match authorize(pairing, params, user_agent) {
+            Ok(Some(authorized)) => Some(authorized),
+            Ok(None) => {
+                tracing::warn!(?client, "refused connection without a valid token or code");
+                return StatusCode::UNAUTHORIZED.into_response();
+            }
+            Err(error) => {
+                tracing::error!(?client, ?error, "failed to check pairing");
+                return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+            }
+        },
+        None => None,
+    };
+
+    let device = authorized.as_ref().map(|authorized| &authorized.device);
+    let accelerator = Accelerator::new(state.pointer.for_device(device).clone());
+
+    ws.on_upgrade(move |socket| {
+        Connection::new(client, authorized, state.name, state.config, accelerator, state.service)
+            .handle(socket)
+    })
+}
+
+/// Lets a paired device in, or pairs a new one if it has the pairing code.
+fn authorize(
+    pairing: &Pairing,
+    params: WsParams,
+    user_agent: String,
+) -> Result<Option<Authorized>, pairing::Error> {
+    if let Some(token) = params.token
+        && let Some(device) = pairing.authenticate(&token)?
+    {
+        return Ok(Some(Authorized { device, new: false }));
+    }
+
+    let Some(code) = params.pair else {
+        return Ok(None);
+    };
+
+    let name = params.name.unwrap_or(user_agent);
+    Ok(pairing
+        .pair(&code, name)?
+        .map(|device| Authorized { device, new: true }))
+}
+
+#[expect(clippy::print_stdout)] // these are for the terminal
+fn run_command(command: Command, config: &ServerConfig) -> Result<(), Box<dyn core::error::Error>> {
+    let path = config.devices_path();
+
+    match command {
+        Command::Devices => {
+            let now = SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map_or(0, |since| since.as_secs());
+
+            for device in pairing::load(&path)? {
+                let days = now.saturating_sub(device.paired_at) / (24 * 60 * 60);
+                println!("{}  {}  (paired {days} days ago)", device.id, device.name);
+            }
+        }
+        Command::Revoke { device } => {
+            let device = pairing::revoke(&path, &device)?;
+            println!("Revoked {} ({})", device.id, device.name);
+        }
+    }
+
+    Ok(())
 }
@@ -0,0 +1,143 @@
+//! Advertising the server over mDNS, so the app can find it on the network without typing a URL.
+
+use std::net::{IpAddr, SocketAddr};
+
+use dark_remote_common::protocol::{Capability, MDNS_SERVICE_TYPE};
+use mdns_sd::{IfKind, ServiceDaemon, ServiceInfo};
+
+/// Advertises the server listening on `addrs` as [`MDNS_SERVICE_TYPE`] called `name`, for as long
+/// as the returned daemon is running.
+///
+/// A service has one port, so if the server listens on more than one, each port gets its own
+/// service, with the port added to the name.
+///
+/// # Errors
+///
+/// Fails if the daemon can't be started, or the hostname isn't a valid service name.
+pub fn advertise(
+    addrs: &[SocketAddr],
+    name: &str,
+    tls: bool,
+    capabilities: &[Capability],
+) -> Result<ServiceDaemon, mdns_sd::Error> {
+    let hostname = crate::config::hostname().unwrap_or_else(|| "dark-remote".to_owned());
+    let host = format!("{hostname}.local.");
+
+    let caps = capabilities
+        .iter()
+        .map(|capability| capability.id())
+        .collect::<Vec<_>>()
+        .join(",");
+    let properties =
+        [("path", "/ws"), ("tls", if tls { "true" } else { "false" }), ("caps", &caps)];
+
+    let daemon = ServiceDaemon::new()?;
+    if addrs.iter().any(|addr| addr.ip().is_loopback()) {
+        // mostly useful for trying out discovery on one machine
+        daemon.enable_interface(IfKind::LoopbackV4)?;
+    }
+
+    let mut ports = addrs.iter().map(SocketAddr::port).collect::<Vec<_>>();
+    ports.sort_unstable();
+    ports.dedup();
+
+    for &port in &ports {
+        let ips = addrs
+            .iter()
+            .filter(|addr| addr.port() == port)
+            .map(SocketAddr::ip)
+            .collect::<Vec<_>>();
+        let instance = if ports.len() == 1 {
+            name.to_owned()
+        } else {
+            format!("{name} ({port})")
+        };
+
+        let service = if ips.iter().any(IpAddr::is_unspecified) {
+            ServiceInfo::new(MDNS_SERVICE_TYPE, &instance, &host, (), port, &properties[..])?
+                .enable_addr_auto()
+        } else {
+            ServiceInfo::new(MDNS_SERVICE_TYPE, &instance, &host, &ips[..], port, &properties[..])?
+        };
+
+        tracing::info!(name = service.get_fullname(), "advertising over mDNS");
+        daemon.register(service)?;
+    }
+
+    Ok(daemon)
+}
+
+#[cfg(test)]
+mod tests {
+    use core::time::Duration;
+    use std::{net::Ipv4Addr, time::Instant};
+
+    use mdns_sd::ServiceEvent;
+
+    use super::*;
+
+    /// Waits for services called `name`, until `count` of them were found.
+    fn browse(name: &str, count: usize) -> Vec<ServiceInfo> {
+        let browser = ServiceDaemon::new().unwrap();
+        browser.enable_interface(IfKind::LoopbackV4).unwrap();
+        let events = browser.browse(MDNS_SERVICE_TYPE).unwrap();
+
+        let deadline = Instant::now() + Duration::from_secs(10);
+        let mut found = Vec::new();
+        while found.len() < count {
+            let timeout = deadline.saturating_duration_since(Instant::now());
+            match events.recv_timeout(timeout) {
+                Ok(ServiceEvent::ServiceResolved(info))
+                    if info.get_fullname().starts_with(name)
+                        && !found
+                            .iter()
+                            .any(|f: &ServiceInfo| f.get_fullname() == info.get_fullname()) =>
+                {
+                    found.push(info);
+                }
+                Ok(_) => {}
+                Err(error) => panic!("found {} of {count} services: {error}", found.len()),
+            }
+        }
+
+        browser.shutdown().unwrap();
+        found
+    }
+
+    #[test]
+    fn loopback() {
+        let name = format!("dark-remote-test-{}", std::process::id());
+        let addr = SocketAddr::from((Ipv4Addr::LOCALHOST, 3999));
+        let daemon =
+            advertise(&[addr], &name, true, &[Capability::Input, Capability::Cec]).unwrap();
+
+        let [info] = browse(&name, 1).try_into().unwrap();
+        assert_eq!(info.get_fullname(), format!("{name}.{MDNS_SERVICE_TYPE}"));
+        assert_eq!(info.get_port(), 3999);
+        assert!(
+            info.get_addresses()
+                .contains(&IpAddr::from(Ipv4Addr::LOCALHOST))
+        );
+        assert_eq!(info.get_property_val_str("path"), Some("/ws"));
+        assert_eq!(info.get_property_val_str("tls"), Some("true"));
+        assert_eq!(info.get_property_val_str("caps"), Some("input,cec"));
+
+        daemon.shutdown().unwrap();
+    }
+
+    #[test]
+    fn every_port() {
+        let name = format!("dark-remote-ports-{}", std::process::id());
+        let addrs = [3997, 3998].map(|port| SocketAddr::from((Ipv4Addr::LOCALHOST, port)));
+        let daemon = advertise(&addrs, &name, false, &[]).unwrap();
+
+        let mut ports = browse(&name, 2)
+            .iter()
+            .map(ServiceInfo::get_port)
+            .collect::<Vec<_>>();
+        ports.sort_unstable();
+        assert_eq!(ports, [3997, 3998]);
+
+        daemon.shutdown().unwrap();
+    }
+}
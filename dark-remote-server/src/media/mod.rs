@@ -0,0 +1,75 @@
+//! Media players, through a pluggable [`MediaBackend`].
+
+pub use dark_remote_common::protocol::{Capability, MediaPlayer, NowPlaying, PlaybackStatus};
+use thiserror::Error;
+
+pub use self::{mpd::Mpd, mpris::Mpris};
+
+pub mod mpd;
+mod mpris;
+
+/// Something that controls one or more media players.
+///
+/// Every method that controls playback goes to the active player, which is the one picked with
+/// [`select_player`](Self::select_player), or else one the backend picks by itself.
+///
+/// # Errors
+///
+/// Every method fails if the player can't be reached, there is no player, or the player doesn't
+/// support what's asked.
+#[expect(clippy::missing_errors_doc)] // documented once, above
+pub trait MediaBackend: Send {
+    /// Which backend this is, to tell clients.
+    fn capability(&self) -> Capability;
+
+    /// The players that commands can be sent to.
+    fn players(&mut self) -> Result<Vec<MediaPlayer>, Error>;
+
+    /// The [`MediaPlayer::id`] that commands currently go to.
+    fn active_player(&mut self) -> Result<Option<String>, Error>;
+
+    /// Sends commands to `id` from now on, or lets the backend pick again with `None`.
+    fn select_player(&mut self, id: Option<String>) -> Result<(), Error>;
+
+    /// What the active player is doing, or `None` if there's no player.
+    fn now_playing(&mut self) -> Result<Option<NowPlaying>, Error>;
+
+    /// Pauses if playing, plays otherwise.
+    fn play_pause(&mut self) -> Result<(), Error>;
+
+    fn next_track(&mut self) -> Result<(), Error>;
+
+    fn previous_track(&mut self) -> Result<(), Error>;
+
+    fn stop(&mut self) -> Result<(), Error>;
+
+    /// Seeks relative to the current position.
+    fn seek(&mut self, seconds: i32) -> Result<(), Error>;
+
+    /// Sets the volume, from 0 to 100.
+    fn set_volume(&mut self, volume: u8) -> Result<(), Error>;
+
+    /// Changes the volume by `delta` percentage points.
+    fn change_volume(&mut self, delta: i8) -> Result<(), Error>;
+
+    fn toggle_shuffle(&mut self) -> Result<(), Error>;
+
+    fn toggle_repeat(&mut self) -> Result<(), Error>;
+
+    /// Replaces what's playing with the stored playlist `name`, and starts playing it.
+    fn load_playlist(&mut self, name: &str) -> Result<(), Error>;
+}
+
+#[derive(Error, Debug)]
+pub enum Error {
+    #[error(transparent)]
+    Mpd(#[from] mpd::Error),
+    #[error("D-Bus error: {0}")]
+    DBus(#[from] zbus::Error),
+    #[error("No media player is running")]
+    NoPlayer,
+    #[error("No media player called {0:?}")]
+    UnknownPlayer(String),
+    #[error("No playlist called {0:?}")]
+    UnknownPlaylist(String),
+}
@@ -0,0 +1,495 @@
+//! A small blocking client for the [MPD protocol](https://mpd.readthedocs.io/en/latest/protocol.html).
+
+use std::{
+    io::{self, BufRead as _, BufReader, Write as _},
+    net::{TcpStream, ToSocketAddrs as _},
+    os::unix::net::UnixStream,
+    path::PathBuf,
+    time::Duration,
+};
+
+use thiserror::Error;
+
+use super::{Capability, MediaBackend, MediaPlayer, NowPlaying, PlaybackStatus};
+
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Where MPD is listening.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Address {
+    Tcp { host: String, port: u16 },
+    Unix(PathBuf),
+}
+
+/// A connection to MPD that's opened on first use, and reopened if MPD closed it in the meantime.
+pub struct Mpd {
+    address: Address,
+    password: Option<String>,
+    connection: Option<Connection>,
+}
+
+struct Connection {
+    reader: BufReader<Box<dyn Stream>>,
+}
+
+trait Stream: io::Read + io::Write + Send {}
+impl<T: io::Read + io::Write + Send> Stream for T {}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PlayerState {
+    Play,
+    Pause,
+    Stop,
+}
+
+/// The parts of `status` we care about.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Status {
+    pub state: PlayerState,
+    /// `None` if there's no mixer.
+    pub volume: Option<u8>,
+    pub random: bool,
+    pub repeat: bool,
+}
+
+/// The parts of `currentsong` we care about.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Song {
+    pub title: Option<String>,
+    /// Songs can have more than one artist, these are joined with commas.
+    pub artist: Option<String>,
+    pub album: Option<String>,
+}
+
+#[derive(Error, Debug)]
+pub enum Error {
+    #[error("Failed to talk to MPD: {0}")]
+    Io(#[from] io::Error),
+    #[error("MPD refused {command:?}: {message}")]
+    Ack { command: String, message: String },
+    #[error("Unexpected response from MPD: {0:?}")]
+    Protocol(String),
+}
+
+impl Mpd {
+    /// Doesn't connect until the first command.
+    pub fn new(address: Address, password: Option<String>) -> Self {
+        Self { address, password, connection: None }
+    }
+
+    /// Connects now rather than on the first command, to find out early if MPD is reachable.
+    ///
+    /// # Errors
+    ///
+    /// Fails if MPD can't be reached or the password is wrong.
+    pub fn connect(&mut self) -> Result<(), Error> {
+        self.connection()?;
+        Ok(())
+    }
+
+    /// # Errors
+    ///
+    /// Fails if MPD can't be reached or returns something unexpected.
+    pub fn status(&mut self) -> Result<Status, Error> {
+        let mut state = None;
+        let mut volume = None;
+        let mut random = false;
+        let mut repeat = false;
+
+        for (key, value) in self.command("status", &[])? {
+            match key.as_str() {
+                "state" => {
+                    state = Some(match value.as_str() {
+                        "play" => PlayerState::Play,
+                        "pause" => PlayerState::Pause,
+                        "stop" => PlayerState::Stop,
+                        _ => return Err(Error::Protocol(format!("state: {value}"))),
+                    });
+                }
+                // -1 without a mixer
+                "volume" => volume = value.parse().ok(),
+                "random" => random = value == "1",
+                "repeat" => repeat = value == "1",
+                _ => {}
+            }
+        }
+
+        Ok(Status {
+            state: state.ok_or_else(|| Error::Protocol("status without state".to_owned()))?,
+            volume,
+            random,
+            repeat,
+        })
+    }
+
+    /// The song that's playing or paused, all `None` if there is none.
+    ///
+    /// # Errors
+    ///
+    /// Fails if MPD can't be reached or returns something unexpected.
+    pub fn current_song(&mut self) -> Result<Song, Error> {
+        let mut title = None;
+        let mut artists = Vec::new();
+        let mut album = None;
+
+        for (key, value) in self.command("currentsong", &[])? {
+            match key.as_str() {
+                "Title" => title = Some(value),
+                "Artist" => artists.push(value),
+                "Album" => album = Some(value),
+                _ => {}
+            }
+        }
+
+        Ok(Song {
+            title,
+            artist: (!artists.is_empty()).then(|| artists.join(", ")),
+            album,
+        })
+    }
+
+    /// Sends `command` and returns the response's key-value pairs, reconnecting once if the
+    /// connection was lost since the last command.
+    fn command(&mut self, command: &str, args: &[&str]) -> Result<Vec<(String, String)>, Error> {
+        let reused = self.connection.is_some();
+
+        match self.connection()?.command(command, args) {
+            Err(Error::Io(error)) if reused => {
+                tracing::debug!(?error, "MPD connection lost, reconnecting");
+                self.connection = None;
+                self.connection()?.command(command, args)
+            }
+            result => result,
+        }
+        .inspect_err(|error| {
+            if matches!(error, Error::Io(_) | Error::Protocol(_)) {
+                self.connection = None;
+            }
+        })
+    }
+
+    fn connection(&mut self) -> Result<&mut Connection, Error> {
+        let connection = match self.connection.take() {
+            Some(connection) => connection,
+            None => Connection::open(&self.address, self.password.as_deref())?,
+        };
+
+        Ok(self.connection.insert(connection))
+    }
+}
+
+/// MPD is the only player, as far as the rest of the server is concerned.
+const PLAYER_ID: &str = "mpd";
+
+impl MediaBackend for Mpd {
+    fn capability(&self) -> Capability {
+        Capability::Mpd
+    }
+
+    fn players(&mut self) -> Result<Vec<MediaPlayer>, super::Error> {
+        Ok(vec![MediaPlayer {
+            id: PLAYER_ID.to_owned(),
+            name: "MPD".to_owned(),
+        }])
+    }
+
+    fn active_player(&mut self) -> Result<Option<String>, super::Error> {
+        Ok(Some(PLAYER_ID.to_owned()))
+    }
+
+    fn select_player(&mut self, id: Option<String>) -> Result<(), super::Error> {
+        match id {
+            Some(id) if id != PLAYER_ID => Err(super::Error::UnknownPlayer(id)),
+            _ => Ok(()),
+        }
+    }
+
+    fn now_playing(&mut self) -> Result<Option<NowPlaying>, super::Error> {
+        let status = self.status()?;
+        let Song { title, artist, album } = self.current_song()?;
+
+        Ok(Some(NowPlaying {
+            player: PLAYER_ID.to_owned(),
+            status: match status.state {
+                PlayerState::Play => PlaybackStatus::Playing,
+                PlayerState::Pause => PlaybackStatus::Paused,
+                PlayerState::Stop => PlaybackStatus::Stopped,
+            },
+            title,
+            artist,
+            album,
+            volume: status.volume,
+        }))
+    }
+
+    fn play_pause(&mut self) -> Result<(), super::Error> {
+        if self.status()?.state == PlayerState::Play {
+            self.command("pause", &["1"])?;
+        } else {
+            self.command("play", &[])?;
+        }
+        Ok(())
+    }
+
+    fn next_track(&mut self) -> Result<(), super::Error> {
+        self.command("next", &[])?;
+        Ok(())
+    }
+
+    fn previous_track(&mut self) -> Result<(), super::Error> {
+        self.command("previous", &[])?;
+        Ok(())
+    }
+
+    fn stop(&mut self) -> Result<(), super::Error> {
+        self.command("stop", &[])?;
+        Ok(())
+    }
+
+    fn seek(&mut self, seconds: i32) -> Result<(), super::Error> {
+        self.command("seekcur", &[&format!("{seconds:+}")])?;
+        Ok(())
+    }
+
+    fn set_volume(&mut self, volume: u8) -> Result<(), super::Error> {
+        self.command("setvol", &[&volume.min(100).to_string()])?;
+        Ok(())
+    }
+
+    fn change_volume(&mut self, delta: i8) -> Result<(), super::Error> {
+        // `volume` is deprecated, and we can't know the current volume without a mixer anyway
+        let volume = self
+            .status()?
+            .volume
+            .ok_or_else(|| Error::Protocol("no volume in status".to_owned()))?;
+
+        self.set_volume(volume.saturating_add_signed(delta))
+    }
+
+    fn toggle_shuffle(&mut self) -> Result<(), super::Error> {
+        let random = self.status()?.random;
+        self.command("random", &[if random { "0" } else { "1" }])?;
+        Ok(())
+    }
+
+    fn toggle_repeat(&mut self) -> Result<(), super::Error> {
+        let repeat = self.status()?.repeat;
+        self.command("repeat", &[if repeat { "0" } else { "1" }])?;
+        Ok(())
+    }
+
+    fn load_playlist(&mut self, name: &str) -> Result<(), super::Error> {
+        // check that it exists first, so a typo doesn't clear the queue
+        self.command("listplaylist", &[name])?;
+        self.command("clear", &[])?;
+        self.command("load", &[name])?;
+        self.command("play", &[])?;
+        Ok(())
+    }
+}
+
+impl Connection {
+    fn open(address: &Address, password: Option<&str>) -> Result<Self, Error> {
+        let stream: Box<dyn Stream> = match address {
+            Address::Tcp { host, port } => {
+                let stream = connect_tcp(host, *port)?;
+                stream.set_read_timeout(Some(TIMEOUT))?;
+                stream.set_write_timeout(Some(TIMEOUT))?;
+                Box::new(stream)
+            }
+            Address::Unix(path) => {
+                let stream = UnixStream::connect(path)?;
+                stream.set_read_timeout(Some(TIMEOUT))?;
+                stream.set_write_timeout(Some(TIMEOUT))?;
+                Box::new(stream)
+            }
+        };
+
+        let mut connection = Self { reader: BufReader::new(stream) };
+
+        let greeting = connection.read_line()?;
+        let Some(version) = greeting.strip_prefix("OK MPD ") else {
+            return Err(Error::Protocol(greeting));
+        };
+        tracing::debug!(?address, version, "connected to MPD");
+
+        if let Some(password) = password {
+            connection.command("password", &[password])?;
+        }
+
+        Ok(connection)
+    }
+
+    fn command(&mut self, command: &str, args: &[&str]) -> Result<Vec<(String, String)>, Error> {
+        let mut line = command.to_owned();
+        for arg in args {
+            line.push_str(" \"");
+            line.push_str(&arg.replace('\\', "\\\\").replace('"', "\\\""));
+            line.push('"');
+        }
+        line.push('\n');
+
+        let stream = self.reader.get_mut();
+        stream.write_all(line.as_bytes())?;
+        stream.flush()?;
+
+        let mut pairs = Vec::new();
+        loop {
+            let line = self.read_line()?;
+            if line == "OK" {
+                return Ok(pairs);
+            }
+            if let Some(ack) = line.strip_prefix("ACK ") {
+                // ACK [error@command_listNum] {current_command} message_text
+                let message = ack.split_once("} ").map_or(ack, |(_, message)| message);
+                return Err(Error::Ack {
+                    command: command.to_owned(),
+                    message: message.to_owned(),
+                });
+            }
+
+            match line.split_once(": ") {
+                Some((key, value)) => pairs.push((key.to_owned(), value.to_owned())),
+                None => return Err(Error::Protocol(line)),
+            }
+        }
+    }
+
+    fn read_line(&mut self) -> Result<String, Error> {
+        let mut line = String::new();
+        if self.reader.read_line(&mut line)? == 0 {
+            return Err(io::Error::from(io::ErrorKind::UnexpectedEof).into());
+        }
+        line.truncate(line.trim_end_matches('\n').len());
+        Ok(line)
+    }
+}
+
+/// Connects to the first address `host` resolves to that answers within [`TIMEOUT`].
+fn connect_tcp(host: &str, port: u16) -> io::Result<TcpStream> {
+    let mut last_error = None;
+    for address in (host, port).to_socket_addrs()? {
+        match TcpStream::connect_timeout(&address, TIMEOUT) {
+            Ok(stream) => return Ok(stream),
+            Err(error) => last_error = Some(error),
+        }
+    }
+    Err(last_error.unwrap_or_else(|| io::Error::new(io::ErrorKind::NotFound, host.to_owned())))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{net::TcpListener, thread::JoinHandle};
+
+    use super::*;
+
+    /// A fake MPD that sends `greeting`, then answers each command with the next of `responses`,
+    /// and returns the commands it got.
+    fn serve(greeting: &str, responses: &[&str]) -> (Mpd, JoinHandle<Vec<String>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+
+        let greeting = format!("{greeting}\n");
+        let responses = responses.iter().map(|&r| r.to_owned()).collect::<Vec<_>>();
+        let server = std::thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            let mut reader = BufReader::new(stream);
+            reader.get_mut().write_all(greeting.as_bytes()).unwrap();
+
+            let mut commands = Vec::new();
+            for response in responses {
+                let mut line = String::new();
+                reader.read_line(&mut line).unwrap();
+                commands.push(line.trim_end().to_owned());
+                reader.get_mut().write_all(response.as_bytes()).unwrap();
+            }
+            commands
+        });
+
+        let address = Address::Tcp { host: "127.0.0.1".to_owned(), port };
+        (Mpd::new(address, None), server)
+    }
+
+    #[test]
+    fn status() {
+        let (mut mpd, server) = serve("OK MPD 0.23.5", &[
+            "volume: 42\nrepeat: 0\nrandom: 1\nsong: 3\nstate: play\nOK\n",
+            "volume: -1\nrepeat: 1\nrandom: 0\nstate: stop\nOK\n",
+        ]);
+
+        assert_eq!(mpd.status().unwrap(), Status {
+            state: PlayerState::Play,
+            volume: Some(42),
+            random: true,
+            repeat: false,
+        });
+        assert_eq!(mpd.status().unwrap(), Status {
+            state: PlayerState::Stop,
+            volume: None,
+            random: false,
+            repeat: true,
+        });
+        assert_eq!(server.join().unwrap(), ["status", "status"]);
+    }
+
+    #[test]
+    fn current_song() {
+        let (mut mpd, server) = serve("OK MPD 0.23.5", &[
+            "file: a.flac\nArtist: One\nArtist: Two\nTitle: Song\nOK\n",
+            "OK\n",
+        ]);
+
+        assert_eq!(mpd.current_song().unwrap(), Song {
+            title: Some("Song".to_owned()),
+            artist: Some("One, Two".to_owned()),
+            album: None,
+        });
+        assert_eq!(mpd.current_song().unwrap(), Song::default());
+        server.join().unwrap();
+    }
+
+    #[test]
+    fn ack() {
+        let (mut mpd, server) =
+            serve("OK MPD 0.23.5", &["ACK [50@0] {listplaylist} No such playlist\n"]);
+
+        let error = mpd.load_playlist("my \"mix\"").unwrap_err();
+        assert!(
+            matches!(
+                &error,
+                crate::media::Error::Mpd(Error::Ack { command, message })
+                    if command == "listplaylist" && message == "No such playlist"
+            ),
+            "{error:?}",
+        );
+        // nothing else is sent when the playlist doesn't exist
+        assert_eq!(server.join().unwrap(), [r#"listplaylist "my \"mix\"""#]);
+    }
+
+    #[test]
+    fn password() {
+        let (mpd, server) = serve("OK MPD 0.23.5", &["OK\n", "state: pause\nOK\n"]);
+        let mut mpd = Mpd::new(mpd.address, Some("secret".to_owned()));
+
+        assert_eq!(mpd.status().unwrap().state, PlayerState::Pause);
+        assert_eq!(server.join().unwrap(), [r#"password "secret""#, "status"]);
+    }
+
+    #[test]
+    fn not_mpd() {
+        let (mut mpd, server) = serve("SSH-2.0-OpenSSH_9.6", &[]);
+
+        assert!(
+            matches!(mpd.connect(), Err(Error::Protocol(greeting)) if greeting.starts_with("SSH"))
+        );
+        server.join().unwrap();
+    }
+
+    #[test]
+    fn unexpected_response() {
+        let (mut mpd, server) = serve("OK MPD 0.23.5", &["state: jumping\nOK\n"]);
+
+        assert!(matches!(mpd.status(), Err(Error::Protocol(_))));
+        server.join().unwrap();
+    }
+}
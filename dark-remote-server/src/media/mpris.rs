@@ -0,0 +1,323 @@
+use std::collections::HashMap;
+
+use zbus::{
+    blocking::{Connection, fdo::DBusProxy},
+    proxy,
+    proxy::CacheProperties,
+    zvariant::{OwnedObjectPath, OwnedValue},
+};
+
+use super::{Capability, Error, MediaBackend, MediaPlayer, NowPlaying, PlaybackStatus};
+
+const BUS_NAME_PREFIX: &str = "org.mpris.MediaPlayer2.";
+
+/// Any player on the session bus that implements [MPRIS](https://specifications.freedesktop.org/mpris-spec/latest/),
+/// like mpv, Firefox or Spotify.
+pub struct Mpris {
+    connection: Connection,
+    /// Bus name of the player picked by the user or the config file.
+    selected: Option<String>,
+    /// The player we last sent a command to, so pausing one player doesn't make the next
+    /// command go to another paused one.
+    last_used: Option<String>,
+}
+
+#[proxy(
+    interface = "org.mpris.MediaPlayer2",
+    default_path = "/org/mpris/MediaPlayer2",
+    gen_async = false
+)]
+trait MediaPlayer2 {
+    #[zbus(property)]
+    fn identity(&self) -> zbus::Result<String>;
+}
+
+#[proxy(
+    interface = "org.mpris.MediaPlayer2.Player",
+    default_path = "/org/mpris/MediaPlayer2",
+    gen_async = false
+)]
+trait Player {
+    fn play_pause(&self) -> zbus::Result<()>;
+    fn next(&self) -> zbus::Result<()>;
+    fn previous(&self) -> zbus::Result<()>;
+    fn stop(&self) -> zbus::Result<()>;
+    /// In microseconds.
+    fn seek(&self, offset: i64) -> zbus::Result<()>;
+
+    /// `Playing`, `Paused` or `Stopped`.
+    #[zbus(property)]
+    fn playback_status(&self) -> zbus::Result<String>;
+    /// `xesam:title` and friends.
+    #[zbus(property)]
+    fn metadata(&self) -> zbus::Result<HashMap<String, OwnedValue>>;
+    /// From 0.0 to 1.0.
+    #[zbus(property)]
+    fn volume(&self) -> zbus::Result<f64>;
+    #[zbus(property)]
+    fn set_volume(&self, value: f64) -> zbus::Result<()>;
+    #[zbus(property)]
+    fn shuffle(&self) -> zbus::Result<bool>;
+    #[zbus(property)]
+    fn set_shuffle(&self, value: bool) -> zbus::Result<()>;
+    /// `None`, `Track` or `Playlist`.
+    #[zbus(property)]
+    fn loop_status(&self) -> zbus::Result<String>;
+    #[zbus(property)]
+    fn set_loop_status(&self, value: &str) -> zbus::Result<()>;
+}
+
+#[proxy(
+    interface = "org.mpris.MediaPlayer2.Playlists",
+    default_path = "/org/mpris/MediaPlayer2",
+    gen_async = false
+)]
+trait Playlists {
+    /// Returns `(id, name, icon)` for each playlist.
+    fn get_playlists(
+        &self,
+        index: u32,
+        max_count: u32,
+        order: &str,
+        reverse_order: bool,
+    ) -> zbus::Result<Vec<(OwnedObjectPath, String, String)>>;
+    fn activate_playlist(&self, playlist_id: &OwnedObjectPath) -> zbus::Result<()>;
+}
+
+impl Mpris {
+    /// Connects to the session bus. `player` is a bus name like `org.mpris.MediaPlayer2.mpv`, or
+    /// just `mpv`.
+    ///
+    /// # Errors
+    ///
+    /// Fails if there's no session bus.
+    pub fn connect(player: Option<String>) -> Result<Self, Error> {
+        Ok(Self {
+            connection: Connection::session()?,
+            selected: player.map(|player| bus_name(&player)),
+            last_used: None,
+        })
+    }
+
+    /// Bus names of every MPRIS player, sorted.
+    fn bus_names(&self) -> Result<Vec<String>, Error> {
+        let mut names: Vec<String> = DBusProxy::new(&self.connection)?
+            .list_names()
+            .map_err(zbus::Error::from)?
+            .into_iter()
+            .map(|name| name.to_string())
+            .filter(|name| name.starts_with(BUS_NAME_PREFIX))
+            .collect();
+
+        names.sort();
+        Ok(names)
+    }
+
+    /// The selected player, or else the first one that's playing, the last one we used, or the
+    /// first one that's paused or running at all.
+    fn active(&self) -> Result<String, Error> {
+        let names = self.bus_names()?;
+
+        if let Some(selected) = &self.selected {
+            // players with more than one instance append something like `.instance123`
+            return names
+                .into_iter()
+                .find(|name| {
+                    name == selected
+                        || name
+                            .strip_prefix(selected.as_str())
+                            .is_some_and(|rest| rest.starts_with('.'))
+                })
+                .ok_or_else(|| Error::UnknownPlayer(selected.clone()));
+        }
+
+        let status = |name: &str| {
+            self.proxy::<PlayerProxy<'static>>(name)
+                .ok()
+                .and_then(|player| player.playback_status().ok())
+                .unwrap_or_default()
+        };
+
+        let mut last_used = None;
+        let mut paused = None;
+        let mut fallback = None;
+        for name in names {
+            match status(&name).as_str() {
+                "Playing" => return Ok(name),
+                _ if self.last_used.as_ref() == Some(&name) => last_used = Some(name),
+                "Paused" => {
+                    paused.get_or_insert(name);
+                }
+                _ => {
+                    fallback.get_or_insert(name);
+                }
+            }
+        }
+
+        last_used.or(paused).or(fallback).ok_or(Error::NoPlayer)
+    }
+
+    fn player(&mut self) -> Result<PlayerProxy<'static>, Error> {
+        let name = self.active()?;
+        let player = self.proxy(&name)?;
+        self.last_used = Some(name);
+        Ok(player)
+    }
+
+    fn proxy<P>(&self, name: &str) -> Result<P, Error>
+    where
+        P: From<zbus::Proxy<'static>> + zbus::proxy::Defaults,
+    {
+        Ok(zbus::blocking::proxy::Builder::<P>::new(&self.connection)
+            .destination(name.to_owned())?
+            .cache_properties(CacheProperties::No)
+            .build()?)
+    }
+}
+
+impl MediaBackend for Mpris {
+    fn capability(&self) -> Capability {
+        Capability::Mpris
+    }
+
+    fn players(&mut self) -> Result<Vec<MediaPlayer>, Error> {
+        self.bus_names()?
+            .into_iter()
+            .map(|id| {
+                let name = self
+                    .proxy::<MediaPlayer2Proxy<'static>>(&id)?
+                    .identity()
+                    .unwrap_or_else(|_| id.trim_start_matches(BUS_NAME_PREFIX).to_owned());
+
+                Ok(MediaPlayer { id, name })
+            })
+            .collect()
+    }
+
+    fn active_player(&mut self) -> Result<Option<String>, Error> {
+        match self.active() {
+            Ok(name) => Ok(Some(name)),
+            Err(Error::NoPlayer | Error::UnknownPlayer(_)) => Ok(None),
+            Err(error) => Err(error),
+        }
+    }
+
+    fn select_player(&mut self, id: Option<String>) -> Result<(), Error> {
+        let previous = std::mem::replace(&mut self.selected, id.as_deref().map(bus_name));
+
+        if let Err(error) = self.active() {
+            self.selected = previous;
+            return Err(error);
+        }
+        Ok(())
+    }
+
+    fn now_playing(&mut self) -> Result<Option<NowPlaying>, Error> {
+        let name = match self.active() {
+            Ok(name) => name,
+            Err(Error::NoPlayer | Error::UnknownPlayer(_)) => return Ok(None),
+            Err(error) => return Err(error),
+        };
+        let player = self.proxy::<PlayerProxy<'static>>(&name)?;
+
+        let status = match player.playback_status()?.as_str() {
+            "Playing" => PlaybackStatus::Playing,
+            "Paused" => PlaybackStatus::Paused,
+            _ => PlaybackStatus::Stopped,
+        };
+        // players that don't have metadata or volume aren't broken, just limited
+        let metadata = player.metadata().unwrap_or_default();
+        let artists = metadata_value::<Vec<String>>(&metadata, "xesam:artist")
+            .filter(|artists| !artists.is_empty());
+
+        Ok(Some(NowPlaying {
+            title: metadata_value(&metadata, "xesam:title"),
+            artist: artists.map(|artists| artists.join(", ")),
+            album: metadata_value(&metadata, "xesam:album"),
+            #[expect(clippy::cast_possible_truncation, clippy::cast_sign_loss)] // clamped
+            volume: player
+                .volume()
+                .ok()
+                .map(|volume| (volume.clamp(0., 1.) * 100.).round() as u8),
+            player: name,
+            status,
+        }))
+    }
+
+    fn play_pause(&mut self) -> Result<(), Error> {
+        Ok(self.player()?.play_pause()?)
+    }
+
+    fn next_track(&mut self) -> Result<(), Error> {
+        Ok(self.player()?.next()?)
+    }
+
+    fn previous_track(&mut self) -> Result<(), Error> {
+        Ok(self.player()?.previous()?)
+    }
+
+    fn stop(&mut self) -> Result<(), Error> {
+        Ok(self.player()?.stop()?)
+    }
+
+    fn seek(&mut self, seconds: i32) -> Result<(), Error> {
+        Ok(self.player()?.seek(i64::from(seconds) * 1_000_000)?)
+    }
+
+    fn set_volume(&mut self, volume: u8) -> Result<(), Error> {
+        Ok(self
+            .player()?
+            .set_volume(f64::from(volume.min(100)) / 100.)?)
+    }
+
+    fn change_volume(&mut self, delta: i8) -> Result<(), Error> {
+        let player = self.player()?;
+        let volume = player.volume()? + f64::from(delta) / 100.;
+        Ok(player.set_volume(volume.clamp(0., 1.))?)
+    }
+
+    fn toggle_shuffle(&mut self) -> Result<(), Error> {
+        let player = self.player()?;
+        Ok(player.set_shuffle(!player.shuffle()?)?)
+    }
+
+    fn toggle_repeat(&mut self) -> Result<(), Error> {
+        let player = self.player()?;
+        let loop_status = if player.loop_status()? == "None" {
+            "Playlist"
+        } else {
+            "None"
+        };
+        Ok(player.set_loop_status(loop_status)?)
+    }
+
+    fn load_playlist(&mut self, name: &str) -> Result<(), Error> {
+        let playlists = self.proxy::<PlaylistsProxy<'static>>(&self.active()?)?;
+
+        let (id, ..) = playlists
+            .get_playlists(0, u32::MAX, "Alphabetical", false)?
+            .into_iter()
+            .find(|(_, playlist, _)| playlist == name)
+            .ok_or_else(|| Error::UnknownPlaylist(name.to_owned()))?;
+
+        Ok(playlists.activate_playlist(&id)?)
+    }
+}
+
+/// `mpv` -> `org.mpris.MediaPlayer2.mpv`
+fn bus_name(player: &str) -> String {
+    if player.starts_with(BUS_NAME_PREFIX) {
+        player.to_owned()
+    } else {
+        format!("{BUS_NAME_PREFIX}{player}")
+    }
+}
+
+/// `None` if `key` is missing or isn't a `T`.
+fn metadata_value<T>(metadata: &HashMap<String, OwnedValue>, key: &str) -> Option<T>
+where
+    T: TryFrom<OwnedValue>,
+{
+    let value = metadata.get(key)?.try_clone().ok()?;
+    T::try_from(value).ok()
+}
@@ -0,0 +1,478 @@
+//! Pairing phones with the server, so only they can use `/ws`.
+//!
+//! The server prints a one-time code, and a QR code of it. A phone that connects with that code is
+//! given a token, which it uses for every connection after that. Paired devices are kept in a TOML file, which is read
+//! again for every connection so devices revoked from the command line are locked out right away.
+
+use core::time::Duration;
+use std::{
+    io,
+    path::{Path, PathBuf},
+    sync::{Mutex, PoisonError},
+    time::{Instant, SystemTime, UNIX_EPOCH},
+};
+
+use qrcode::{QrCode, render::unicode::Dense1x2};
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+/// Wrong codes allowed before the code is replaced.
+const MAX_ATTEMPTS: u32 = 5;
+
+/// How long pairing is locked after the first wrong code. Doubles with every wrong code after
+/// that, so guessing one of the million codes would take years.
+const LOCKOUT: Duration = Duration::from_secs(1);
+
+const MAX_LOCKOUT: Duration = Duration::from_secs(60 * 60);
+
+/// The paired devices, and the code to pair a new one.
+pub struct Pairing {
+    path: PathBuf,
+    code: Mutex<Code>,
+}
+
+struct Code {
+    code: String,
+    /// Wrong codes since this code was made.
+    failed_attempts: u32,
+    /// Wrong codes since a device was last paired, which this code may have taken over from the
+    /// one it replaced.
+    failures: u32,
+    /// Every code is refused until then, even the right one.
+    locked_until: Option<Instant>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Device {
+    /// Short and random, to refer to the device from the command line.
+    pub id: String,
+    /// The name the phone gave, or its user agent.
+    pub name: String,
+    pub token: String,
+    /// Seconds since the Unix epoch.
+    pub paired_at: u64,
+}
+
+/// A client that's allowed to connect.
+#[derive(Debug, Clone)]
+pub struct Authorized {
+    pub device: Device,
+    /// Whether the device was paired by this connection, and still needs its token.
+    pub new: bool,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct DevicesFile {
+    #[serde(default)]
+    devices: Vec<Device>,
+}
+
+#[derive(Error, Debug)]
+pub enum Error {
+    #[error("Failed to read {0:?}: {1}")]
+    Read(PathBuf, io::Error),
+    #[error("Failed to parse {0:?}: {1}")]
+    Parse(PathBuf, toml::de::Error),
+    #[error("Failed to write {0:?}: {1}")]
+    Write(PathBuf, io::Error),
+    #[error("Failed to generate a token: {0}")]
+    Random(#[from] getrandom::Error),
+    #[error("No paired device with ID or name {0:?}")]
+    UnknownDevice(String),
+    #[error("More than one paired device is called {0:?}, use its ID instead")]
+    AmbiguousDevice(String),
+}
+
+impl Pairing {
+    /// Uses the devices in `path`, and prints a new pairing code.
+    ///
+    /// # Errors
+    ///
+    /// Fails if no code could be generated.
+    pub fn new(path: PathBuf) -> Result<Self, Error> {
+        let code = Code::new()?;
+        Ok(Self { path, code: Mutex::new(code) })
+    }
+
+    /// Checks a token sent by a client. Returns `None` if it doesn't belong to a paired device.
+    ///
+    /// # Errors
+    ///
+    /// Fails if the devices file can't be read.
+    pub fn authenticate(&self, token: &str) -> Result<Option<Device>, Error> {
+        Ok(load(&self.path)?
+            .into_iter()
+            .find(|device| constant_time_eq(device.token.as_bytes(), token.as_bytes())))
+    }
+
+    /// Pairs a new device if `code` is the current pairing code, and replaces the code.
+    ///
+    /// Every wrong code locks pairing for twice as long as the one before, up to an hour. Codes
+    /// sent while it's locked are refused without being checked.
+    ///
+    /// # Errors
+    ///
+    /// Fails if the devices file can't be read or written.
+    pub fn pair(&self, code: &str, name: String) -> Result<Option<Device>, Error> {
+        self.pair_at(code, name, Instant::now())
+    }
+
+    fn pair_at(&self, code: &str, name: String, now: Instant) -> Result<Option<Device>, Error> {
+        let mut current = self.code.lock().unwrap_or_else(PoisonError::into_inner);
+
+        if let Some(locked_until) = current.locked_until
+            && now < locked_until
+        {
+            tracing::debug!(left = ?locked_until - now, "pairing is locked");
+            return Ok(None);
+        }
+
+        if !constant_time_eq(current.code.as_bytes(), code.as_bytes()) {
+            current.failed_attempts += 1;
+            current.failures += 1;
+
+            let lockout = LOCKOUT
+                .saturating_mul(1 << (current.failures - 1).min(31))
+                .min(MAX_LOCKOUT);
+            current.locked_until = Some(now + lockout);
+            tracing::warn!(failures = current.failures, ?lockout, "wrong pairing code");
+
+            if current.failed_attempts >= MAX_ATTEMPTS {
+                let Code { failures, locked_until, .. } = *current;
+                *current = Code {
+                    failures,
+                    locked_until,
+                    ..Code::new()?
+                };
+            }
+            return Ok(None);
+        }
+
+        let device = Device {
+            id: random_hex(4)?,
+            name,
+            token: random_hex(32)?,
+            paired_at: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map_or(0, |since| since.as_secs()),
+        };
+
+        let mut devices = load(&self.path)?;
+        devices.push(device.clone());
+        save(&self.path, &devices)?;
+
+        tracing::info!(id = device.id, name = device.name, "paired new device");
+        *current = Code::new()?;
+
+        Ok(Some(device))
+    }
+}
+
+impl Code {
+    fn new() -> Result<Self, Error> {
+        let mut bytes = [0; 4];
+        getrandom::fill(&mut bytes)?;
+        let code = format!("{:06}", u32::from_le_bytes(bytes) % 1_000_000);
+
+        show(&code);
+
+        Ok(Self {
+            code,
+            failed_attempts: 0,
+            failures: 0,
+            locked_until: None,
+        })
+    }
+}
+
+/// Prints the code to the terminal rather than logging it, so it's there whatever the log level is,
+/// and it doesn't end up in log files.
+#[expect(clippy::print_stdout)] // for whoever started the server
+fn show(code: &str) {
+    println!("Pairing code: {code}");
+
+    // light on dark, since most terminals are dark
+    if let Ok(qr) = QrCode::new(code) {
+        let qr = qr
+            .render::<Dense1x2>()
+            .dark_color(Dense1x2::Light)
+            .light_color(Dense1x2::Dark)
+            .build();
+        println!("{qr}");
+    }
+}
+
+/// Reads the paired devices from `path`. No file means no devices.
+///
+/// # Errors
+///
+/// Fails if the file exists but can't be read or parsed.
+pub fn load(path: &Path) -> Result<Vec<Device>, Error> {
+    let contents = match std::fs::read_to_string(path) {
+        Ok(contents) => contents,
+        Err(error) if error.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(error) => return Err(Error::Read(path.to_owned(), error)),
+    };
+
+    toml::from_str::<DevicesFile>(&contents)
+        .map(|file| file.devices)
+        .map_err(|error| Error::Parse(path.to_owned(), error))
+}
+
+/// Removes the device with ID or name `device` from `path`, and returns it.
+///
+/// # Errors
+///
+/// Fails if the file can't be read or written, or `device` doesn't match exactly one device.
+pub fn revoke(path: &Path, device: &str) -> Result<Device, Error> {
+    let mut devices = load(path)?;
+
+    let index = find(&devices, device)?;
+    let revoked = devices.remove(index);
+    save(path, &devices)?;
+    Ok(revoked)
+}
+
+/// The index of the device with ID `device`, or else of the only device named `device`.
+fn find(devices: &[Device], device: &str) -> Result<usize, Error> {
+    if let Some(index) = devices.iter().position(|d| d.id == device) {
+        return Ok(index);
+    }
+
+    let mut named = devices.iter().enumerate().filter(|(_, d)| d.name == device);
+
+    match (named.next(), named.next()) {
+        (Some((index, _)), None) => Ok(index),
+        (Some(_), Some(_)) => Err(Error::AmbiguousDevice(device.to_owned())),
+        (None, _) => Err(Error::UnknownDevice(device.to_owned())),
+    }
+}
+
+/// Only readable by us, since the tokens are as good as passwords.
+fn save(path: &Path, devices: &[Device]) -> Result<(), Error> {
+    use std::{io::Write as _, os::unix::fs::OpenOptionsExt as _};
+
+    let file = DevicesFile { devices: devices.to_vec() };
+    let contents = toml::to_string(&file).expect("Failed to serialize devices");
+    let write_error = |error| Error::Write(path.to_owned(), error);
+
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent).map_err(write_error)?;
+    }
+
+    std::fs::OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .mode(0o600)
+        .open(path)
+        .and_then(|mut file| file.write_all(contents.as_bytes()))
+        .map_err(write_error)
+}
+
+fn random_hex(len: usize) -> Result<String, Error> {
+    let mut bytes = vec![0; len];
+    getrandom::fill(&mut bytes)?;
+    Ok(bytes.iter().map(|byte| format!("{byte:02x}")).collect())
+}
+
+/// Doesn't give away how much of a token was right by how long it took.
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |acc, (a, b)| acc | (a ^ b)) == 0
+}
+
+#[cfg(test)]
+mod tests {
+    use std::os::unix::fs::PermissionsExt as _;
+
+    use super::*;
+
+    /// A devices file of its own for each test, which is gone afterwards.
+    struct TempFile(PathBuf);
+
+    impl TempFile {
+        fn new(name: &str) -> Self {
+            let path = std::env::temp_dir()
+                .join(format!("dark-remote-{}-{name}", std::process::id()))
+                .join("devices.toml");
+            Self(path)
+        }
+    }
+
+    impl Drop for TempFile {
+        fn drop(&mut self) {
+            if let Some(dir) = self.0.parent() {
+                std::fs::remove_dir_all(dir).ok();
+            }
+        }
+    }
+
+    fn code(pairing: &Pairing) -> String {
+        pairing.code.lock().unwrap().code.clone()
+    }
+
+    fn device(id: &str, name: &str) -> Device {
+        Device {
+            id: id.to_owned(),
+            name: name.to_owned(),
+            token: format!("token-{id}"),
+            paired_at: 0,
+        }
+    }
+
+    #[test]
+    fn pair_and_authenticate() {
+        let file = TempFile::new("pair");
+        let pairing = Pairing::new(file.0.clone()).unwrap();
+        let code = code(&pairing);
+
+        let device = pairing.pair(&code, String::from("phone")).unwrap().unwrap();
+        assert_eq!(device.name, "phone");
+        assert_eq!(device.token.len(), 64);
+
+        let found = pairing.authenticate(&device.token).unwrap().unwrap();
+        assert_eq!(found.id, device.id);
+        assert!(pairing.authenticate("nope").unwrap().is_none());
+        assert!(pairing.authenticate("").unwrap().is_none());
+
+        // only by us
+        let mode = std::fs::metadata(&file.0).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o600);
+    }
+
+    #[test]
+    fn code_is_used_up() {
+        let file = TempFile::new("used-up");
+        let pairing = Pairing::new(file.0.clone()).unwrap();
+        let code = code(&pairing);
+
+        assert!(pairing.pair(&code, String::from("a")).unwrap().is_some());
+        assert!(pairing.pair(&code, String::from("b")).unwrap().is_none());
+        assert_eq!(load(&file.0).unwrap().len(), 1);
+    }
+
+    #[test]
+    fn locked_after_wrong_code() {
+        let file = TempFile::new("locked");
+        let pairing = Pairing::new(file.0.clone()).unwrap();
+        let code = code(&pairing);
+        let now = Instant::now();
+
+        assert!(
+            pairing
+                .pair_at("wrong", String::new(), now)
+                .unwrap()
+                .is_none()
+        );
+        // even the right code is refused for a second
+        let name = String::from("phone");
+        assert!(pairing.pair_at(&code, name.clone(), now).unwrap().is_none());
+        assert!(
+            pairing
+                .pair_at(&code, name.clone(), now + LOCKOUT / 2)
+                .unwrap()
+                .is_none()
+        );
+        assert!(
+            pairing
+                .pair_at(&code, name, now + LOCKOUT)
+                .unwrap()
+                .is_some()
+        );
+    }
+
+    #[test]
+    fn lockout_doubles() {
+        let file = TempFile::new("doubles");
+        let pairing = Pairing::new(file.0.clone()).unwrap();
+        let mut now = Instant::now();
+
+        for expected in [1, 2, 4, 8] {
+            pairing.pair_at("wrong", String::new(), now).unwrap();
+            let locked_until = pairing.code.lock().unwrap().locked_until.unwrap();
+            assert_eq!(locked_until - now, Duration::from_secs(expected));
+            now = locked_until;
+        }
+    }
+
+    #[test]
+    fn lockout_is_capped() {
+        let file = TempFile::new("capped");
+        let pairing = Pairing::new(file.0.clone()).unwrap();
+        let mut now = Instant::now();
+
+        for _ in 0..40 {
+            pairing.pair_at("wrong", String::new(), now).unwrap();
+            now = pairing.code.lock().unwrap().locked_until.unwrap();
+        }
+        pairing.pair_at("wrong", String::new(), now).unwrap();
+        let locked_until = pairing.code.lock().unwrap().locked_until.unwrap();
+        assert_eq!(locked_until - now, MAX_LOCKOUT);
+    }
+
+    #[test]
+    fn replaced_after_failures() {
+        let file = TempFile::new("replaced");
+        let pairing = Pairing::new(file.0.clone()).unwrap();
+        let first = code(&pairing);
+        let mut now = Instant::now();
+
+        for _ in 0..MAX_ATTEMPTS {
+            pairing.pair_at("wrong", String::new(), now).unwrap();
+            now += MAX_LOCKOUT;
+        }
+
+        let current = pairing.code.lock().unwrap();
+        assert_eq!(current.failed_attempts, 0);
+        // a new code doesn't start with a clean slate
+        assert_eq!(current.failures, MAX_ATTEMPTS);
+        assert!(current.locked_until.is_some());
+        drop(current);
+
+        if code(&pairing) != first {
+            assert!(
+                pairing
+                    .pair_at(&first, String::new(), now)
+                    .unwrap()
+                    .is_none()
+            );
+        }
+    }
+
+    #[test]
+    fn revoke_by_id_or_name() {
+        let file = TempFile::new("revoke");
+        save(&file.0, &[device("a1", "phone"), device("b2", "tablet")]).unwrap();
+
+        assert_eq!(revoke(&file.0, "b2").unwrap().name, "tablet");
+        assert_eq!(revoke(&file.0, "phone").unwrap().id, "a1");
+        assert!(load(&file.0).unwrap().is_empty());
+        assert!(matches!(revoke(&file.0, "a1"), Err(Error::UnknownDevice(_))));
+    }
+
+    #[test]
+    fn revoked_device_is_locked_out() {
+        let file = TempFile::new("revoked");
+        save(&file.0, &[device("a1", "phone")]).unwrap();
+        let pairing = Pairing::new(file.0.clone()).unwrap();
+
+        assert!(pairing.authenticate("token-a1").unwrap().is_some());
+        revoke(&file.0, "a1").unwrap();
+        assert!(pairing.authenticate("token-a1").unwrap().is_none());
+    }
+
+    #[test]
+    fn find_prefers_id() {
+        let devices = [device("a1", "b2"), device("b2", "phone"), device("c3", "phone")];
+
+        assert_eq!(find(&devices, "b2").unwrap(), 1);
+        assert!(matches!(find(&devices, "phone"), Err(Error::AmbiguousDevice(_))));
+        assert!(matches!(find(&devices, "tv"), Err(Error::UnknownDevice(_))));
+    }
+
+    #[test]
+    fn no_file_no_devices() {
+        let file = TempFile::new("missing");
+        assert!(load(&file.0).unwrap().is_empty());
+    }
+}
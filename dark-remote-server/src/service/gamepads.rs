@@ -0,0 +1,95 @@
+use std::{collections::HashMap, net::SocketAddr, sync::mpsc};
+
+use dark_remote_common::{config::RemoteCommand, protocol::Subsystem};
+use dark_remote_server::gamepad::{self, Gamepad};
+
+use super::{Error, Message, Request};
+
+/// Owns each client's gamepad, made on its first gamepad command, and executes gamepad commands
+/// from all connections one at a time.
+///
+/// Runs on its own thread, apart from the service, so sticks and triggers don't queue up behind
+/// CEC frames, D-Bus calls or `pactl`.
+#[derive(Default)]
+struct Gamepads {
+    gamepads: HashMap<SocketAddr, Gamepad>,
+}
+
+/// Starts the gamepads on a new thread. Returns where to send their commands, or `None` if
+/// gamepads can't be made.
+pub fn spawn() -> Option<mpsc::Sender<Message>> {
+    gamepad::probe()
+        .inspect_err(|error| tracing::warn!(?error, "gamepads are unavailable"))
+        .ok()?;
+
+    let (messages, rx) = mpsc::channel::<Message>();
+
+    std::thread::Builder::new()
+        .name("gamepads".to_owned())
+        .spawn(move || {
+            let mut gamepads = Gamepads::default();
+            for message in rx {
+                match message {
+                    Message::Request(Request { client, command, reply }) => {
+                        reply
+                            .send(gamepads.execute(client, command).map(|()| None))
+                            .ok();
+                    }
+                    Message::Disconnect(client) => gamepads.disconnect(client),
+                }
+            }
+        })
+        .expect("failed to spawn gamepad thread");
+
+    Some(messages)
+}
+
+impl Gamepads {
+    fn execute(&mut self, client: SocketAddr, cmd: RemoteCommand) -> Result<(), Error> {
+        tracing::debug!(?client, ?cmd, "executing command");
+        match cmd {
+            RemoteCommand::GamepadPress { button } => self.gamepad(client)?.button(button, true)?,
+            RemoteCommand::GamepadRelease { button } => {
+                self.gamepad(client)?.button(button, false)?;
+            }
+            RemoteCommand::GamepadStick { stick, x, y } => {
+                self.gamepad(client)?.stick(stick, x, y)?;
+            }
+            RemoteCommand::GamepadTrigger { trigger, value } => {
+                self.gamepad(client)?.trigger(trigger, value)?;
+            }
+            // the service does these
+            cmd => return Err(Error::Unavailable(cmd.subsystem())),
+        }
+
+        Ok(())
+    }
+
+    /// The client's gamepad, which is made if it doesn't have one yet.
+    fn gamepad(&mut self, client: SocketAddr) -> Result<&mut Gamepad, Error> {
+        if !self.gamepads.contains_key(&client) {
+            // the lowest number that's free, so players keep theirs when someone else leaves
+            let player = (1..=u8::MAX)
+                .find(|&player| {
+                    self.gamepads
+                        .values()
+                        .all(|gamepad| gamepad.player() != player)
+                })
+                .ok_or(Error::Unavailable(Subsystem::Gamepad))?;
+
+            tracing::info!(?client, player, "creating gamepad");
+            self.gamepads.insert(client, Gamepad::new(player)?);
+        }
+
+        Ok(self
+            .gamepads
+            .get_mut(&client)
+            .expect("gamepad was just inserted"))
+    }
+
+    fn disconnect(&mut self, client: SocketAddr) {
+        if let Some(gamepad) = self.gamepads.remove(&client) {
+            tracing::info!(?client, player = gamepad.player(), "removing gamepad");
+        }
+    }
+}
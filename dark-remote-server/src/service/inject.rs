@@ -0,0 +1,106 @@
+use std::{net::SocketAddr, sync::mpsc};
+
+use dark_remote_common::config::{Modifier, RemoteCommand};
+use dark_remote_server::input::{self, Direction, Enigo, InputBackend, Uinput};
+use tokio::sync::oneshot;
+
+use super::{Error, Request};
+use crate::config::InputBackendKind;
+
+/// How much further apart the fingers have to get to zoom in by one Ctrl+scroll click.
+const ZOOM_PER_CLICK: f32 = 1.15;
+
+/// Owns the mouse and keyboard, and executes input commands from all connections one at a time.
+///
+/// Runs on its own thread, apart from the service, so pointer moves don't queue up behind CEC
+/// frames, D-Bus calls or `pactl`.
+struct Injector {
+    input: Box<dyn InputBackend>,
+    /// Fraction of a Ctrl+scroll click that wasn't zoomed yet, positive for zooming in.
+    zoom: f32,
+}
+
+/// Starts the injector on a new thread. Returns where to send its commands, or `None` if input
+/// injection is unavailable.
+pub async fn spawn(kind: InputBackendKind) -> Option<mpsc::Sender<Request>> {
+    let (requests, rx) = mpsc::channel::<Request>();
+    let (opened_tx, opened_rx) = oneshot::channel();
+
+    std::thread::Builder::new()
+        .name("input".to_owned())
+        .spawn(move || {
+            let input = open_input(kind)
+                .inspect_err(|error| tracing::warn!(?error, "input injection is unavailable"))
+                .ok();
+            opened_tx.send(input.is_some()).ok();
+
+            let Some(input) = input else {
+                return;
+            };
+            let mut injector = Injector { input, zoom: 0. };
+
+            for Request { client, command, reply } in rx {
+                reply
+                    .send(injector.execute(client, command).map(|()| None))
+                    .ok();
+            }
+        })
+        .expect("failed to spawn input thread");
+
+    opened_rx
+        .await
+        .expect("input thread stopped")
+        .then_some(requests)
+}
+
+impl Injector {
+    fn execute(&mut self, client: SocketAddr, cmd: RemoteCommand) -> Result<(), Error> {
+        tracing::debug!(?client, ?cmd, "executing command");
+        match cmd {
+            // already accelerated and smoothed out, in whole pixels
+            RemoteCommand::TrackpadMove { delta_x, delta_y, .. } => {
+                self.input
+                    .move_pointer(delta_x.round() as i32, delta_y.round() as i32)?;
+            }
+            RemoteCommand::TrackpadClick { button } => {
+                self.input.button(button, Direction::Click)?;
+            }
+            RemoteCommand::TrackpadPress { button } => {
+                self.input.button(button, Direction::Press)?;
+            }
+            RemoteCommand::TrackpadRelease { button } => {
+                self.input.button(button, Direction::Release)?;
+            }
+            RemoteCommand::TrackpadScroll { delta_x, delta_y } => {
+                self.input.scroll(delta_x, delta_y)?;
+            }
+            RemoteCommand::TrackpadZoom { factor } => {
+                let clicks = input::take_whole(&mut self.zoom, factor.ln() / ZOOM_PER_CLICK.ln());
+                if clicks != 0 {
+                    // scrolling up zooms in
+                    input::chord(self.input.as_mut(), &[Modifier::Control], |input| {
+                        input.scroll(0., -clicks as f32)
+                    })?;
+                }
+            }
+            RemoteCommand::KeyboardPress { key } => self.input.key(key, Direction::Click)?,
+            RemoteCommand::KeyboardChord { modifiers, key } => {
+                input::chord(self.input.as_mut(), &modifiers, |input| {
+                    input.key(key, Direction::Click)
+                })?;
+            }
+            RemoteCommand::KeyboardText { text } => self.input.text(&text)?,
+            // the service does these
+            cmd => return Err(Error::Unavailable(cmd.subsystem())),
+        }
+
+        Ok(())
+    }
+}
+
+fn open_input(kind: InputBackendKind) -> Result<Box<dyn InputBackend>, input::Error> {
+    Ok(match kind {
+        InputBackendKind::Enigo => Box::new(Enigo::new()?),
+        InputBackendKind::Uinput => Box::new(Uinput::new()?),
+    })
+}
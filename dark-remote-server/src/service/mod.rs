@@ -0,0 +1,692 @@
+use std::{
+    net::SocketAddr,
+    path::Path,
+    sync::{Arc, Mutex, MutexGuard, mpsc},
+};
+
+use dark_remote_common::{
+    config::RemoteCommand,
+    protocol::{Capability, CommandError, RemoteState, ServerMessage, Subsystem},
+};
+use dark_remote_server::{
+    audio::{self, AudioBackend, Pulse, SimulatedAudio, SimulatedSink},
+    cec::{self, Cec, CecBackend, LinuxCec, PhysicalAddress, SimulatedBus, SimulatedDevice},
+    input,
+    media::{self, MediaBackend, Mpd, Mpris},
+};
+use thiserror::Error;
+use tokio::sync::{mpsc::UnboundedSender, oneshot, watch};
+
+use self::{
+    pointer::Motion,
+    poll::{Poller, Shared, lock},
+};
+use crate::config::{
+    AudioBackendKind, CecBackendKind, CecConfig, ConfigFile, MediaBackendKind, MpdConfig,
+};
+
+mod gamepads;
+mod inject;
+mod pointer;
+mod poll;
+
+/// Owns the CEC device, and executes commands from all connections one at a time. Input and
+/// gamepad commands go to threads of their own instead.
+///
+/// Runs on its own thread, since most of the work is blocking ioctls and socket writes. The CEC,
+/// media and audio backends are shared with a [`Poller`] on another thread.
+struct Service {
+    cec: Option<Shared<Cec>>,
+    media: Option<Shared<Box<dyn MediaBackend>>>,
+    audio: Option<Shared<Box<dyn AudioBackend>>>,
+    state: watch::Sender<RemoteState>,
+}
+
+/// Cheap to clone, one per connection.
+#[derive(Clone)]
+pub struct ServiceHandle {
+    requests: mpsc::Sender<Request>,
+    /// Input commands go here instead. `None` if input injection is unavailable.
+    input: Option<mpsc::Sender<Request>>,
+    /// Gamepad commands go here instead. `None` if gamepads can't be made.
+    gamepads: Option<mpsc::Sender<Message>>,
+    /// Trackpad moves and buttons go here, to be smoothed out before they reach the input thread.
+    motion: UnboundedSender<Motion>,
+    capabilities: Vec<Capability>,
+    state: watch::Receiver<RemoteState>,
+}
+
+enum Message {
+    Request(Request),
+    /// A client went away, so its gamepad can be removed.
+    Disconnect(SocketAddr),
+}
+
+struct Request {
+    client: SocketAddr,
+    command: RemoteCommand,
+    reply: oneshot::Sender<Result<Option<ServerMessage>, Error>>,
+}
+
+impl ServiceHandle {
+    /// Starts the service on a new thread, and the input and gamepad threads and [`Poller`] on
+    /// others.
+    pub async fn spawn(config: &ConfigFile) -> Self {
+        let (requests, rx) = mpsc::channel::<Request>();
+        let (capabilities_tx, capabilities_rx) = oneshot::channel();
+        let (state_tx, state) = watch::channel(RemoteState::default());
+
+        let cec = open_cec(&config.cec);
+        let media = open_media(config);
+        let audio = open_audio(config.audio.backend);
+
+        std::thread::Builder::new()
+            .name("service".to_owned())
+            .spawn(move || {
+                let service = Service::new(cec, media, audio, state_tx);
+                capabilities_tx.send(service.capabilities()).ok();
+
+                let poller = service.poller();
+                std::thread::Builder::new()
+                    .name("poller".to_owned())
+                    .spawn(move || poller.run())
+                    .expect("failed to spawn poller thread");
+
+                for Request { client, command, reply } in rx {
+                    reply.send(service.execute(client, command)).ok();
+                }
+            })
+            .expect("failed to spawn service thread");
+
+        let mut capabilities = capabilities_rx.await.expect("service thread stopped");
+
+        let input = inject::spawn(config.input.backend).await;
+        if input.is_some() {
+            capabilities.insert(0, Capability::Input);
+        }
+
+        let gamepads = gamepads::spawn();
+        if gamepads.is_some() {
+            capabilities.push(Capability::Gamepad);
+        }
+
+        let (motion, motion_rx) = tokio::sync::mpsc::unbounded_channel();
+        if let Some(input) = &input {
+            tokio::spawn(pointer::run(motion_rx, input.clone()));
+        }
+
+        Self {
+            requests,
+            input,
+            gamepads,
+            motion,
+            capabilities,
+            state,
+        }
+    }
+
+    /// What started successfully.
+    pub fn capabilities(&self) -> &[Capability] {
+        &self.capabilities
+    }
+
+    /// The TV and media state, which changes whenever the service notices something changed.
+    pub fn state(&self) -> watch::Receiver<RemoteState> {
+        self.state.clone()
+    }
+
+    /// Lets go of whatever was kept for `client`, like its gamepad. Call this after its last
+    /// command.
+    pub fn disconnect(&self, client: SocketAddr) {
+        if let Some(gamepads) = &self.gamepads {
+            gamepads
+                .send(Message::Disconnect(client))
+                .expect("gamepad thread stopped");
+        }
+    }
+
+    /// Returns a message for the client if the command has a reply.
+    pub async fn execute(
+        &self,
+        client: SocketAddr,
+        command: RemoteCommand,
+    ) -> Result<Option<ServerMessage>, Error> {
+        let subsystem = command.subsystem();
+        let (input, gamepads) = match (subsystem, &self.input, &self.gamepads) {
+            (Subsystem::Input, None, _) | (Subsystem::Gamepad, _, None) => {
+                return Err(Error::Unavailable(subsystem));
+            }
+            (Subsystem::Input, input, _) => (input.as_ref(), None),
+            (Subsystem::Gamepad, _, gamepads) => (None, gamepads.as_ref()),
+            _ => (None, None),
+        };
+
+        if let RemoteCommand::TrackpadMove { delta_x, delta_y, .. } = command {
+            self.motion
+                .send(Motion::Move { client, delta_x, delta_y })
+                .expect("pointer motion task stopped");
+            return Ok(None);
+        }
+
+        let (reply, rx) = oneshot::channel();
+        let request = Request { client, command, reply };
+
+        match (&request.command, input, gamepads) {
+            // after the moves sent before them, so they happen where the pointer was going
+            (
+                RemoteCommand::TrackpadClick { .. }
+                | RemoteCommand::TrackpadPress { .. }
+                | RemoteCommand::TrackpadRelease { .. },
+                ..,
+            ) => self
+                .motion
+                .send(Motion::Button(request))
+                .expect("pointer motion task stopped"),
+            (_, Some(input), _) => input.send(request).expect("input thread stopped"),
+            (_, _, Some(gamepads)) => gamepads
+                .send(Message::Request(request))
+                .expect("gamepad thread stopped"),
+            (_, None, None) => self.requests.send(request).expect("service thread stopped"),
+        }
+
+        rx.await.expect("command was dropped")
+    }
+}
+
+impl Service {
+    fn new(
+        cec: Result<Cec, cec::Error>,
+        media: Result<Box<dyn MediaBackend>, media::Error>,
+        audio: Result<Box<dyn AudioBackend>, audio::Error>,
+        state: watch::Sender<RemoteState>,
+    ) -> Self {
+        let mut cec = cec
+            .inspect_err(|error| tracing::warn!(?error, "CEC is unavailable"))
+            .ok();
+
+        let media = media
+            .inspect_err(|error| tracing::warn!(?error, "media control is unavailable"))
+            .ok();
+
+        let audio = audio
+            .inspect_err(|error| tracing::warn!(?error, "volume control is unavailable"))
+            .ok();
+
+        if let Some(cec) = &mut cec {
+            cec.request_active_source()
+                .unwrap_or_else(|error| tracing::warn!(?error, "failed to request active source"));
+        }
+
+        Self {
+            cec: cec.map(|cec| Arc::new(Mutex::new(cec))),
+            media: media.map(|media| Arc::new(Mutex::new(media))),
+            audio: audio.map(|audio| Arc::new(Mutex::new(audio))),
+            state,
+        }
+    }
+
+    /// Keeps the state up to date with the same backends.
+    fn poller(&self) -> Poller {
+        Poller::new(self.cec.clone(), self.media.clone(), self.audio.clone(), self.state.clone())
+    }
+
+    fn capabilities(&self) -> Vec<Capability> {
+        let mut capabilities = Vec::new();
+        if self.cec.is_some() {
+            capabilities.push(Capability::Cec);
+        }
+        if self.audio.is_some() {
+            capabilities.push(Capability::Audio);
+        }
+        if let Some(media) = &self.media {
+            capabilities.push(lock(media).capability());
+        }
+        capabilities
+    }
+
+    fn cec(&self) -> Result<MutexGuard<'_, Cec>, Error> {
+        self.cec
+            .as_deref()
+            .map(lock)
+            .ok_or(Error::Unavailable(Subsystem::Cec))
+    }
+
+    fn media(&self) -> Result<MutexGuard<'_, Box<dyn MediaBackend>>, Error> {
+        self.media
+            .as_deref()
+            .map(lock)
+            .ok_or(Error::Unavailable(Subsystem::Media))
+    }
+
+    fn audio(&self) -> Result<MutexGuard<'_, Box<dyn AudioBackend>>, Error> {
+        self.audio
+            .as_deref()
+            .map(lock)
+            .ok_or(Error::Unavailable(Subsystem::Audio))
+    }
+
+    fn audio_sinks(&self) -> Result<ServerMessage, Error> {
+        let mut audio = self.audio()?;
+        Ok(ServerMessage::AudioSinks {
+            sinks: audio.sinks()?,
+            default: audio.default_sink()?,
+        })
+    }
+
+    fn media_players(&self) -> Result<ServerMessage, Error> {
+        let mut media = self.media()?;
+        Ok(ServerMessage::MediaPlayers {
+            players: media.players()?,
+            active: media.active_player()?,
+        })
+    }
+
+    /// So clients see the new volume right away, without waiting for the [`Poller`].
+    fn poll_audio(&self) {
+        if let Ok(mut audio) = self.audio() {
+            poll::update_audio(audio.as_mut(), &self.state);
+        }
+    }
+
+    /// Asks the TV whether it's on now. The [`Poller`] picks up the answer.
+    fn request_tv_power_status(&self) {
+        if let Ok(mut cec) = self.cec() {
+            poll::request_tv_power_status(&mut cec, &self.state);
+        }
+    }
+
+    /// Other devices don't hear their own announcements, and neither do we.
+    fn set_active_source(&self, physical_address: Option<PhysicalAddress>) {
+        self.state.send_if_modified(|state| {
+            let changed = state.active_source != physical_address;
+            state.active_source = physical_address;
+            changed
+        });
+    }
+
+    fn execute(
+        &self,
+        client: SocketAddr,
+        cmd: RemoteCommand,
+    ) -> Result<Option<ServerMessage>, Error> {
+        tracing::debug!(?client, ?cmd, "executing command");
+        let subsystem = cmd.subsystem();
+        match cmd {
+            // the input and gamepad threads do these
+            RemoteCommand::TrackpadMove { .. }
+            | RemoteCommand::TrackpadClick { .. }
+            | RemoteCommand::TrackpadPress { .. }
+            | RemoteCommand::TrackpadRelease { .. }
+            | RemoteCommand::TrackpadScroll { .. }
+            | RemoteCommand::TrackpadZoom { .. }
+            | RemoteCommand::KeyboardPress { .. }
+            | RemoteCommand::KeyboardChord { .. }
+            | RemoteCommand::KeyboardText { .. }
+            | RemoteCommand::GamepadPress { .. }
+            | RemoteCommand::GamepadRelease { .. }
+            | RemoteCommand::GamepadStick { .. }
+            | RemoteCommand::GamepadTrigger { .. } => return Err(Error::Unavailable(subsystem)),
+            RemoteCommand::CecImageViewOn => {
+                tracing::info!(?client, "sending CEC image view on command");
+                self.cec()?.image_view_on()?;
+                self.request_tv_power_status();
+            }
+            RemoteCommand::CecActiveSourceSelf => {
+                tracing::info!(?client, "setting active source to self");
+                let mut cec = self.cec()?;
+                cec.active_source_self()?;
+                let physical_address = cec.addresses().physical_address;
+                drop(cec);
+                self.set_active_source(physical_address);
+            }
+            RemoteCommand::CecActiveSource { physical_address } => {
+                tracing::info!(?client, ?physical_address, "setting active source");
+                self.cec()?.active_source(physical_address)?;
+                self.set_active_source(Some(physical_address));
+            }
+            RemoteCommand::CecStandby => {
+                tracing::info!(?client, "sending CEC standby command");
+                self.cec()?.standby()?;
+                self.request_tv_power_status();
+            }
+            RemoteCommand::MediaPlayPause => self.media()?.play_pause()?,
+            RemoteCommand::MediaNext => self.media()?.next_track()?,
+            RemoteCommand::MediaPrevious => self.media()?.previous_track()?,
+            RemoteCommand::MediaStop => self.media()?.stop()?,
+            RemoteCommand::MediaSeek { seconds } => self.media()?.seek(seconds)?,
+            RemoteCommand::MediaSetVolume { volume } => self.media()?.set_volume(volume)?,
+            RemoteCommand::MediaChangeVolume { delta } => self.media()?.change_volume(delta)?,
+            RemoteCommand::MediaToggleShuffle => self.media()?.toggle_shuffle()?,
+            RemoteCommand::MediaToggleRepeat => self.media()?.toggle_repeat()?,
+            RemoteCommand::MediaLoadPlaylist { name } => {
+                tracing::info!(?client, name, "loading playlist");
+                self.media()?.load_playlist(&name)?;
+            }
+            RemoteCommand::MediaListPlayers => return self.media_players().map(Some),
+            RemoteCommand::MediaSelectPlayer { player } => {
+                tracing::info!(?client, ?player, "selecting media player");
+                self.media()?.select_player(player)?;
+                return self.media_players().map(Some);
+            }
+            RemoteCommand::AudioSetVolume { volume } => self.audio()?.set_volume(volume)?,
+            RemoteCommand::AudioChangeVolume { delta } => self.audio()?.change_volume(delta)?,
+            RemoteCommand::AudioToggleMute => self.audio()?.toggle_mute()?,
+            RemoteCommand::AudioListSinks => return self.audio_sinks().map(Some),
+            RemoteCommand::AudioSetDefaultSink { sink } => {
+                tracing::info!(?client, ?sink, "setting default audio output");
+                self.audio()?.set_default_sink(&sink)?;
+                self.poll_audio();
+                return self.audio_sinks().map(Some);
+            }
+        }
+
+        if subsystem == Subsystem::Audio {
+            self.poll_audio();
+        }
+
+        Ok(None)
+    }
+}
+
+#[derive(Error, Debug)]
+pub enum Error {
+    #[error("{0} is not available")]
+    Unavailable(Subsystem),
+    #[error("Input error: {0}")]
+    Input(#[from] input::Error),
+    #[error("CEC error: {0}")]
+    Cec(#[from] cec::Error),
+    #[error("Media error: {0}")]
+    Media(#[from] media::Error),
+    #[error("Gamepad error: {0}")]
+    Gamepad(#[from] std::io::Error),
+    #[error("Audio error: {0}")]
+    Audio(#[from] audio::Error),
+}
+
+impl Error {
+    /// What to tell the client that sent the command.
+    pub fn to_client(&self) -> CommandError {
+        match self {
+            Self::Unavailable(subsystem) => CommandError::Unavailable(*subsystem),
+            Self::Input(error) => CommandError::Failed {
+                subsystem: Subsystem::Input,
+                message: error.to_string(),
+            },
+            Self::Cec(cec::Error::Nack(_)) => CommandError::CecNack,
+            Self::Cec(error) => CommandError::Failed {
+                subsystem: Subsystem::Cec,
+                message: error.to_string(),
+            },
+            Self::Media(error) => CommandError::Failed {
+                subsystem: Subsystem::Media,
+                message: error.to_string(),
+            },
+            Self::Gamepad(error) => CommandError::Failed {
+                subsystem: Subsystem::Gamepad,
+                message: error.to_string(),
+            },
+            Self::Audio(error) => CommandError::Failed {
+                subsystem: Subsystem::Audio,
+                message: error.to_string(),
+            },
+        }
+    }
+}
+
+fn open_cec(config: &CecConfig) -> Result<Cec, cec::Error> {
+    let backend: Box<dyn CecBackend> = match config.backend {
+        CecBackendKind::Linux => Box::new(LinuxCec::open(&config.device)?),
+        CecBackendKind::Simulated => Box::new(
+            SimulatedBus::new()
+                .with_device(SimulatedDevice::tv())
+                .with_device(SimulatedDevice::audio_system()),
+        ),
+    };
+
+    Cec::claim(backend, config.device_type, physical_address(config), &config.osd_name)
+}
+
+/// Our physical address from the config file, or else from the connector's EDID.
+fn physical_address(config: &CecConfig) -> Option<PhysicalAddress> {
+    if let Some(physical_address) = config.physical_address {
+        return Some(physical_address);
+    }
+
+    let path = Path::new("/sys/class/drm")
+        .join(config.connector.as_ref()?)
+        .join("edid");
+
+    let edid = std::fs::read(&path)
+        .inspect_err(|error| tracing::warn!(?path, ?error, "failed to read EDID"))
+        .ok()?;
+
+    let physical_address = cec::edid::physical_address(&edid);
+    if physical_address.is_none() {
+        tracing::warn!(?path, "EDID has no physical address, is the connector plugged in?");
+    }
+    physical_address
+}
+
+fn open_media(config: &ConfigFile) -> Result<Box<dyn MediaBackend>, media::Error> {
+    Ok(match config.media.backend {
+        MediaBackendKind::Mpris => Box::new(Mpris::connect(config.media.player.clone())?),
+        MediaBackendKind::Mpd => Box::new(open_mpd(&config.mpd)),
+    })
+}
+
+fn open_audio(kind: AudioBackendKind) -> Result<Box<dyn AudioBackend>, audio::Error> {
+    Ok(match kind {
+        AudioBackendKind::Pulse => Box::new(Pulse::connect()?),
+        AudioBackendKind::Simulated => Box::new(
+            SimulatedAudio::new()
+                .with_sink(SimulatedSink::new("speakers", "Speakers"))
+                .with_sink(SimulatedSink::new("hdmi", "HDMI")),
+        ),
+    })
+}
+
+/// MPD doesn't have to be running yet, we'll connect whenever a command comes in.
+fn open_mpd(config: &MpdConfig) -> Mpd {
+    let mut mpd = Mpd::new(config.address(), config.password.clone());
+    if let Err(error) = mpd.connect() {
+        tracing::warn!(?error, "failed to connect to MPD, will retry on the next command");
+    }
+    mpd
+}
+
+#[cfg(test)]
+mod tests {
+    use dark_remote_common::{
+        config::{GamepadButton, Key},
+        protocol::AudioSink,
+    };
+
+    use super::*;
+
+    const CLIENT: SocketAddr =
+        SocketAddr::new(std::net::IpAddr::V4(std::net::Ipv4Addr::LOCALHOST), 1);
+
+    /// A service with only simulated audio, and the audio to look at what it did.
+    fn service() -> (Service, SimulatedAudio, watch::Receiver<RemoteState>) {
+        service_with(
+            SimulatedAudio::new()
+                .with_sink(SimulatedSink::new("speakers", "Speakers"))
+                .with_sink(SimulatedSink::new("hdmi", "HDMI")),
+        )
+    }
+
+    fn service_with(
+        audio: SimulatedAudio,
+    ) -> (Service, SimulatedAudio, watch::Receiver<RemoteState>) {
+        let (state_tx, state) = watch::channel(RemoteState::default());
+
+        let service = Service::new(
+            Err(cec::Error::UnknownPhysicalAddress),
+            Err(media::Error::NoPlayer),
+            Ok(Box::new(audio.clone())),
+            state_tx,
+        );
+        (service, audio, state)
+    }
+
+    #[test]
+    fn capabilities() {
+        let (service, ..) = service();
+        assert!(service.capabilities().contains(&Capability::Audio));
+        assert!(!service.capabilities().contains(&Capability::Cec));
+    }
+
+    #[test]
+    fn set_volume() {
+        let (service, audio, state) = service();
+        service
+            .execute(CLIENT, RemoteCommand::AudioSetVolume { volume: 30 })
+            .unwrap();
+
+        assert_eq!(audio.sink("speakers").unwrap().volume, 30);
+        // clients see it without waiting for the poller
+        assert_eq!(state.borrow().audio.as_ref().unwrap().volume, 30);
+    }
+
+    #[test]
+    fn volume_is_capped() {
+        let (service, audio, state) = service();
+        service
+            .execute(CLIENT, RemoteCommand::AudioSetVolume { volume: 150 })
+            .unwrap();
+
+        assert_eq!(audio.sink("speakers").unwrap().volume, 100);
+        assert_eq!(state.borrow().audio.as_ref().unwrap().volume, 100);
+    }
+
+    #[test]
+    fn turned_down_from_past_the_cap() {
+        let loud = SimulatedSink {
+            volume: 150,
+            ..SimulatedSink::new("speakers", "Speakers")
+        };
+        let (service, audio, _) = service_with(SimulatedAudio::new().with_sink(loud));
+
+        // not straight down to 100
+        service
+            .execute(CLIENT, RemoteCommand::AudioChangeVolume { delta: -5 })
+            .unwrap();
+        assert_eq!(audio.sink("speakers").unwrap().volume, 145);
+
+        // nor back up past where it was
+        service
+            .execute(CLIENT, RemoteCommand::AudioChangeVolume { delta: 10 })
+            .unwrap();
+        assert_eq!(audio.sink("speakers").unwrap().volume, 145);
+    }
+
+    #[test]
+    fn change_volume() {
+        let (service, audio, state) = service();
+        for _ in 0..20 {
+            service
+                .execute(CLIENT, RemoteCommand::AudioChangeVolume { delta: 5 })
+                .unwrap();
+        }
+
+        assert_eq!(audio.sink("speakers").unwrap().volume, 100);
+        assert_eq!(state.borrow().audio.as_ref().unwrap().volume, 100);
+    }
+
+    #[test]
+    fn toggle_mute() {
+        let (service, audio, state) = service();
+        service
+            .execute(CLIENT, RemoteCommand::AudioToggleMute)
+            .unwrap();
+
+        assert!(audio.sink("speakers").unwrap().muted);
+        assert!(state.borrow().audio.as_ref().unwrap().muted);
+    }
+
+    #[test]
+    fn list_sinks() {
+        let (service, ..) = service();
+        let reply = service
+            .execute(CLIENT, RemoteCommand::AudioListSinks)
+            .unwrap();
+
+        let Some(ServerMessage::AudioSinks { sinks, default }) = reply else {
+            panic!("expected sinks, got {reply:?}");
+        };
+        assert_eq!(sinks, [
+            AudioSink {
+                id: String::from("speakers"),
+                name: String::from("Speakers")
+            },
+            AudioSink {
+                id: String::from("hdmi"),
+                name: String::from("HDMI")
+            },
+        ]);
+        assert_eq!(default.as_deref(), Some("speakers"));
+    }
+
+    #[test]
+    fn set_default_sink() {
+        let (service, audio, state) = service();
+        let reply = service
+            .execute(CLIENT, RemoteCommand::AudioSetDefaultSink { sink: String::from("hdmi") })
+            .unwrap();
+
+        assert!(matches!(
+            reply,
+            Some(ServerMessage::AudioSinks { default: Some(default), .. }) if default == "hdmi"
+        ));
+        assert_eq!(state.borrow().audio.as_ref().unwrap().sink, "hdmi");
+
+        // volume changes go to the new default
+        service
+            .execute(CLIENT, RemoteCommand::AudioSetVolume { volume: 10 })
+            .unwrap();
+        assert_eq!(audio.sink("hdmi").unwrap().volume, 10);
+        assert_eq!(audio.sink("speakers").unwrap().volume, 50);
+    }
+
+    #[test]
+    fn unknown_sink() {
+        let (service, ..) = service();
+        let error = service
+            .execute(CLIENT, RemoteCommand::AudioSetDefaultSink { sink: String::from("usb") })
+            .unwrap_err();
+
+        assert_eq!(error.to_client(), CommandError::Failed {
+            subsystem: Subsystem::Audio,
+            message: String::from("No audio output called \"usb\""),
+        });
+    }
+
+    #[test]
+    fn unavailable() {
+        let (service, ..) = service();
+        let error = service
+            .execute(CLIENT, RemoteCommand::CecStandby)
+            .unwrap_err();
+        assert_eq!(error.to_client(), CommandError::Unavailable(Subsystem::Cec));
+
+        let error = service
+            .execute(CLIENT, RemoteCommand::MediaPlayPause)
+            .unwrap_err();
+        assert_eq!(error.to_client(), CommandError::Unavailable(Subsystem::Media));
+    }
+
+    #[test]
+    fn input_and_gamepads_are_not_for_the_service() {
+        let (service, ..) = service();
+        let error = service
+            .execute(CLIENT, RemoteCommand::KeyboardPress { key: Key::Return })
+            .unwrap_err();
+        assert_eq!(error.to_client(), CommandError::Unavailable(Subsystem::Input));
+
+        let error = service
+            .execute(CLIENT, RemoteCommand::GamepadPress { button: GamepadButton::A })
+            .unwrap_err();
+        assert_eq!(error.to_client(), CommandError::Unavailable(Subsystem::Gamepad));
+    }
+}
@@ -0,0 +1,263 @@
+use core::time::Duration;
+use std::{net::SocketAddr, sync::mpsc};
+
+use dark_remote_common::config::RemoteCommand;
+use tokio::{
+    sync::{mpsc::UnboundedReceiver, oneshot},
+    time::{Instant, MissedTickBehavior},
+};
+
+use super::Request;
+
+/// How often the pointer moves while there's motion left to do.
+const TICK: Duration = Duration::from_micros(1_000_000 / 120);
+
+/// Most ticks a delta is spread over. Deltas further apart than this are from separate swipes,
+/// not from a slow client.
+const MAX_SPREAD: u32 = 6;
+
+/// How much each new gap between deltas counts towards the average.
+const INTERVAL_SMOOTHING: f32 = 0.2;
+
+pub enum Motion {
+    /// A trackpad move from a client, to be done bit by bit. In pixels, after acceleration.
+    Move {
+        client: SocketAddr,
+        delta_x: f32,
+        delta_y: f32,
+    },
+    /// A click, press or release, which is passed on to the input thread once the pointer got to
+    /// where the moves before it were going.
+    Button(Request),
+}
+
+/// Spreads deltas out over the time until the next one is expected, so the pointer glides
+/// instead of jumping whenever a frame comes in, even if they come in bursts.
+struct Smoother {
+    /// Motion that hasn't been done yet, in pixels. Fractions are kept for the next tick, so slow
+    /// movements aren't lost to rounding.
+    pending: (f32, f32),
+    /// Ticks left to spread [`Self::pending`] over.
+    ticks_left: u32,
+    /// Average time between deltas, in ticks.
+    interval: f32,
+    last_delta: Option<Instant>,
+}
+
+impl Smoother {
+    fn new() -> Self {
+        Self {
+            pending: (0., 0.),
+            ticks_left: 0,
+            interval: 1.,
+            last_delta: None,
+        }
+    }
+
+    fn push(&mut self, delta_x: f32, delta_y: f32, now: Instant) {
+        if let Some(last) = self.last_delta {
+            let gap = now.duration_since(last).as_secs_f32() / TICK.as_secs_f32();
+            if gap <= MAX_SPREAD as f32 {
+                self.interval += (gap - self.interval) * INTERVAL_SMOOTHING;
+            }
+        }
+        self.last_delta = Some(now);
+
+        self.pending.0 += delta_x;
+        self.pending.1 += delta_y;
+        self.ticks_left = (self.interval.round() as u32).clamp(1, MAX_SPREAD);
+    }
+
+    fn is_moving(&self) -> bool {
+        self.ticks_left > 0
+    }
+
+    /// Whole pixels to move right away, to do everything that's pending at once.
+    fn flush(&mut self) -> Option<(f32, f32)> {
+        self.ticks_left = 0;
+
+        let x = self.pending.0.round();
+        let y = self.pending.1.round();
+        self.pending.0 -= x;
+        self.pending.1 -= y;
+
+        (x != 0. || y != 0.).then_some((x, y))
+    }
+
+    /// Whole pixels to move this tick, if any.
+    fn tick(&mut self) -> Option<(f32, f32)> {
+        if self.ticks_left == 0 {
+            return None;
+        }
+
+        let ticks_left = self.ticks_left as f32;
+        let x = (self.pending.0 / ticks_left).round();
+        let y = (self.pending.1 / ticks_left).round();
+        self.pending.0 -= x;
+        self.pending.1 -= y;
+        self.ticks_left -= 1;
+
+        (x != 0. || y != 0.).then_some((x, y))
+    }
+}
+
+/// Moves the pointer until every sender of `motion` is gone.
+///
+/// The moves go through the input thread like any other input command, but connections don't wait
+/// for them, so they aren't held up by the interpolation. A slow command only makes the motion
+/// catch up in bigger steps.
+pub async fn run(mut motion: UnboundedReceiver<Motion>, requests: mpsc::Sender<Request>) {
+    let mut smoother = Smoother::new();
+    let mut client = None;
+
+    // after a pause the first tick is immediate, so a new swipe starts without delay
+    let mut ticks = tokio::time::interval(TICK);
+    ticks.set_missed_tick_behavior(MissedTickBehavior::Skip);
+
+    loop {
+        tokio::select! {
+            received = motion.recv() => match received {
+                Some(Motion::Move { client: from, delta_x, delta_y }) => {
+                    client = Some(from);
+                    smoother.push(delta_x, delta_y, Instant::now());
+                }
+                Some(Motion::Button(request)) => {
+                    if let (Some(client), Some((delta_x, delta_y))) = (client, smoother.flush())
+                        && !move_pointer(&requests, client, delta_x, delta_y).await
+                    {
+                        break;
+                    }
+                    if requests.send(request).is_err() {
+                        break;
+                    }
+                }
+                None => break,
+            },
+            _ = ticks.tick(), if smoother.is_moving() => {
+                if let (Some(client), Some((delta_x, delta_y))) = (client, smoother.tick())
+                    && !move_pointer(&requests, client, delta_x, delta_y).await
+                {
+                    break;
+                }
+            }
+        }
+    }
+}
+
+/// Returns `false` if the input thread stopped.
+async fn move_pointer(
+    requests: &mpsc::Sender<Request>,
+    client: SocketAddr,
+    delta_x: f32,
+    delta_y: f32,
+) -> bool {
+    let (reply, rx) = oneshot::channel();
+    let command = RemoteCommand::TrackpadMove { delta_x, delta_y, time: None };
+    if requests.send(Request { client, command, reply }).is_err() {
+        return false;
+    }
+
+    match rx.await {
+        Ok(Ok(_)) => true,
+        Ok(Err(error)) => {
+            tracing::debug!(?client, ?error, "failed to move the pointer");
+            true
+        }
+        Err(_) => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Pushes `delta_x` every `gap` ticks until the interval settles.
+    fn settled(gap: u32) -> (Smoother, Instant) {
+        let mut smoother = Smoother::new();
+        let mut now = Instant::now();
+        for _ in 0..50 {
+            smoother.push(1., 0., now);
+            smoother.flush();
+            now += TICK * gap;
+        }
+        (smoother, now)
+    }
+
+    fn ticks(smoother: &mut Smoother) -> Vec<(f32, f32)> {
+        let mut moves = Vec::new();
+        while smoother.is_moving() {
+            moves.extend(smoother.tick());
+        }
+        moves
+    }
+
+    #[test]
+    fn first_delta_is_immediate() {
+        let mut smoother = Smoother::new();
+        smoother.push(5., -3., Instant::now());
+        assert_eq!(ticks(&mut smoother), [(5., -3.)]);
+    }
+
+    #[test]
+    fn spread_over_interval() {
+        let (mut smoother, now) = settled(3);
+        smoother.push(9., -6., now);
+        assert_eq!(ticks(&mut smoother), [(3., -2.), (3., -2.), (3., -2.)]);
+    }
+
+    #[test]
+    fn uneven_spread_adds_up() {
+        let (mut smoother, now) = settled(3);
+        smoother.push(10., 0., now);
+        let moves = ticks(&mut smoother);
+        assert_eq!(moves.len(), 3);
+        assert_eq!(moves.iter().map(|(x, _)| x).sum::<f32>(), 10.);
+    }
+
+    #[test]
+    fn spread_is_capped() {
+        let (mut smoother, now) = settled(MAX_SPREAD * 2);
+        // gaps that long are separate swipes, and don't count
+        smoother.push(4., 0., now);
+        assert_eq!(ticks(&mut smoother), [(4., 0.)]);
+    }
+
+    #[test]
+    fn fractions_add_up() {
+        let mut smoother = Smoother::new();
+        let mut now = Instant::now();
+
+        smoother.push(0.4, 0., now);
+        assert_eq!(ticks(&mut smoother), []);
+
+        now += TICK;
+        smoother.push(0.4, 0., now);
+        assert_eq!(ticks(&mut smoother), [(1., 0.)]);
+        // overshot by 0.2, which the next move makes up for
+        now += TICK;
+        smoother.push(0.6, 0., now);
+        assert_eq!(ticks(&mut smoother), []);
+    }
+
+    #[test]
+    fn flush_moves_everything() {
+        let (mut smoother, now) = settled(4);
+        smoother.push(8.6, 2., now);
+        assert_eq!(smoother.tick(), Some((2., 1.)));
+        assert_eq!(smoother.flush(), Some((7., 1.)));
+        assert!(!smoother.is_moving());
+        assert_eq!(smoother.tick(), None);
+
+        // overshot by 0.4, which isn't forgotten
+        smoother.push(0.8, 0., now + TICK);
+        assert_eq!(smoother.flush(), None);
+        smoother.push(0.8, 0., now + TICK * 2);
+        assert_eq!(smoother.flush(), Some((1., 0.)));
+    }
+
+    #[test]
+    fn flush_without_motion() {
+        let mut smoother = Smoother::new();
+        assert_eq!(smoother.flush(), None);
+    }
+}
@@ -0,0 +1,170 @@
+use core::time::Duration;
+use std::{
+    sync::{Arc, Mutex, MutexGuard, PoisonError},
+    time::Instant,
+};
+
+use dark_remote_common::protocol::{PowerStatus, RemoteState};
+use dark_remote_server::{
+    audio::AudioBackend,
+    cec::{self, Cec, CecFrame, CecLogicalAddress, CecOpcode, CecPowerStatus},
+    media::MediaBackend,
+};
+use tokio::sync::watch;
+
+/// How often the TV, media player and volume are checked for changes.
+const POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+/// How often the TV is asked whether it's on. It tells us when it switches inputs by itself, but
+/// not when it turns on or off.
+const POWER_STATUS_INTERVAL: Duration = Duration::from_secs(10);
+
+/// A backend that both the service and the [`Poller`] use.
+pub type Shared<T> = Arc<Mutex<T>>;
+
+pub fn lock<T: ?Sized>(shared: &Mutex<T>) -> MutexGuard<'_, T> {
+    shared.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+/// Catches up on what happened on the CEC bus, in the media player and to the volume, and
+/// updates the state when something changed.
+///
+/// Runs on its own thread, since D-Bus calls and `pactl` can take a while, and commands shouldn't
+/// have to wait for them.
+pub struct Poller {
+    cec: Option<Shared<Cec>>,
+    media: Option<Shared<Box<dyn MediaBackend>>>,
+    audio: Option<Shared<Box<dyn AudioBackend>>>,
+    state: watch::Sender<RemoteState>,
+    last_power_request: Option<Instant>,
+}
+
+impl Poller {
+    pub fn new(
+        cec: Option<Shared<Cec>>,
+        media: Option<Shared<Box<dyn MediaBackend>>>,
+        audio: Option<Shared<Box<dyn AudioBackend>>>,
+        state: watch::Sender<RemoteState>,
+    ) -> Self {
+        Self {
+            cec,
+            media,
+            audio,
+            state,
+            last_power_request: None,
+        }
+    }
+
+    /// Polls until every client of the state is gone.
+    pub fn run(mut self) {
+        while !self.state.is_closed() {
+            self.poll();
+            std::thread::sleep(POLL_INTERVAL);
+        }
+    }
+
+    fn poll(&mut self) {
+        if let Some(cec) = &self.cec {
+            let mut cec = lock(cec);
+            loop {
+                match cec.receive(Duration::ZERO) {
+                    Ok(Some(frame)) => {
+                        tracing::debug!(?frame, "received CEC frame");
+                        self.state
+                            .send_if_modified(|state| update_cec_state(state, &frame));
+                    }
+                    Ok(None) => break,
+                    Err(error) => {
+                        tracing::warn!(?error, "failed to receive CEC frame");
+                        break;
+                    }
+                }
+            }
+
+            let now = Instant::now();
+            if self
+                .last_power_request
+                .is_none_or(|last| now.duration_since(last) >= POWER_STATUS_INTERVAL)
+            {
+                self.last_power_request = Some(now);
+                request_tv_power_status(&mut cec, &self.state);
+            }
+        }
+
+        if let Some(media) = &self.media {
+            let now_playing = lock(media).now_playing();
+            match now_playing {
+                Ok(now_playing) => {
+                    self.state.send_if_modified(|state| {
+                        let changed = state.now_playing != now_playing;
+                        state.now_playing = now_playing;
+                        changed
+                    });
+                }
+                Err(error) => tracing::debug!(?error, "failed to get what's playing"),
+            }
+        }
+
+        if let Some(audio) = &self.audio {
+            update_audio(lock(audio).as_mut(), &self.state);
+        }
+    }
+}
+
+/// Asks the TV whether it's on. The answer is picked up by the next poll.
+pub fn request_tv_power_status(cec: &mut Cec, state: &watch::Sender<RemoteState>) {
+    if let Err(error) = cec.request_power_status(CecLogicalAddress::Tv) {
+        tracing::debug!(?error, "failed to request TV power status");
+        state.send_if_modified(|state| {
+            let changed = state.tv_power != PowerStatus::Unknown;
+            state.tv_power = PowerStatus::Unknown;
+            changed
+        });
+    }
+}
+
+/// Gets the volume from `audio`, and updates `state` if it changed.
+pub fn update_audio(audio: &mut dyn AudioBackend, state: &watch::Sender<RemoteState>) {
+    match audio.state() {
+        Ok(audio) => {
+            state.send_if_modified(|state| {
+                let changed = state.audio != audio;
+                state.audio = audio;
+                changed
+            });
+        }
+        Err(error) => tracing::debug!(?error, "failed to get the volume"),
+    }
+}
+
+/// Updates `state` for a frame another device sent, returning whether anything changed.
+fn update_cec_state(state: &mut RemoteState, frame: &CecFrame) -> bool {
+    let old = state.clone();
+
+    match (frame.opcode, frame.parameters.as_slice()) {
+        (CecOpcode::ReportPowerStatus, &[power]) if frame.initiator == CecLogicalAddress::Tv => {
+            state.tv_power =
+                CecPowerStatus::try_from(power).map_or(PowerStatus::Unknown, power_status);
+        }
+        (CecOpcode::Standby, _) if frame.initiator == CecLogicalAddress::Tv => {
+            state.tv_power = PowerStatus::Standby;
+        }
+        // the new address comes after the old one for a routing change
+        (CecOpcode::ActiveSource | CecOpcode::SetStreamPath, &[ab, cd])
+        | (CecOpcode::RoutingChange, &[_, _, ab, cd]) => {
+            state.active_source = Some(cec::decode_physical_address([ab, cd]));
+        }
+        _ => {}
+    }
+
+    *state != old
+}
+
+fn power_status(power: CecPowerStatus) -> PowerStatus {
+    match power {
+        CecPowerStatus::On => PowerStatus::On,
+        CecPowerStatus::Standby => PowerStatus::Standby,
+        CecPowerStatus::InTransitionStandbyToOn => PowerStatus::TurningOn,
+        CecPowerStatus::InTransitionOnToStandby => PowerStatus::TurningOff,
+    }
+}
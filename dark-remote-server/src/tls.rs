@@ -0,0 +1,123 @@
+//! Serving HTTPS and WSS, with a configured certificate or a generated self-signed one.
+
+use std::{
+    io,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use rustls::pki_types::{CertificateDer, PrivateKeyDer, pem::PemObject as _};
+use thiserror::Error;
+
+use crate::config::TlsConfig;
+
+/// What's needed to serve TLS.
+pub struct Tls {
+    pub config: Arc<rustls::ServerConfig>,
+    /// SHA-256 of the certificate, like `AB:CD:…`, for clients to compare or pin.
+    pub fingerprint: String,
+}
+
+#[derive(Error, Debug)]
+pub enum Error {
+    #[error("Set both `cert` and `key`, or neither to use a self-signed certificate")]
+    Incomplete,
+    #[error("Failed to read {0:?}: {1}")]
+    Read(PathBuf, rustls::pki_types::pem::Error),
+    #[error("Failed to write {0:?}: {1}")]
+    Write(PathBuf, io::Error),
+    #[error("Failed to generate a certificate: {0}")]
+    Generate(#[from] rcgen::Error),
+    #[error("Invalid certificate or key: {0}")]
+    Rustls(#[from] rustls::Error),
+}
+
+impl Tls {
+    /// Loads the certificate from the config, or else the self-signed one in `data_dir`, which
+    /// is generated if it doesn't exist yet.
+    ///
+    /// # Errors
+    ///
+    /// Fails if a certificate or key can't be read, or doesn't work.
+    pub fn load(config: &TlsConfig, data_dir: &Path) -> Result<Self, Error> {
+        let (cert, key) = match (&config.cert, &config.key) {
+            (Some(cert), Some(key)) => (cert.clone(), key.clone()),
+            (None, None) => {
+                let cert = data_dir.join("cert.pem");
+                let key = data_dir.join("key.pem");
+                if !cert.exists() {
+                    generate(&cert, &key, &config.hostnames)?;
+                }
+                (cert, key)
+            }
+            _ => return Err(Error::Incomplete),
+        };
+
+        let chain = CertificateDer::pem_file_iter(&cert)
+            .and_then(Iterator::collect::<Result<Vec<_>, _>>)
+            .map_err(|error| Error::Read(cert.clone(), error))?;
+        let private_key =
+            PrivateKeyDer::from_pem_file(&key).map_err(|error| Error::Read(key.clone(), error))?;
+
+        let fingerprint = chain.first().map(fingerprint).unwrap_or_default();
+
+        let mut server_config = rustls::ServerConfig::builder_with_provider(Arc::new(
+            rustls::crypto::ring::default_provider(),
+        ))
+        .with_safe_default_protocol_versions()?
+        .with_no_client_auth()
+        .with_single_cert(chain, private_key)?;
+        // websockets need HTTP/1.1, and a remote doesn't gain anything from HTTP/2
+        server_config.alpn_protocols = vec![b"http/1.1".to_vec()];
+
+        Ok(Self {
+            config: Arc::new(server_config),
+            fingerprint,
+        })
+    }
+}
+
+/// Writes a new self-signed certificate for `hostnames` and this machine's hostname.
+fn generate(cert_path: &Path, key_path: &Path, hostnames: &[String]) -> Result<(), Error> {
+    use std::{io::Write as _, os::unix::fs::OpenOptionsExt as _};
+
+    let mut names = hostnames.to_vec();
+    if let Some(hostname) = crate::config::hostname() {
+        let local = format!("{hostname}.local");
+        names.extend([hostname, local]);
+    }
+    names.push("localhost".to_owned());
+    names.dedup();
+
+    tracing::info!(?cert_path, ?names, "generating self-signed certificate");
+    let certified = rcgen::generate_simple_self_signed(names)?;
+
+    let write = |path: &Path, contents: String, mode| {
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        std::fs::OpenOptions::new()
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .mode(mode)
+            .open(path)?
+            .write_all(contents.as_bytes())
+    };
+
+    write(key_path, certified.signing_key.serialize_pem(), 0o600)
+        .map_err(|error| Error::Write(key_path.to_owned(), error))?;
+    write(cert_path, certified.cert.pem(), 0o644)
+        .map_err(|error| Error::Write(cert_path.to_owned(), error))?;
+
+    Ok(())
+}
+
+fn fingerprint(cert: &CertificateDer<'_>) -> String {
+    ring::digest::digest(&ring::digest::SHA256, cert)
+        .as_ref()
+        .iter()
+        .map(|byte| format!("{byte:02X}"))
+        .collect::<Vec<_>>()
+        .join(":")
+}
@@ -1,10 +1,20 @@
-use dark_remote_common::config::{
-    Config, ConfigButton, ConfigPage, ConfigPageLayout, ConfigPanel, RemoteCommand, TrackpadButton,
+use dark_remote_common::{
+    config::{Config, ConfigPageLayout, ConfigPanel, RemoteCommand, Swipes},
+    protocol::{
+        Capability, PROTOCOL_VERSION, PlaybackStatus, RemoteState, ServerMessage, Subsystem,
+    },
 };
-use egui::{CentralPanel, Color32, Frame, Layout, Sense, TopBottomPanel, UiBuilder, Vec2};
+use egui::{CentralPanel, Color32, Frame, Layout, RichText, TopBottomPanel, UiBuilder};
 
+#[cfg(not(target_arch = "wasm32"))]
+use crate::discovery::Discovery;
 use crate::{
-    connection::Connection,
+    connection::{Connection, Event, State},
+    feedback::Feedback,
+    gamepad::GamepadPanel,
+    keyboard::KeyboardPanel,
+    servers::{SavedServer, Servers, Suggestion},
+    trackpad::TrackpadPanel,
     utils::{all_widget_visuals, layout::SplitEqual},
 };
 
@@ -13,110 +23,262 @@ use crate::{
 pub struct DarkRemoteApp {
     config: Config,
     current_page: String,
+    servers: Servers,
     #[serde(skip)]
     connection: Option<Connection>,
+    /// URL of the server we're connected to, without the query.
+    #[serde(skip)]
+    url: String,
+    /// Used instead of the token on the next connection, if set.
+    #[serde(skip)]
+    pairing_code: String,
+    /// Show the saved servers instead of the current page.
+    #[serde(skip)]
+    show_servers: bool,
     #[serde(skip)]
     message: String,
+    #[serde(skip)]
+    keyboard: KeyboardPanel,
+    trackpad: TrackpadPanel,
+    #[serde(skip)]
+    gamepad: GamepadPanel,
+    #[serde(skip)]
+    feedback: Feedback,
+    #[serde(skip)]
+    state: RemoteState,
+    /// What the server said it can do, or `None` before it said so.
+    #[serde(skip)]
+    capabilities: Option<Vec<Capability>>,
+    /// Servers found on the network, if mDNS could be started.
+    #[cfg(not(target_arch = "wasm32"))]
+    #[serde(skip)]
+    discovery: Option<Discovery>,
 }
 
 impl Default for DarkRemoteApp {
     fn default() -> Self {
         Self {
-            config: Config { pages: vec![] },
+            config: Config {
+                pages: vec![],
+                swipes: Swipes::default(),
+            },
             current_page: String::new(),
+            servers: Servers::default(),
             connection: None,
+            url: String::new(),
+            pairing_code: String::new(),
+            show_servers: false,
             message: String::new(),
+            keyboard: KeyboardPanel::default(),
+            trackpad: TrackpadPanel::default(),
+            gamepad: GamepadPanel::default(),
+            feedback: Feedback::default(),
+            state: RemoteState::default(),
+            capabilities: None,
+            #[cfg(not(target_arch = "wasm32"))]
+            discovery: None,
         }
     }
 }
 
 impl DarkRemoteApp {
     pub fn new(cc: &eframe::CreationContext<'_>) -> Self {
-        let mut this = cc
+        let mut app = cc
             .storage
             .and_then(|storage| eframe::get_value::<Self>(storage, eframe::APP_KEY))
             .unwrap_or_default();
 
-        this.config = Config {
-            pages: vec![ConfigPage {
-                name: "Test".to_owned(),
-                layout: ConfigPageLayout::Linear {
-                    panels: vec![
-                        (60, ConfigPanel::Trackpad),
-                        (
-                            40,
-                            ConfigPanel::ButtonGrid {
-                                rows: vec![
-                                    vec![
-                                        ConfigButton {
-                                            label: "Turn on".to_owned(),
-                                            command: RemoteCommand::CecImageViewOn,
-                                        },
-                                        ConfigButton {
-                                            label: "Switch".to_owned(),
-                                            command: RemoteCommand::CecActiveSourceSelf,
-                                        },
-                                        ConfigButton {
-                                            label: "Standby".to_owned(),
-                                            command: RemoteCommand::CecStandby,
-                                        },
-                                    ],
-                                    vec![
-                                        ConfigButton {
-                                            label: "HDMI 1".to_owned(),
-                                            command: RemoteCommand::CecActiveSource {
-                                                physical_address: [1, 0, 0, 0],
-                                            },
-                                        },
-                                        ConfigButton {
-                                            label: "HDMI 2".to_owned(),
-                                            command: RemoteCommand::CecActiveSource {
-                                                physical_address: [2, 0, 0, 0],
-                                            },
-                                        },
-                                        ConfigButton {
-                                            label: "HDMI 3".to_owned(),
-                                            command: RemoteCommand::CecActiveSource {
-                                                physical_address: [3, 0, 0, 0],
-                                            },
-                                        },
-                                        ConfigButton {
-                                            label: "HDMI 4".to_owned(),
-                                            command: RemoteCommand::CecActiveSource {
-                                                physical_address: [4, 0, 0, 0],
-                                            },
-                                        },
-                                    ],
-                                    vec![
-                                        ConfigButton {
-                                            label: "Prev".to_owned(),
-                                            command: RemoteCommand::MpdPrevious,
-                                        },
-                                        ConfigButton {
-                                            label: "Pause".to_owned(),
-                                            command: RemoteCommand::MpdPlayPause,
-                                        },
-                                        ConfigButton {
-                                            label: "Next".to_owned(),
-                                            command: RemoteCommand::MpdNext,
-                                        },
-                                    ],
-                                    vec![],
-                                ],
-                            },
-                        ),
-                    ],
-                },
-            }],
+        #[cfg(not(target_arch = "wasm32"))]
+        {
+            app.discovery = Discovery::start()
+                .inspect_err(|err| log::warn!("Failed to start mDNS discovery: {err}"))
+                .ok();
+        }
+
+        if let Some(server) = app.servers.auto_connect() {
+            let url = server.url.clone();
+            app.connect(&url, &cc.egui_ctx);
+        }
+
+        app
+    }
+
+    fn connect(&mut self, url: &str, ctx: &egui::Context) {
+        let code = std::mem::take(&mut self.pairing_code);
+        let query = if !code.trim().is_empty() {
+            // the web version is named after its user agent by the server
+            if cfg!(target_arch = "wasm32") {
+                format!("pair={}", code.trim())
+            } else {
+                format!("pair={}&name={}", code.trim(), std::env::consts::OS)
+            }
+        } else if let Some(server) = self.servers.get(url)
+            && !server.token.is_empty()
+        {
+            format!("token={}", server.token)
+        } else {
+            String::new()
         };
 
-        this
+        self.connection = Some(Connection::new(format!("{url}?{query}"), ctx));
+        self.url = url.to_owned();
+        self.servers.last_used = Some(url.to_owned());
+    }
+
+    /// `now` is in seconds, like [`egui::InputState::time`].
+    fn handle_event(&mut self, event: Event, now: f64) {
+        match event {
+            Event::Status(msg) => self.message = msg,
+            Event::State(State::Open) => {
+                // the server sends everything again, starting from nothing
+                self.state = RemoteState::default();
+                self.capabilities = None;
+                self.message.clear();
+            }
+            Event::State(State::Error(err)) => {
+                self.message = format!("Connection error: {err}");
+                self.feedback.clear();
+            }
+            Event::State(State::Rejected(reason)) => {
+                self.message = reason;
+                self.feedback.clear();
+            }
+            Event::State(State::Closed) => self.feedback.clear(),
+            Event::State(State::Connecting) => {}
+            Event::Message(ServerMessage::Welcome { version, name, capabilities, .. }) => {
+                if version == PROTOCOL_VERSION {
+                    self.message = format!("Connected to {name}");
+                } else {
+                    self.message = format!(
+                        "{name} speaks protocol version {version}, but this app speaks \
+                         {PROTOCOL_VERSION}. Update whichever is older."
+                    );
+                }
+                self.capabilities = Some(capabilities);
+            }
+            Event::Message(ServerMessage::Paired { id, token }) => {
+                self.message = format!("Paired as {id}");
+                if let Some(conn) = &mut self.connection {
+                    // the pairing code is used up
+                    conn.set_url(format!("{}?token={token}", self.url));
+                }
+                match self.servers.get_mut(&self.url) {
+                    Some(server) => server.token = token,
+                    // deleted while connecting
+                    None => self.servers.saved.push(SavedServer {
+                        name: self.url.clone(),
+                        url: self.url.clone(),
+                        token,
+                    }),
+                }
+            }
+            Event::Message(ServerMessage::Config(config)) => {
+                if !config.pages.iter().any(|p| p.name == self.current_page) {
+                    self.current_page = config
+                        .pages
+                        .first()
+                        .map(|p| p.name.clone())
+                        .unwrap_or_default();
+                }
+
+                self.config = config;
+            }
+            Event::Message(ServerMessage::Ack { id }) => self.feedback.replied(id, true, now),
+            Event::Message(ServerMessage::CommandFailed { id, error }) => {
+                self.message = format!("Failed: {error}");
+                if let Some(id) = id {
+                    self.feedback.replied(id, false, now);
+                }
+            }
+            Event::Message(ServerMessage::MediaPlayers { players, active }) => {
+                let players = players
+                    .iter()
+                    .map(|p| {
+                        let marker = if active.as_ref() == Some(&p.id) {
+                            "*"
+                        } else {
+                            ""
+                        };
+                        format!("{}{marker}", p.name)
+                    })
+                    .collect::<Vec<_>>();
+
+                self.message = format!("Players: {}", players.join(", "));
+            }
+            Event::Message(ServerMessage::AudioSinks { sinks, default }) => {
+                let sinks = sinks
+                    .iter()
+                    .map(|s| {
+                        let marker = if default.as_ref() == Some(&s.id) {
+                            "*"
+                        } else {
+                            ""
+                        };
+                        format!("{}{marker}", s.name)
+                    })
+                    .collect::<Vec<_>>();
+
+                self.message = format!("Outputs: {}", sinks.join(", "));
+            }
+            Event::Message(
+                msg @ (ServerMessage::TvPower(_)
+                | ServerMessage::ActiveSource(_)
+                | ServerMessage::NowPlaying(_)
+                | ServerMessage::Audio(_)),
+            ) => self.state.apply(&msg),
+        }
+    }
+
+    /// Servers found on the network.
+    #[cfg(not(target_arch = "wasm32"))]
+    fn suggestions(&self) -> Vec<Suggestion> {
+        let Some(discovery) = &self.discovery else {
+            return Vec::new();
+        };
+
+        discovery
+            .servers()
+            .map(|server| {
+                let capabilities = server
+                    .capabilities
+                    .iter()
+                    .map(ToString::to_string)
+                    .collect::<Vec<_>>();
+
+                Suggestion {
+                    server: SavedServer {
+                        name: server.name.clone(),
+                        url: server.url.clone(),
+                        token: String::new(),
+                    },
+                    details: format!("{}\nHas {}", server.url, capabilities.join(", ")),
+                }
+            })
+            .collect()
+    }
+
+    #[cfg(target_arch = "wasm32")]
+    #[expect(clippy::unused_self)] // there's no discovery on the web
+    fn suggestions(&self) -> Vec<Suggestion> {
+        Vec::new()
     }
 }
 
 impl eframe::App for DarkRemoteApp {
     #[expect(clippy::too_many_lines)] // TODO: later
     fn update(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
+        let now = ctx.input(|i| i.time);
+        while let Some(event) = self.connection.as_mut().and_then(Connection::poll) {
+            self.handle_event(event, now);
+        }
+
+        #[cfg(not(target_arch = "wasm32"))]
+        if let Some(discovery) = &mut self.discovery {
+            discovery.poll();
+            // servers are found in the background, keep checking
+            ctx.request_repaint_after(std::time::Duration::from_secs(1));
+        }
+
         ctx.all_styles_mut(|s| {
             s.visuals.panel_fill = if s.visuals.dark_mode {
                 Color32::BLACK
@@ -131,34 +293,32 @@ impl eframe::App for DarkRemoteApp {
         TopBottomPanel::bottom("page selection")
             .frame(frame)
             .show(ctx, |ui| {
+                if self.connection.is_some() {
+                    ui.horizontal(|ui| {
+                        ui.add_space(30.);
+                        ui.label(state_summary(&self.state));
+                    });
+                }
+
                 ui.horizontal(|ui| {
                     ui.add_space(30.);
 
-                    if ui.button("Tetsuya").clicked() {
-                        let res = Connection::new("ws://tetsuya.fbk.red:3000/ws");
-
-                        match res {
-                            Ok(conn) => self.connection = Some(conn),
-                            Err(err) => self.message = format!("Failed to connect: {err}"),
-                        }
+                    if let Some(conn) = &self.connection {
+                        connection_status(ui, conn);
                     }
 
-                    if ui.button("Sinon").clicked() {
-                        let res = Connection::new("ws://sinon.fbk.red:3000/ws");
+                    ui.toggle_value(&mut self.show_servers, "Servers");
 
-                        match res {
-                            Ok(conn) => self.connection = Some(conn),
-                            Err(err) => self.message = format!("Failed to connect: {err}"),
+                    let mut clicked = None;
+                    for server in &self.servers.saved {
+                        let current = self.connection.is_some() && server.url == self.url;
+                        if ui.selectable_label(current, &server.name).clicked() {
+                            clicked = Some(server.url.clone());
                         }
                     }
 
-                    if ui.button("Sinon secure").clicked() {
-                        let res = Connection::new("wss://dark-remote.sinon.fbk.red/ws");
-
-                        match res {
-                            Ok(conn) => self.connection = Some(conn),
-                            Err(err) => self.message = format!("Failed to connect: {err}"),
-                        }
+                    if let Some(url) = clicked {
+                        self.connect(&url, ctx);
                     }
 
                     for page in &self.config.pages {
@@ -167,9 +327,11 @@ impl eframe::App for DarkRemoteApp {
                         }
                     }
 
-                    if let Some(msg) = self.connection.as_mut().and_then(|c| c.check_msg()) {
-                        self.message = msg;
-                    }
+                    ui.add(
+                        egui::TextEdit::singleline(&mut self.pairing_code)
+                            .hint_text("Pairing code")
+                            .desired_width(100.),
+                    );
 
                     ui.label(&self.message);
                 });
@@ -183,6 +345,17 @@ impl eframe::App for DarkRemoteApp {
                 v.corner_radius = 16.0.into();
             });
 
+            if self.show_servers || self.connection.is_none() {
+                ui.add_space(16.);
+                ui.indent("servers", |ui| {
+                    if let Some(url) = self.servers.show(ui, &self.suggestions()) {
+                        self.connect(&url, ctx);
+                        self.show_servers = false;
+                    }
+                });
+                return;
+            }
+
             let Some(config_page) = self
                 .config
                 .pages
@@ -195,6 +368,15 @@ impl eframe::App for DarkRemoteApp {
 
             let ConfigPageLayout::Linear { panels } = &config_page.layout;
 
+            // commands would be dropped anyway
+            if self
+                .connection
+                .as_ref()
+                .is_some_and(|conn| *conn.state() != State::Open)
+            {
+                ui.disable();
+            }
+
             ui.with_layout(Layout::top_down(egui::Align::Center), |ui| {
                 let weight_sum: f32 = panels.iter().map(|(weight, _)| *weight as f32).sum();
 
@@ -208,40 +390,24 @@ impl eframe::App for DarkRemoteApp {
 
                     let res = {
                         ui.scope_builder(UiBuilder::new().max_rect(rect), |ui| {
+                            if matches!(panel, ConfigPanel::Trackpad | ConfigPanel::Keyboard)
+                                && !supports(self.capabilities.as_deref(), Subsystem::Input)
+                            {
+                                ui.disable();
+                            }
+                            if matches!(panel, ConfigPanel::Gamepad)
+                                && !supports(self.capabilities.as_deref(), Subsystem::Gamepad)
+                            {
+                                ui.disable();
+                            }
+
                             match panel {
                                 ConfigPanel::Trackpad => {
-                                    let rect = ui.available_rect_before_wrap().shrink(10.);
-
-                                    let response = ui.allocate_rect(rect, Sense::click_and_drag());
-
-                                    ui.painter().rect_filled(
-                                        rect,
-                                        50.,
-                                        ui.visuals().widgets.noninteractive.bg_fill,
+                                    self.trackpad.show(
+                                        ui,
+                                        self.connection.as_mut(),
+                                        &self.config.swipes,
                                     );
-                                    if let Some(conn) = &mut self.connection {
-                                        let delta = response.drag_delta();
-                                        if delta != Vec2::ZERO {
-                                            let sensitivity = (delta.length() / 10.).clamp(1., 5.);
-
-                                            conn.send(RemoteCommand::TrackpadMove {
-                                                delta_x: (delta.x * sensitivity).round() as i32,
-                                                delta_y: (delta.y * sensitivity).round() as i32,
-                                            });
-                                        }
-
-                                        if response.clicked() {
-                                            conn.send(RemoteCommand::TrackpadClick {
-                                                button: TrackpadButton::Left,
-                                            });
-                                        }
-
-                                        if response.long_touched() {
-                                            conn.send(RemoteCommand::TrackpadClick {
-                                                button: TrackpadButton::Right,
-                                            });
-                                        }
-                                    }
                                 }
                                 ConfigPanel::ButtonGrid { rows } => {
                                     SplitEqual::vertical().iterate(ui, rows, |ui, buttons| {
@@ -249,20 +415,42 @@ impl eframe::App for DarkRemoteApp {
                                             ui,
                                             buttons,
                                             |ui, button| {
-                                                let res = ui.place(
-                                                    ui.available_rect_before_wrap().shrink(8.),
-                                                    egui::Button::new(&button.label),
+                                                let enabled = supports(
+                                                    self.capabilities.as_deref(),
+                                                    button.command.subsystem(),
                                                 );
+                                                let res = ui
+                                                    .add_enabled_ui(enabled, |ui| {
+                                                        ui.place(
+                                                            ui.available_rect_before_wrap()
+                                                                .shrink(8.),
+                                                            egui::Button::new(&button.label)
+                                                                .selected(is_current(
+                                                                    &self.state,
+                                                                    &button.command,
+                                                                )),
+                                                        )
+                                                    })
+                                                    .inner;
 
                                                 if let Some(conn) = &mut self.connection
                                                     && res.clicked()
+                                                    && let Some(id) = conn.request(&button.command)
                                                 {
-                                                    conn.send(button.command);
+                                                    self.feedback.sent(id, res.id);
                                                 }
+
+                                                self.feedback.show(ui, &res);
                                             },
                                         );
                                     });
                                 }
+                                ConfigPanel::Keyboard => {
+                                    self.keyboard.show(ui, self.connection.as_mut());
+                                }
+                                ConfigPanel::Gamepad => {
+                                    self.gamepad.show(ui, self.connection.as_mut());
+                                }
                             }
 
                             ui.take_available_space();
@@ -279,3 +467,89 @@ impl eframe::App for DarkRemoteApp {
         eframe::set_value(storage, eframe::APP_KEY, self);
     }
 }
+
+/// Whether a server with `capabilities` can execute commands for `subsystem`. `None` means we
+/// don't know yet, and assume it can.
+fn supports(capabilities: Option<&[Capability]>, subsystem: Subsystem) -> bool {
+    capabilities.is_none_or(|capabilities| {
+        capabilities
+            .iter()
+            .any(|capability| capability.subsystem() == subsystem)
+    })
+}
+
+/// A coloured dot and what the connection is doing.
+fn connection_status(ui: &mut egui::Ui, conn: &Connection) {
+    let retry = match conn.retry_in() {
+        Some(secs) => {
+            // count down
+            ui.ctx().request_repaint_after_secs(1.);
+            format!(", retrying in {}s", secs.ceil().max(0.))
+        }
+        None => String::new(),
+    };
+
+    let (color, text) = match conn.state() {
+        State::Connecting => (Color32::YELLOW, "Connecting"),
+        State::Open => (Color32::GREEN, "Connected"),
+        State::Closed => (Color32::GRAY, "Closed"),
+        State::Error(_) => (Color32::RED, "Error"),
+        State::Rejected(_) => (Color32::RED, "Rejected"),
+    };
+
+    ui.label(RichText::new(format!("● {text}{retry}")).color(color));
+}
+
+/// A line like "TV on · 2.0.0.0 · ▶ Song – Artist · 40% · 🔊 25%".
+fn state_summary(state: &RemoteState) -> String {
+    let mut parts = vec![format!("TV {}", state.tv_power)];
+
+    if let Some([a, b, c, d]) = state.active_source {
+        parts.push(format!("{a}.{b}.{c}.{d}"));
+    }
+
+    if let Some(now_playing) = &state.now_playing {
+        let icon = match now_playing.status {
+            PlaybackStatus::Playing => "▶",
+            PlaybackStatus::Paused => "⏸",
+            PlaybackStatus::Stopped => "⏹",
+        };
+        let song = [&now_playing.title, &now_playing.artist]
+            .into_iter()
+            .flatten()
+            .map(String::as_str)
+            .collect::<Vec<_>>()
+            .join(" – ");
+        parts.push(format!("{icon} {song}").trim_end().to_owned());
+
+        if let Some(volume) = now_playing.volume {
+            parts.push(format!("{volume}%"));
+        }
+    }
+
+    if let Some(audio) = &state.audio {
+        let icon = if audio.muted { "🔇" } else { "🔊" };
+        parts.push(format!("{icon} {}%", audio.volume));
+    }
+
+    parts.join(" · ")
+}
+
+/// Whether `command` would put the server in the state it's already in, like the input the TV is
+/// already showing.
+fn is_current(state: &RemoteState, command: &RemoteCommand) -> bool {
+    match command {
+        RemoteCommand::CecActiveSource { physical_address } => {
+            state.active_source == Some(*physical_address)
+        }
+        RemoteCommand::AudioSetVolume { volume } => state
+            .audio
+            .as_ref()
+            .is_some_and(|audio| audio.volume == *volume),
+        RemoteCommand::AudioSetDefaultSink { sink } => state
+            .audio
+            .as_ref()
+            .is_some_and(|audio| audio.sink == *sink),
+        _ => false,
+    }
+}
@@ -1,30 +1,430 @@
-use dark_remote_common::config::RemoteCommand;
-use ewebsock::WsEvent;
+use dark_remote_common::{
+    config::RemoteCommand,
+    protocol::{Encoding, Hello, PROTOCOL_VERSION, Request, ServerMessage},
+};
+use ewebsock::{WsEvent, WsMessage};
 
+/// Seconds to wait before the first reconnect. Doubles with every failed attempt.
+const INITIAL_RETRY_DELAY: f64 = 1.;
+const MAX_RETRY_DELAY: f64 = 30.;
+
+/// A connection to a server, which reconnects whenever it's lost, unless the server turned it
+/// away.
 pub struct Connection {
-    sender: ewebsock::WsSender,
-    receiver: ewebsock::WsReceiver,
+    /// Repainted when something happens, and the source of time, since `std::time::Instant`
+    /// doesn't work on the web.
+    ctx: egui::Context,
+    /// `None` while waiting to reconnect, or after being rejected.
+    socket: Option<(ewebsock::WsSender, ewebsock::WsReceiver)>,
+    link: Link,
+    /// ID for the next request that wants a reply.
+    next_id: u32,
+}
+
+/// Everything about the connection but the socket: what state it's in, and when to reconnect.
+struct Link {
+    /// Including the query.
+    url: String,
+    state: State,
+    /// How commands are sent, as agreed on in the welcome.
+    encoding: Encoding,
+    /// Failed attempts since the connection was last open.
+    attempts: u32,
+    /// When to reconnect if the connection is closed, in seconds like [`egui::InputState::time`].
+    retry_at: f64,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum State {
+    Connecting,
+    /// Commands are only sent in this state, and dropped in any other.
+    Open,
+    /// The server closed the connection.
+    Closed,
+    /// Connecting failed, or the connection broke.
+    Error(String),
+    /// The server won't take this connection, so there's no point in reconnecting.
+    Rejected(String),
+}
+
+pub enum Event {
+    /// Something to show to the user.
+    Status(String),
+    /// The connection went from one [`State`] to this one.
+    State(State),
+    Message(ServerMessage),
 }
 
 impl Connection {
-    pub fn new(url: &str) -> Result<Self, ewebsock::Error> {
-        let options = ewebsock::Options::default();
-        let (sender, receiver) = ewebsock::connect(url, options)?;
+    pub fn new(url: String, ctx: &egui::Context) -> Self {
+        let mut conn = Self {
+            ctx: ctx.clone(),
+            socket: None,
+            link: Link::new(url),
+            next_id: 0,
+        };
+        conn.open();
+        conn
+    }
+
+    pub fn state(&self) -> &State {
+        &self.link.state
+    }
+
+    /// Seconds until the next reconnect, if one is planned.
+    pub fn retry_in(&self) -> Option<f64> {
+        self.link.retry_in(self.now())
+    }
+
+    /// Changes the URL used when reconnecting, like after pairing.
+    pub fn set_url(&mut self, url: String) {
+        self.link.url = url;
+    }
+
+    /// Returns the next event, after reconnecting if it's time to.
+    pub fn poll(&mut self) -> Option<Event> {
+        let now = self.now();
+        if self.link.retry_in(now).is_some_and(|secs| secs <= 0.) {
+            self.open();
+            return Some(Event::State(self.link.state.clone()));
+        }
+
+        let (_, receiver) = self.socket.as_ref()?;
+        let event = receiver.try_recv()?;
+        if matches!(event, WsEvent::Opened) {
+            self.say_hello();
+        }
+
+        let event = self.link.handle(event, now);
+        self.drop_socket_if_gone();
+        event
+    }
+
+    /// Sends `cmd` if the connection is open. Commands sent at any other time are dropped, since
+    /// replaying old input when the connection comes back would do more harm than good.
+    ///
+    /// The server only replies if the command fails.
+    pub fn send(&mut self, cmd: &RemoteCommand) {
+        self.send_request(&Request { id: None, command: cmd.clone() });
+    }
+
+    /// Like [`Self::send`], but the server replies either way. Returns the ID the reply will have,
+    /// or `None` if the command was dropped.
+    pub fn request(&mut self, cmd: &RemoteCommand) -> Option<u32> {
+        let id = self.next_id;
+        self.next_id = self.next_id.wrapping_add(1);
+
+        self.send_request(&Request { id: Some(id), command: cmd.clone() })
+            .then_some(id)
+    }
+
+    /// Returns whether the request was sent.
+    fn send_request(&mut self, request: &Request) -> bool {
+        let Some((sender, _)) = self
+            .socket
+            .as_mut()
+            .filter(|_| self.link.state == State::Open)
+        else {
+            log::warn!("Not connected, dropping {:?}", request.command);
+            return false;
+        };
+
+        sender.send(match self.link.encoding {
+            Encoding::Json => WsMessage::Text(
+                serde_json::to_string(request).expect("Failed to serialize Request"),
+            ),
+            Encoding::Postcard => WsMessage::Binary(
+                postcard::to_allocvec(request).expect("Failed to serialize Request"),
+            ),
+        });
+        true
+    }
+
+    /// Has to be the first message on every connection.
+    fn say_hello(&mut self) {
+        let platform = if cfg!(target_arch = "wasm32") {
+            "web"
+        } else {
+            std::env::consts::OS
+        };
+        let hello = Hello {
+            version: PROTOCOL_VERSION,
+            client: format!("dark-remote-ui {} ({platform})", env!("CARGO_PKG_VERSION")),
+            encodings: vec![Encoding::Postcard],
+        };
+
+        if let Some((sender, _)) = &mut self.socket {
+            let msg = serde_json::to_string(&hello).expect("Failed to serialize Hello");
+            sender.send(WsMessage::Text(msg));
+        }
+    }
+
+    fn open(&mut self) {
+        let ctx = self.ctx.clone();
+        let wake_up = move || ctx.request_repaint();
+
+        match ewebsock::connect_with_wakeup(&self.link.url, ewebsock::Options::default(), wake_up) {
+            Ok(socket) => {
+                self.socket = Some(socket);
+                self.link.opening();
+            }
+            Err(err) => {
+                self.link.lost(State::Error(err), self.now());
+                self.drop_socket_if_gone();
+            }
+        }
+    }
+
+    /// Lets go of the socket once the link is closed, and wakes up in time for the next attempt.
+    fn drop_socket_if_gone(&mut self) {
+        if matches!(self.link.state, State::Connecting | State::Open) {
+            return;
+        }
+
+        self.socket = None;
+        if let Some(delay) = self.retry_in() {
+            self.ctx.request_repaint_after_secs(delay as f32);
+        }
+    }
+
+    fn now(&self) -> f64 {
+        self.ctx.input(|i| i.time)
+    }
+}
+
+impl Link {
+    fn new(url: String) -> Self {
+        Self {
+            url,
+            state: State::Connecting,
+            encoding: Encoding::Json,
+            attempts: 0,
+            retry_at: 0.,
+        }
+    }
+
+    /// Seconds from `now` until the next reconnect, if one is planned.
+    fn retry_in(&self, now: f64) -> Option<f64> {
+        matches!(self.state, State::Closed | State::Error(_)).then(|| self.retry_at - now)
+    }
 
-        Ok(Self { sender, receiver })
+    /// A new socket is being opened.
+    fn opening(&mut self) {
+        self.state = State::Connecting;
+        self.encoding = Encoding::Json;
     }
 
-    pub fn check_msg(&self) -> Option<String> {
-        Some(match self.receiver.try_recv()? {
-            WsEvent::Opened => "Connection opened".to_owned(),
-            WsEvent::Message(_) => "Received a message".to_owned(),
-            WsEvent::Error(e) => format!("Connection error: {e}"),
-            WsEvent::Closed => None?,
+    /// Updates the state for an event from the socket, and returns what to tell the app.
+    fn handle(&mut self, event: WsEvent, now: f64) -> Option<Event> {
+        if matches!(self.state, State::Rejected(_)) {
+            return None;
+        }
+
+        Some(match event {
+            WsEvent::Opened => {
+                self.attempts = 0;
+                self.state = State::Open;
+                Event::State(State::Open)
+            }
+            WsEvent::Message(WsMessage::Text(text)) => {
+                match serde_json::from_str::<ServerMessage>(&text) {
+                    Ok(msg) => {
+                        if let ServerMessage::Welcome { version, encoding, .. } = msg {
+                            self.encoding = encoding;
+                            if version != PROTOCOL_VERSION {
+                                // the server hangs up next, and would again on every reconnect
+                                self.reject(format!("Server speaks protocol version {version}"));
+                            }
+                        }
+                        Event::Message(msg)
+                    }
+                    Err(e) => Event::Status(format!("Failed to parse message: {e}")),
+                }
+            }
+            WsEvent::Error(e) => {
+                if self.state == State::Connecting && self.is_refused(&e) {
+                    self.reject(if self.url.contains("pair=") {
+                        String::from("The pairing code was wrong. Pair again with the new one.")
+                    } else {
+                        String::from("The server doesn't know this device anymore. Pair again.")
+                    });
+                } else {
+                    self.lost(State::Error(e), now);
+                }
+                Event::State(self.state.clone())
+            }
+            WsEvent::Closed => {
+                self.lost(State::Closed, now);
+                Event::State(self.state.clone())
+            }
+            WsEvent::Message(_) => None?,
         })
     }
 
-    pub fn send(&mut self, cmd: RemoteCommand) {
-        let msg = serde_json::to_string(&cmd).expect("Failed to serialize RemoteCommand");
-        self.sender.send(ewebsock::WsMessage::Text(msg));
+    /// Plans the next attempt.
+    fn lost(&mut self, state: State, now: f64) {
+        let delay =
+            (INITIAL_RETRY_DELAY * 2_f64.powi(self.attempts.min(16) as i32)).min(MAX_RETRY_DELAY);
+        self.retry_at = now + delay;
+        self.attempts += 1;
+        self.state = state;
+    }
+
+    /// Gives up for good, and drops the query, since a pairing code only works once.
+    fn reject(&mut self, reason: String) {
+        self.state = State::Rejected(reason);
+        if let Some((url, _)) = self.url.split_once('?') {
+            self.url = url.to_owned();
+        }
+    }
+
+    /// Whether connecting failed because the server refused the token or pairing code, rather
+    /// than because it couldn't be reached.
+    fn is_refused(&self, error: &str) -> bool {
+        if cfg!(target_arch = "wasm32") {
+            // browsers don't say why a websocket failed to open, but a wrong code retried
+            // would only make the server pick another one
+            self.url.contains("pair=")
+        } else {
+            // like "Connect: HTTP error: 401 Unauthorized"
+            error.contains("401")
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use dark_remote_common::protocol::Capability;
+
+    use super::*;
+
+    fn welcome(version: u32, encoding: Encoding) -> WsEvent {
+        let welcome = ServerMessage::Welcome {
+            version,
+            name: String::from("tv"),
+            capabilities: vec![Capability::Input],
+            encoding,
+        };
+        WsEvent::Message(WsMessage::Text(serde_json::to_string(&welcome).unwrap()))
+    }
+
+    fn refused() -> WsEvent {
+        WsEvent::Error(String::from("Connect: HTTP error: 401 Unauthorized"))
+    }
+
+    fn unreachable() -> WsEvent {
+        WsEvent::Error(String::from("Connect: IO error: Connection refused (os error 111)"))
+    }
+
+    /// Fails to connect at `now`, and returns how long until the next attempt.
+    fn fail(link: &mut Link, now: f64) -> f64 {
+        link.opening();
+        link.handle(unreachable(), now);
+        link.retry_in(now).unwrap()
+    }
+
+    #[test]
+    fn backoff_grows() {
+        let mut link = Link::new(String::from("ws://tv/ws?token=abc"));
+        let delays = (0..8).map(|_| fail(&mut link, 0.)).collect::<Vec<_>>();
+        assert_eq!(delays, [1., 2., 4., 8., 16., 30., 30., 30.]);
+    }
+
+    #[test]
+    fn backoff_resets_once_open() {
+        let mut link = Link::new(String::from("ws://tv/ws"));
+        fail(&mut link, 0.);
+        fail(&mut link, 1.);
+        fail(&mut link, 3.);
+
+        link.opening();
+        link.handle(WsEvent::Opened, 7.);
+        link.handle(WsEvent::Closed, 100.);
+        assert_eq!(link.state, State::Closed);
+        assert_eq!(link.retry_in(100.), Some(1.));
+    }
+
+    #[test]
+    fn retry_counts_down() {
+        let mut link = Link::new(String::from("ws://tv/ws"));
+        fail(&mut link, 10.);
+        assert_eq!(link.retry_in(10.5), Some(0.5));
+        assert_eq!(link.retry_in(11.), Some(0.));
+    }
+
+    #[test]
+    fn no_retry_while_connected() {
+        let mut link = Link::new(String::from("ws://tv/ws"));
+        assert_eq!(link.retry_in(0.), None);
+        link.handle(WsEvent::Opened, 0.);
+        assert_eq!(link.retry_in(0.), None);
+    }
+
+    #[test]
+    fn back_to_connected() {
+        let mut link = Link::new(String::from("ws://tv/ws"));
+        link.handle(WsEvent::Opened, 0.);
+        link.handle(welcome(PROTOCOL_VERSION, Encoding::Postcard), 0.);
+        assert_eq!(link.encoding, Encoding::Postcard);
+
+        assert!(matches!(link.handle(unreachable(), 5.), Some(Event::State(State::Error(_)))));
+
+        // the encoding is agreed on again for the new connection
+        link.opening();
+        assert_eq!(link.state, State::Connecting);
+        assert_eq!(link.encoding, Encoding::Json);
+        assert!(matches!(link.handle(WsEvent::Opened, 6.), Some(Event::State(State::Open))));
+        assert_eq!(link.state, State::Open);
+    }
+
+    #[test]
+    fn welcome_from_another_version() {
+        let mut link = Link::new(String::from("ws://tv/ws?token=abc"));
+        link.handle(WsEvent::Opened, 0.);
+
+        assert!(matches!(
+            link.handle(welcome(PROTOCOL_VERSION + 1, Encoding::Json), 0.),
+            Some(Event::Message(ServerMessage::Welcome { .. }))
+        ));
+        assert!(matches!(link.state, State::Rejected(_)));
+        assert_eq!(link.retry_in(100.), None);
+
+        // the server hanging up after doesn't bring it back
+        assert!(link.handle(WsEvent::Closed, 0.).is_none());
+        assert!(matches!(link.state, State::Rejected(_)));
+    }
+
+    #[test]
+    fn wrong_pairing_code() {
+        let mut link = Link::new(String::from("ws://tv/ws?pair=123456&name=android"));
+        link.handle(refused(), 0.);
+
+        assert!(matches!(&link.state, State::Rejected(reason) if reason.contains("pairing code")));
+        assert_eq!(link.retry_in(100.), None);
+        assert_eq!(link.url, "ws://tv/ws");
+    }
+
+    #[test]
+    fn unknown_token() {
+        let mut link = Link::new(String::from("ws://tv/ws?token=abc"));
+        link.handle(refused(), 0.);
+        assert!(matches!(&link.state, State::Rejected(reason) if reason.contains("Pair again")));
+    }
+
+    #[test]
+    fn unreachable_server_is_retried() {
+        let mut link = Link::new(String::from("ws://tv/ws?pair=123456"));
+        link.handle(unreachable(), 0.);
+        assert!(matches!(link.state, State::Error(_)));
+        assert_eq!(link.url, "ws://tv/ws?pair=123456");
+    }
+
+    #[test]
+    fn broken_message() {
+        let mut link = Link::new(String::from("ws://tv/ws"));
+        link.handle(WsEvent::Opened, 0.);
+        let event = link.handle(WsEvent::Message(WsMessage::Text(String::from("{"))), 0.);
+        assert!(matches!(event, Some(Event::Status(_))));
+        assert_eq!(link.state, State::Open);
     }
 }
@@ -0,0 +1,110 @@
+//! Finding servers on the local network over mDNS. Not available on the web, which can't send
+//! multicast.
+//!
+//! Android drops multicast packets unless the app holds a `WifiManager.MulticastLock`, which is
+//! held for as long as discovery runs.
+
+use std::collections::BTreeMap;
+
+use dark_remote_common::protocol::{Capability, MDNS_SERVICE_TYPE};
+use mdns_sd::{IfKind, ServiceDaemon, ServiceEvent, ServiceInfo};
+
+pub struct Discovery {
+    events: mdns_sd::Receiver<ServiceEvent>,
+    /// By the service's full name.
+    servers: BTreeMap<String, DiscoveredServer>,
+    /// Kept so browsing doesn't stop.
+    _daemon: ServiceDaemon,
+    #[cfg(target_os = "android")]
+    _multicast_lock: Option<crate::multicast_lock::MulticastLock>,
+}
+
+#[derive(Debug, Clone)]
+pub struct DiscoveredServer {
+    /// The service's instance name, which is the server's name.
+    pub name: String,
+    /// Where to connect, without a query.
+    pub url: String,
+    pub capabilities: Vec<Capability>,
+}
+
+impl Discovery {
+    /// Starts browsing for servers in the background.
+    pub fn start() -> Result<Self, mdns_sd::Error> {
+        let daemon = ServiceDaemon::new()?;
+        // servers only listening on localhost advertise themselves there
+        daemon.enable_interface(IfKind::LoopbackV4)?;
+        let events = daemon.browse(MDNS_SERVICE_TYPE)?;
+
+        Ok(Self {
+            events,
+            servers: BTreeMap::new(),
+            _daemon: daemon,
+            #[cfg(target_os = "android")]
+            _multicast_lock: crate::multicast_lock::MulticastLock::acquire()
+                .inspect_err(|err| log::warn!("Failed to acquire a multicast lock: {err}"))
+                .ok(),
+        })
+    }
+
+    /// Handles what the daemon found since the last call.
+    pub fn poll(&mut self) {
+        while let Ok(event) = self.events.try_recv() {
+            match event {
+                ServiceEvent::ServiceResolved(info) => {
+                    if let Some(server) = DiscoveredServer::from_info(&info) {
+                        log::info!("discovered {} at {}", server.name, server.url);
+                        self.servers.insert(info.get_fullname().to_owned(), server);
+                    }
+                }
+                ServiceEvent::ServiceRemoved(_, fullname) => {
+                    self.servers.remove(&fullname);
+                }
+                _ => {}
+            }
+        }
+    }
+
+    pub fn servers(&self) -> impl Iterator<Item = &DiscoveredServer> {
+        self.servers.values()
+    }
+}
+
+impl DiscoveredServer {
+    fn from_info(info: &ServiceInfo) -> Option<Self> {
+        let name = info
+            .get_fullname()
+            .strip_suffix(MDNS_SERVICE_TYPE)?
+            .trim_end_matches('.')
+            .to_owned();
+        let path = info.get_property_val_str("path").unwrap_or("/ws");
+        let port = info.get_port();
+
+        let url = if info.get_property_val_str("tls") == Some("true") {
+            // the certificate is for the hostname, not the address
+            let host = info.get_hostname().trim_end_matches('.');
+            format!("wss://{host}:{port}{path}")
+        } else {
+            // prefer IPv4, since IPv6 link-local addresses need a scope to be usable
+            let addr = info
+                .get_addresses()
+                .iter()
+                .min_by_key(|addr| addr.is_ipv6())?;
+            let host = if addr.is_ipv6() {
+                format!("[{addr}]")
+            } else {
+                addr.to_string()
+            };
+            format!("ws://{host}:{port}{path}")
+        };
+
+        let capabilities = info
+            .get_property_val_str("caps")
+            .unwrap_or_default()
+            .split(',')
+            .filter_map(Capability::from_id)
+            .collect();
+
+        Some(Self { name, url, capabilities })
+    }
+}
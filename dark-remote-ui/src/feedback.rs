@@ -0,0 +1,67 @@
+use std::collections::HashMap;
+
+use egui::{Color32, Id, Response, Ui};
+
+/// Seconds a button stays green or red after the server replied.
+const FLASH_DURATION: f64 = 0.6;
+
+/// Flashes buttons green or red when the server replies to the command they sent.
+#[derive(Default)]
+pub struct Feedback {
+    /// Buttons waiting for a reply, by request ID.
+    pending: HashMap<u32, Id>,
+    flashes: HashMap<Id, Flash>,
+}
+
+struct Flash {
+    ok: bool,
+    /// In seconds, like [`egui::InputState::time`].
+    since: f64,
+}
+
+impl Feedback {
+    /// Remembers that `button` sent request `id`.
+    pub fn sent(&mut self, id: u32, button: Id) {
+        self.pending.insert(id, button);
+    }
+
+    /// Starts flashing the button that sent request `id`, if any.
+    pub fn replied(&mut self, id: u32, ok: bool, now: f64) {
+        if let Some(button) = self.pending.remove(&id) {
+            self.flashes.insert(button, Flash { ok, since: now });
+        }
+    }
+
+    /// Forgets requests that won't be replied to, because the connection they were sent on is
+    /// gone.
+    pub fn clear(&mut self) {
+        self.pending.clear();
+    }
+
+    /// Paints the flash over `button`, if it has one.
+    pub fn show(&mut self, ui: &Ui, button: &Response) {
+        let Some(flash) = self.flashes.get(&button.id) else {
+            return;
+        };
+
+        let age = ui.input(|i| i.time) - flash.since;
+        if age >= FLASH_DURATION {
+            self.flashes.remove(&button.id);
+            return;
+        }
+
+        let color = if flash.ok {
+            Color32::GREEN
+        } else {
+            Color32::RED
+        };
+        let fade = 1. - (age / FLASH_DURATION) as f32;
+
+        ui.painter().rect_filled(
+            button.rect,
+            ui.visuals().widgets.inactive.corner_radius,
+            color.gamma_multiply(0.6 * fade),
+        );
+        ui.ctx().request_repaint();
+    }
+}
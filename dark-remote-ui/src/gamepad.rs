@@ -0,0 +1,306 @@
+use std::collections::BTreeMap;
+
+use dark_remote_common::config::{GamepadButton, GamepadStick, GamepadTrigger, RemoteCommand};
+use egui::{
+    Align2, Event, FontId, PointerButton, Pos2, Rect, Sense, TouchId, TouchPhase, Ui, Vec2, pos2,
+};
+
+use crate::connection::Connection;
+
+/// Stands in for a finger when there's a mouse instead of a touch screen.
+const MOUSE: TouchId = TouchId(u64::MAX);
+
+/// How much a stick has to move, from -1 to 1, before it's sent again.
+const STICK_STEP: f32 = 0.02;
+
+/// The buttons in the row along the top, left to right.
+const TOP_ROW: [(&str, Control); 9] = [
+    ("LT", Control::Trigger(GamepadTrigger::Left)),
+    ("LB", Control::Button(GamepadButton::LeftBumper)),
+    ("LS", Control::Button(GamepadButton::LeftStick)),
+    ("Back", Control::Button(GamepadButton::Back)),
+    ("Guide", Control::Button(GamepadButton::Guide)),
+    ("Start", Control::Button(GamepadButton::Start)),
+    ("RS", Control::Button(GamepadButton::RightStick)),
+    ("RB", Control::Button(GamepadButton::RightBumper)),
+    ("RT", Control::Trigger(GamepadTrigger::Right)),
+];
+
+/// An Xbox-style controller: the left stick above the d-pad, the face buttons above the right
+/// stick, and bumpers, triggers and the middle buttons along the top.
+///
+/// Every finger is followed on its own, so a stick can be held while pressing buttons.
+#[derive(Default)]
+pub struct GamepadPanel {
+    /// What each finger on the panel is holding.
+    touches: BTreeMap<TouchId, Control>,
+    /// Where the sticks were last sent as being, left and right.
+    sticks: [Vec2; 2],
+    /// Set once there are touch events, after which pointer events are ignored, since egui makes
+    /// those up from the first finger too.
+    touch_screen: bool,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Control {
+    Button(GamepadButton),
+    /// Pulled all the way while held.
+    Trigger(GamepadTrigger),
+    Stick(GamepadStick),
+}
+
+/// Where the controls are on the panel.
+struct Layout {
+    buttons: Vec<(Rect, &'static str, Control)>,
+    /// Centers of the left and right sticks.
+    sticks: [Pos2; 2],
+    /// How far a stick can be pushed from its center, in points.
+    stick_radius: f32,
+}
+
+impl GamepadPanel {
+    pub fn show(&mut self, ui: &mut Ui, mut connection: Option<&mut Connection>) {
+        let rect = ui.available_rect_before_wrap().shrink(8.);
+        ui.allocate_rect(rect, Sense::click_and_drag());
+        let layout = Layout::new(rect);
+
+        if ui.is_enabled() {
+            for (id, phase, pos) in self.touches(ui) {
+                self.touch(connection.as_deref_mut(), &layout, id, phase, pos);
+            }
+        } else {
+            // nothing would tell us when the fingers are lifted
+            for id in self.touches.keys().copied().collect::<Vec<_>>() {
+                self.touch(
+                    connection.as_deref_mut(),
+                    &layout,
+                    id,
+                    TouchPhase::Cancel,
+                    rect.center(),
+                );
+            }
+        }
+
+        self.paint(ui, &layout);
+    }
+
+    /// This frame's touches, or mouse presses and drags as a touch without a touch screen.
+    fn touches(&mut self, ui: &Ui) -> Vec<(TouchId, TouchPhase, Pos2)> {
+        let events = ui.input(|i| i.events.clone());
+
+        let mut touches = Vec::new();
+        for event in events {
+            match event {
+                Event::Touch { id, phase, pos, .. } => {
+                    self.touch_screen = true;
+                    touches.push((id, phase, pos));
+                }
+                Event::PointerButton {
+                    pos,
+                    button: PointerButton::Primary,
+                    pressed,
+                    ..
+                } if !self.touch_screen => {
+                    let phase = if pressed {
+                        TouchPhase::Start
+                    } else {
+                        TouchPhase::End
+                    };
+                    touches.push((MOUSE, phase, pos));
+                }
+                Event::PointerMoved(pos) if !self.touch_screen => {
+                    touches.push((MOUSE, TouchPhase::Move, pos));
+                }
+                _ => {}
+            }
+        }
+        touches
+    }
+
+    fn touch(
+        &mut self,
+        mut connection: Option<&mut Connection>,
+        layout: &Layout,
+        id: TouchId,
+        phase: TouchPhase,
+        pos: Pos2,
+    ) {
+        let mut send = |command: RemoteCommand| {
+            if let Some(conn) = connection.as_deref_mut() {
+                conn.send(&command);
+            }
+        };
+
+        match phase {
+            TouchPhase::Start => {
+                let Some(control) = layout.control_at(pos) else {
+                    return;
+                };
+                self.touches.insert(id, control);
+
+                match control {
+                    Control::Button(button) => send(RemoteCommand::GamepadPress { button }),
+                    Control::Trigger(trigger) => {
+                        send(RemoteCommand::GamepadTrigger { trigger, value: 1. });
+                    }
+                    Control::Stick(stick) => self.move_stick(layout, stick, pos, send),
+                }
+            }
+            TouchPhase::Move => {
+                if let Some(&Control::Stick(stick)) = self.touches.get(&id) {
+                    self.move_stick(layout, stick, pos, send);
+                }
+            }
+            TouchPhase::End | TouchPhase::Cancel => match self.touches.remove(&id) {
+                Some(Control::Button(button)) => send(RemoteCommand::GamepadRelease { button }),
+                Some(Control::Trigger(trigger)) => {
+                    send(RemoteCommand::GamepadTrigger { trigger, value: 0. });
+                }
+                Some(Control::Stick(stick)) => {
+                    let center = side(stick, layout.sticks);
+                    self.move_stick(layout, stick, center, send);
+                }
+                None => {}
+            },
+        }
+    }
+
+    /// Pushes `stick` towards `pos`, as far as it goes.
+    fn move_stick(
+        &mut self,
+        layout: &Layout,
+        stick: GamepadStick,
+        pos: Pos2,
+        mut send: impl FnMut(RemoteCommand),
+    ) {
+        let mut offset = (pos - side(stick, layout.sticks)) / layout.stick_radius;
+        if offset.length() > 1. {
+            offset = offset.normalized();
+        }
+
+        // always sent when it's let go, so it doesn't drift
+        let last = side(stick, self.sticks.each_mut());
+        if (offset - *last).length() < STICK_STEP && offset != Vec2::ZERO {
+            return;
+        }
+
+        *last = offset;
+        send(RemoteCommand::GamepadStick { stick, x: offset.x, y: offset.y });
+    }
+
+    fn paint(&self, ui: &Ui, layout: &Layout) {
+        let painter = ui.painter();
+        let visuals = ui.visuals();
+        let held = |control: Control| self.touches.values().any(|&held| held == control);
+
+        for &(rect, label, control) in &layout.buttons {
+            let fill = if held(control) {
+                visuals.selection.bg_fill
+            } else {
+                visuals.widgets.inactive.bg_fill
+            };
+            painter.rect_filled(rect, rect.height().min(rect.width()) / 4., fill);
+            painter.text(
+                rect.center(),
+                Align2::CENTER_CENTER,
+                label,
+                FontId::proportional(16.),
+                visuals.text_color(),
+            );
+        }
+
+        for (stick, center) in [GamepadStick::Left, GamepadStick::Right]
+            .into_iter()
+            .zip(layout.sticks)
+        {
+            let radius = layout.stick_radius;
+            painter.circle_filled(center, radius, visuals.widgets.noninteractive.bg_fill);
+
+            let fill = if held(Control::Stick(stick)) {
+                visuals.selection.bg_fill
+            } else {
+                visuals.widgets.inactive.bg_fill
+            };
+            let knob = center + side(stick, self.sticks) * radius;
+            painter.circle_filled(knob, radius / 2., fill);
+        }
+    }
+}
+
+impl Layout {
+    fn new(rect: Rect) -> Self {
+        let (top, rest) = rect.split_top_bottom_at_fraction(0.15);
+        let (left, right) = rest.split_left_right_at_fraction(0.5);
+
+        let stick_radius = left.width().min(left.height() / 2.) * 0.3;
+        let at = |half: Rect, y: f32| pos2(half.center().x, half.top() + half.height() * y);
+        let square = |center: Pos2| Rect::from_center_size(center, Vec2::splat(stick_radius * 0.8));
+
+        let mut buttons = Vec::new();
+
+        let width = top.width() / TOP_ROW.len() as f32;
+        for (i, (label, control)) in TOP_ROW.into_iter().enumerate() {
+            let min = pos2(top.left() + width * i as f32, top.top());
+            let rect = Rect::from_min_size(min, Vec2::new(width, top.height())).shrink(4.);
+            buttons.push((rect, label, control));
+        }
+
+        // a cross of four squares, and a diamond of four more
+        let step = stick_radius * 0.85;
+        let around = |center: Pos2, [up, down, left, right]: [(&'static str, GamepadButton); 4]| {
+            [
+                (Vec2::new(0., -step), up),
+                (Vec2::new(0., step), down),
+                (Vec2::new(-step, 0.), left),
+                (Vec2::new(step, 0.), right),
+            ]
+            .map(|(offset, (label, button))| {
+                (square(center + offset), label, Control::Button(button))
+            })
+        };
+        buttons.extend(around(at(left, 0.75), [
+            ("↑", GamepadButton::DpadUp),
+            ("↓", GamepadButton::DpadDown),
+            ("←", GamepadButton::DpadLeft),
+            ("→", GamepadButton::DpadRight),
+        ]));
+        buttons.extend(around(at(right, 0.25), [
+            ("Y", GamepadButton::Y),
+            ("A", GamepadButton::A),
+            ("X", GamepadButton::X),
+            ("B", GamepadButton::B),
+        ]));
+
+        Self {
+            buttons,
+            sticks: [at(left, 0.25), at(right, 0.75)],
+            stick_radius,
+        }
+    }
+
+    /// The control a finger that lands at `pos` takes hold of.
+    fn control_at(&self, pos: Pos2) -> Option<Control> {
+        let button = self
+            .buttons
+            .iter()
+            .find(|(rect, ..)| rect.contains(pos))
+            .map(|&(.., control)| control);
+
+        // a little room around the sticks, so they're easy to grab
+        let stick = [GamepadStick::Left, GamepadStick::Right]
+            .into_iter()
+            .zip(self.sticks)
+            .find(|&(_, center)| center.distance(pos) < self.stick_radius * 1.5)
+            .map(|(stick, _)| Control::Stick(stick));
+
+        button.or(stick)
+    }
+}
+
+/// The one of a left and right pair that's for `stick`.
+fn side<T>(stick: GamepadStick, [left, right]: [T; 2]) -> T {
+    match stick {
+        GamepadStick::Left => left,
+        GamepadStick::Right => right,
+    }
+}
@@ -0,0 +1,88 @@
+use dark_remote_common::config::{Key, RemoteCommand};
+use egui::{Button, TextEdit, Ui, UiBuilder};
+
+use crate::{connection::Connection, utils::layout::SplitEqual};
+
+/// Keys that soft keyboards don't have, or that don't change the text field.
+const EXTRA_KEYS: [(&str, Key); 8] = [
+    ("Esc", Key::Escape),
+    ("Tab", Key::Tab),
+    ("Bksp", Key::Backspace),
+    ("←", Key::Left),
+    ("↑", Key::Up),
+    ("↓", Key::Down),
+    ("→", Key::Right),
+    ("Enter", Key::Return),
+];
+
+/// Streams whatever is typed into a text field to the server.
+#[derive(Default)]
+pub struct KeyboardPanel {
+    /// What's been typed since the last Enter. The server has been sent exactly this.
+    text: String,
+}
+
+impl KeyboardPanel {
+    pub fn show(&mut self, ui: &mut Ui, mut connection: Option<&mut Connection>) {
+        let rect = ui.available_rect_before_wrap().shrink(8.);
+        let (field, keys) = rect.split_top_bottom_at_fraction(0.5);
+
+        let before = self.text.clone();
+        let response =
+            ui.put(field.shrink(8.), TextEdit::singleline(&mut self.text).hint_text("Type here"));
+
+        if self.text != before
+            && let Some(conn) = connection.as_deref_mut()
+        {
+            send_diff(conn, &before, &self.text);
+        }
+
+        // a single line text field loses focus on enter, which also closes the soft keyboard
+        if response.lost_focus() && ui.input(|i| i.key_pressed(egui::Key::Enter)) {
+            if let Some(conn) = connection.as_deref_mut() {
+                conn.send(&RemoteCommand::KeyboardPress { key: Key::Return });
+            }
+            self.text.clear();
+            response.request_focus();
+        }
+
+        ui.scope_builder(UiBuilder::new().max_rect(keys), |ui| {
+            SplitEqual::horizontal().iterate(ui, EXTRA_KEYS, |ui, (label, key)| {
+                let res = ui.place(ui.available_rect_before_wrap().shrink(4.), Button::new(label));
+
+                if let Some(conn) = connection.as_deref_mut()
+                    && res.clicked()
+                {
+                    conn.send(&RemoteCommand::KeyboardPress { key });
+
+                    // keep the field in step with what the server has
+                    match key {
+                        Key::Backspace => {
+                            self.text.pop();
+                        }
+                        Key::Return => self.text.clear(),
+                        _ => {}
+                    }
+                }
+            });
+        });
+    }
+}
+
+/// Sends the backspaces and text it takes to turn `before` into `after` on the server.
+fn send_diff(conn: &mut Connection, before: &str, after: &str) {
+    let common = before
+        .chars()
+        .zip(after.chars())
+        .take_while(|(a, b)| a == b)
+        .count();
+
+    for _ in common..before.chars().count() {
+        conn.send(&RemoteCommand::KeyboardPress { key: Key::Backspace });
+    }
+
+    let added = after.chars().skip(common).collect::<String>();
+    if !added.is_empty() {
+        conn.send(&RemoteCommand::KeyboardText { text: added });
+    }
+}
@@ -1,6 +1,15 @@
 #![warn(clippy::all, rust_2018_idioms)]
 
 mod connection;
+#[cfg(not(target_arch = "wasm32"))]
+mod discovery;
+mod feedback;
+mod gamepad;
+mod keyboard;
+#[cfg(target_os = "android")]
+mod multicast_lock;
+mod servers;
+mod trackpad;
 mod utils;
 
 mod app;
@@ -0,0 +1,66 @@
+//! Android drops multicast packets to save power, unless an app holds a
+//! `WifiManager.MulticastLock`. This needs the `CHANGE_WIFI_MULTICAST_STATE` permission in the
+//! manifest.
+
+use jni::{
+    JavaVM,
+    objects::{GlobalRef, JObject, JValue},
+};
+
+/// Released when dropped.
+pub struct MulticastLock {
+    vm: JavaVM,
+    lock: GlobalRef,
+}
+
+impl MulticastLock {
+    pub fn acquire() -> jni::errors::Result<Self> {
+        let context = ndk_context::android_context();
+        // SAFETY: the VM lives as long as the app
+        let vm = unsafe { JavaVM::from_raw(context.vm().cast()) }?;
+        // SAFETY: the activity lives as long as the app, which is longer than this local reference
+        let activity = unsafe { JObject::from_raw(context.context().cast()) };
+
+        let lock = {
+            let mut env = vm.attach_current_thread()?;
+
+            let service = env.new_string("wifi")?;
+            let wifi = env
+                .call_method(
+                    &activity,
+                    "getSystemService",
+                    "(Ljava/lang/String;)Ljava/lang/Object;",
+                    &[JValue::from(&service)],
+                )?
+                .l()?;
+
+            let tag = env.new_string("dark-remote")?;
+            let lock = env
+                .call_method(
+                    &wifi,
+                    "createMulticastLock",
+                    "(Ljava/lang/String;)Landroid/net/wifi/WifiManager$MulticastLock;",
+                    &[JValue::from(&tag)],
+                )?
+                .l()?;
+            env.call_method(&lock, "acquire", "()V", &[])?;
+
+            env.new_global_ref(lock)?
+        };
+
+        Ok(Self { vm, lock })
+    }
+}
+
+impl Drop for MulticastLock {
+    fn drop(&mut self) {
+        let released = self
+            .vm
+            .attach_current_thread()
+            .and_then(|mut env| env.call_method(&self.lock, "release", "()V", &[]).map(drop));
+
+        if let Err(err) = released {
+            log::warn!("Failed to release the multicast lock: {err}");
+        }
+    }
+}
@@ -0,0 +1,183 @@
+use egui::{Button, Grid, TextEdit, Ui};
+
+/// The servers the user saved, and the screen to manage them.
+#[derive(Default, serde::Deserialize, serde::Serialize)]
+#[serde(default)]
+pub struct Servers {
+    pub saved: Vec<SavedServer>,
+    /// Connect to [`Self::last_used`] when the app starts.
+    pub auto_connect: bool,
+    /// URL of the server connected to most recently.
+    pub last_used: Option<String>,
+    /// The server being added or edited, if the form is open.
+    #[serde(skip)]
+    editing: Option<Editing>,
+}
+
+#[derive(Debug, Clone, Default, serde::Deserialize, serde::Serialize)]
+pub struct SavedServer {
+    pub name: String,
+    /// Where to connect, without a query, like `ws://tv.local:3000/ws`.
+    pub url: String,
+    /// Given by the server when pairing. Empty if not paired yet.
+    pub token: String,
+}
+
+/// A server that isn't saved, but could be, like one found on the network.
+pub struct Suggestion {
+    pub server: SavedServer,
+    /// Shown when hovering it.
+    pub details: String,
+}
+
+struct Editing {
+    /// Index into [`Servers::saved`], or `None` for a new server.
+    index: Option<usize>,
+    server: SavedServer,
+}
+
+impl Servers {
+    pub fn get(&self, url: &str) -> Option<&SavedServer> {
+        self.saved.iter().find(|server| server.url == url)
+    }
+
+    pub fn get_mut(&mut self, url: &str) -> Option<&mut SavedServer> {
+        self.saved.iter_mut().find(|server| server.url == url)
+    }
+
+    /// The server to connect to on startup, if any.
+    pub fn auto_connect(&self) -> Option<&SavedServer> {
+        self.get(self.last_used.as_deref()?)
+            .filter(|_| self.auto_connect)
+    }
+
+    /// Shows the list of servers, or the form if one is being added or edited. Returns the URL of
+    /// the server to connect to, if one was picked.
+    pub fn show(&mut self, ui: &mut Ui, suggestions: &[Suggestion]) -> Option<String> {
+        if let Some(editing) = &mut self.editing {
+            match show_form(ui, editing) {
+                Some(true) => {
+                    let Editing { index, server } = self.editing.take()?;
+                    match index.and_then(|index| self.saved.get_mut(index)) {
+                        Some(saved) => *saved = server,
+                        None => self.saved.push(server),
+                    }
+                }
+                Some(false) => self.editing = None,
+                None => {}
+            }
+            return None;
+        }
+
+        let mut connect = None;
+        let mut delete = None;
+
+        ui.heading("Servers");
+
+        if self.saved.is_empty() {
+            ui.label("No servers yet. Add one, or pick one found on the network.");
+        }
+
+        for (index, server) in self.saved.iter().enumerate() {
+            ui.horizontal(|ui| {
+                if ui.button(&server.name).on_hover_text(&server.url).clicked() {
+                    connect = Some(server.url.clone());
+                }
+
+                if ui.button("Edit").clicked() {
+                    self.editing = Some(Editing {
+                        index: Some(index),
+                        server: server.clone(),
+                    });
+                }
+
+                if ui.button("Delete").clicked() {
+                    delete = Some(index);
+                }
+            });
+        }
+
+        if let Some(index) = delete {
+            self.saved.remove(index);
+        }
+
+        if ui.button("Add").clicked() {
+            self.editing = Some(Editing {
+                index: None,
+                server: SavedServer::default(),
+            });
+        }
+
+        ui.checkbox(&mut self.auto_connect, "Connect to the last used server on startup");
+
+        let suggestions = suggestions
+            .iter()
+            .filter(|suggestion| self.get(&suggestion.server.url).is_none())
+            .collect::<Vec<_>>();
+
+        if !suggestions.is_empty() {
+            ui.add_space(16.);
+            ui.heading("On this network");
+
+            for suggestion in suggestions {
+                ui.horizontal(|ui| {
+                    ui.label(&suggestion.server.name)
+                        .on_hover_text(&suggestion.details);
+
+                    if ui.button("Save").clicked() {
+                        self.editing = Some(Editing {
+                            index: None,
+                            server: suggestion.server.clone(),
+                        });
+                    }
+                });
+            }
+        }
+
+        connect
+    }
+}
+
+/// Returns `Some(true)` when the server should be saved, and `Some(false)` when the form was
+/// cancelled.
+fn show_form(ui: &mut Ui, editing: &mut Editing) -> Option<bool> {
+    let server = &mut editing.server;
+
+    ui.heading(if editing.index.is_some() {
+        "Edit server"
+    } else {
+        "Add server"
+    });
+
+    Grid::new("server form").num_columns(2).show(ui, |ui| {
+        ui.label("Name");
+        ui.add(TextEdit::singleline(&mut server.name).hint_text("Living room"));
+        ui.end_row();
+
+        ui.label("URL");
+        ui.add(TextEdit::singleline(&mut server.url).hint_text("ws://tv.local:3000/ws"));
+        ui.end_row();
+
+        ui.label("Token");
+        ui.add(
+            TextEdit::singleline(&mut server.token)
+                .password(true)
+                .hint_text("Set by pairing"),
+        );
+        ui.end_row();
+    });
+
+    let valid = !server.name.trim().is_empty() && !server.url.trim().is_empty();
+
+    ui.horizontal(|ui| {
+        if ui.add_enabled(valid, Button::new("Save")).clicked() {
+            for field in [&mut server.name, &mut server.url, &mut server.token] {
+                *field = field.trim().to_owned();
+            }
+            return Some(true);
+        }
+
+        ui.button("Cancel").clicked().then_some(false)
+    })
+    .inner
+}
@@ -0,0 +1,602 @@
+use dark_remote_common::config::{RemoteCommand, Swipes, TrackpadButton};
+use egui::{Button, MultiTouchInfo, Rect, Response, Sense, Ui, Vec2};
+
+use crate::connection::Connection;
+
+/// How far two fingers move, in points, to scroll by one wheel click.
+const POINTS_PER_CLICK: f32 = 20.;
+
+/// How far fingers can move, in points, before a tap becomes a gesture.
+const TAP_SLOP: f32 = 10.;
+
+/// Longest a two-finger tap can take, in seconds.
+const TAP_TIME: f64 = 0.3;
+
+/// How much further apart or closer together two fingers get before it's a pinch instead of a
+/// scroll.
+const PINCH_THRESHOLD: f32 = 0.1;
+
+/// How far three fingers move, in points, to swipe.
+const SWIPE_DISTANCE: f32 = 80.;
+
+/// How long after a tap a touch turns into a drag, in seconds. Taps are sent this late, in case
+/// one does.
+const TAP_DRAG_TIME: f64 = 0.25;
+
+/// Moves the pointer with one finger, and turns multi-touch gestures into scrolling, zooming,
+/// right clicks and swipes.
+///
+/// Tapping and then touching again right away holds the left button down until the finger is
+/// lifted, to drag things. With drag lock, it stays down until the next tap.
+#[derive(Default, serde::Deserialize, serde::Serialize)]
+#[serde(default)]
+pub struct TrackpadPanel {
+    drag_lock: bool,
+    #[serde(skip)]
+    drag: Drag,
+    /// The multi-touch gesture in progress, if any.
+    #[serde(skip)]
+    gesture: Option<Gesture>,
+    /// Set until every finger is lifted after a gesture, so the last one doesn't move the pointer
+    /// or click.
+    #[serde(skip)]
+    after_gesture: bool,
+}
+
+#[derive(Default, Clone, Copy)]
+enum Drag {
+    #[default]
+    Idle,
+    /// Tapped at this time, like [`egui::InputState::time`]. Clicks if nothing else happens
+    /// within [`TAP_DRAG_TIME`].
+    Tapped(f64),
+    /// The left button is down for as long as the finger is.
+    Dragging {
+        /// Otherwise it was a double tap.
+        moved: bool,
+    },
+    /// The left button stays down after a drag, until the next tap.
+    Locked,
+}
+
+struct Gesture {
+    /// In seconds, like [`egui::InputState::time`].
+    start_time: f64,
+    /// The most fingers that were down at once.
+    fingers: usize,
+    /// How far the fingers moved since the start, in points.
+    moved: Vec2,
+    /// How much further apart the fingers got since the start.
+    zoom: f32,
+    /// `None` while it could still be a tap.
+    kind: Option<GestureKind>,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum GestureKind {
+    Scroll,
+    Pinch,
+    /// A swipe that already sent its command.
+    Swiped,
+}
+
+impl Gesture {
+    fn is_tap(&self, now: f64) -> bool {
+        self.fingers == 2 && self.kind.is_none() && now - self.start_time < TAP_TIME
+    }
+}
+
+impl TrackpadPanel {
+    pub fn show(&mut self, ui: &mut Ui, connection: Option<&mut Connection>, swipes: &Swipes) {
+        let rect = ui.available_rect_before_wrap().shrink(10.);
+
+        let response = ui.allocate_rect(rect, Sense::click_and_drag());
+
+        ui.painter()
+            .rect_filled(rect, 50., ui.visuals().widgets.noninteractive.bg_fill);
+
+        // on top of the trackpad, so it gets the touches there
+        let lock_rect =
+            Rect::from_min_size(rect.left_top() + Vec2::splat(16.), Vec2::new(96., 28.));
+        let lock = ui.put(lock_rect, Button::selectable(self.drag_lock, "Drag lock"));
+
+        if let Some(conn) = connection {
+            for command in self.update(ui, &response, lock.clicked(), swipes) {
+                conn.send(&command);
+            }
+        }
+    }
+
+    /// What the touches on the trackpad `response` came to this frame.
+    fn update(
+        &mut self,
+        ui: &Ui,
+        response: &Response,
+        toggle_lock: bool,
+        swipes: &Swipes,
+    ) -> Vec<RemoteCommand> {
+        let mut commands = Vec::new();
+
+        if toggle_lock {
+            self.drag_lock = !self.drag_lock;
+            if !self.drag_lock && matches!(self.drag, Drag::Locked) {
+                commands.push(RemoteCommand::TrackpadRelease { button: TrackpadButton::Left });
+                self.drag = Drag::Idle;
+            }
+        }
+
+        let touch = ui
+            .input(|i| i.multi_touch())
+            .filter(|touch| response.rect.contains(touch.start_pos));
+        if let Some(touch) = touch {
+            self.after_gesture = true;
+            self.touch(&mut commands, &touch, swipes);
+            return commands;
+        }
+
+        // the gesture ends as soon as all but one finger are lifted
+        if let Some(gesture) = self.gesture.take()
+            && gesture.is_tap(ui.input(|i| i.time))
+        {
+            commands.push(RemoteCommand::TrackpadClick { button: TrackpadButton::Right });
+        }
+
+        if self.after_gesture {
+            if !ui.input(|i| i.any_touches()) {
+                self.after_gesture = false;
+            }
+            return commands;
+        }
+
+        self.drag(ui, response, &mut commands);
+
+        // the server applies acceleration, and doesn't mind the time wrapping around
+        let delta = response.drag_delta();
+        if delta != Vec2::ZERO {
+            let time = ui.input(|i| i.time * 1000.) as u64 as u32;
+
+            commands.push(RemoteCommand::TrackpadMove {
+                delta_x: delta.x,
+                delta_y: delta.y,
+                time: Some(time),
+            });
+        }
+
+        if response.long_touched() {
+            commands.push(RemoteCommand::TrackpadClick { button: TrackpadButton::Right });
+        }
+
+        commands
+    }
+
+    /// Turns taps into clicks, and taps followed by a touch into drags.
+    fn drag(&mut self, ui: &Ui, response: &Response, commands: &mut Vec<RemoteCommand>) {
+        let now = ui.input(|i| i.time);
+        let down = response.is_pointer_button_down_on();
+        let left = TrackpadButton::Left;
+
+        self.drag = match self.drag {
+            Drag::Idle if response.clicked() => Drag::Tapped(now),
+            Drag::Tapped(_) if down => {
+                commands.push(RemoteCommand::TrackpadPress { button: left });
+                Drag::Dragging { moved: false }
+            }
+            Drag::Tapped(at) if now - at >= TAP_DRAG_TIME => {
+                commands.push(RemoteCommand::TrackpadClick { button: left });
+                Drag::Idle
+            }
+            Drag::Tapped(at) => {
+                ui.ctx()
+                    .request_repaint_after_secs((at + TAP_DRAG_TIME - now) as f32);
+                Drag::Tapped(at)
+            }
+            Drag::Dragging { moved } if down => Drag::Dragging {
+                moved: moved || response.drag_delta() != Vec2::ZERO,
+            },
+            Drag::Dragging { moved: true } if self.drag_lock => Drag::Locked,
+            Drag::Dragging { moved } => {
+                commands.push(RemoteCommand::TrackpadRelease { button: left });
+                if !moved {
+                    commands.push(RemoteCommand::TrackpadClick { button: left });
+                }
+                Drag::Idle
+            }
+            Drag::Locked if response.clicked() => {
+                commands.push(RemoteCommand::TrackpadRelease { button: left });
+                Drag::Idle
+            }
+            drag @ (Drag::Idle | Drag::Locked) => drag,
+        };
+    }
+
+    /// Handles a frame of a gesture with two or more fingers.
+    fn touch(
+        &mut self,
+        commands: &mut Vec<RemoteCommand>,
+        touch: &MultiTouchInfo,
+        swipes: &Swipes,
+    ) {
+        let gesture = self.gesture.get_or_insert(Gesture {
+            start_time: touch.start_time,
+            fingers: 0,
+            moved: Vec2::ZERO,
+            zoom: 1.,
+            kind: None,
+        });
+        gesture.fingers = gesture.fingers.max(touch.num_touches);
+        gesture.moved += touch.translation_delta;
+        gesture.zoom *= touch.zoom_delta;
+
+        // the fingers of a three-finger swipe don't all land at once, so it can take over
+        if gesture.fingers >= 3 {
+            if gesture.kind != Some(GestureKind::Swiped) && gesture.moved.length() > SWIPE_DISTANCE
+            {
+                gesture.kind = Some(GestureKind::Swiped);
+                commands.extend(swipe(swipes, gesture.moved).cloned());
+            }
+            return;
+        }
+
+        if gesture.kind.is_none() {
+            if (gesture.zoom - 1.).abs() > PINCH_THRESHOLD {
+                gesture.kind = Some(GestureKind::Pinch);
+            } else if gesture.moved.length() > TAP_SLOP {
+                gesture.kind = Some(GestureKind::Scroll);
+            }
+        }
+
+        match gesture.kind {
+            // content follows the fingers, like scrolling on the phone itself
+            Some(GestureKind::Scroll) if touch.translation_delta != Vec2::ZERO => {
+                commands.push(RemoteCommand::TrackpadScroll {
+                    delta_x: -touch.translation_delta.x / POINTS_PER_CLICK,
+                    delta_y: -touch.translation_delta.y / POINTS_PER_CLICK,
+                });
+            }
+            Some(GestureKind::Pinch) if touch.zoom_delta != 1. => {
+                commands.push(RemoteCommand::TrackpadZoom { factor: touch.zoom_delta });
+            }
+            _ => {}
+        }
+    }
+}
+
+/// The command for a swipe in the direction the fingers `moved` the most in.
+fn swipe(swipes: &Swipes, moved: Vec2) -> Option<&RemoteCommand> {
+    let command = if moved.x.abs() > moved.y.abs() {
+        if moved.x > 0. {
+            &swipes.right
+        } else {
+            &swipes.left
+        }
+    } else if moved.y > 0. {
+        &swipes.down
+    } else {
+        &swipes.up
+    };
+    command.as_ref()
+}
+
+#[cfg(test)]
+mod tests {
+    use egui::{
+        CentralPanel, Context, Event, Modifiers, PointerButton, Pos2, RawInput, TouchDeviceId,
+        TouchId, TouchPhase, pos2,
+    };
+
+    use super::*;
+
+    /// A trackpad filling the screen, fed touches the way egui-winit passes them on: every finger
+    /// is a touch, and the first one down also moves the pointer.
+    struct Trackpad {
+        ctx: Context,
+        panel: TrackpadPanel,
+        swipes: Swipes,
+        time: f64,
+        events: Vec<Event>,
+        pointer: Option<u64>,
+        toggle_lock: bool,
+    }
+
+    impl Trackpad {
+        fn new() -> Self {
+            let mut trackpad = Self {
+                ctx: Context::default(),
+                panel: TrackpadPanel::default(),
+                swipes: Swipes::default(),
+                time: 0.,
+                events: Vec::new(),
+                pointer: None,
+                toggle_lock: false,
+            };
+            // egui finds what's under the pointer in the last frame
+            trackpad.frame(0.);
+            trackpad
+        }
+
+        fn touch(&mut self, finger: u64, phase: TouchPhase, (x, y): (f32, f32)) {
+            let pos = pos2(x, y);
+            self.events.push(Event::Touch {
+                device_id: TouchDeviceId(0),
+                id: TouchId(finger),
+                phase,
+                pos,
+                force: None,
+            });
+
+            if phase == TouchPhase::Start && self.pointer.is_none() {
+                self.pointer = Some(finger);
+            }
+            if self.pointer != Some(finger) {
+                return;
+            }
+            let button = |pressed| Event::PointerButton {
+                pos,
+                button: PointerButton::Primary,
+                pressed,
+                modifiers: Modifiers::NONE,
+            };
+            match phase {
+                TouchPhase::Start => {
+                    self.events.push(Event::PointerMoved(pos));
+                    self.events.push(button(true));
+                }
+                TouchPhase::Move => self.events.push(Event::PointerMoved(pos)),
+                TouchPhase::End | TouchPhase::Cancel => {
+                    self.pointer = None;
+                    self.events.push(button(false));
+                    self.events.push(Event::PointerGone);
+                }
+            }
+        }
+
+        fn down(&mut self, finger: u64, pos: (f32, f32)) {
+            self.touch(finger, TouchPhase::Start, pos);
+        }
+
+        fn to(&mut self, finger: u64, pos: (f32, f32)) {
+            self.touch(finger, TouchPhase::Move, pos);
+        }
+
+        fn up(&mut self, finger: u64, pos: (f32, f32)) {
+            self.touch(finger, TouchPhase::End, pos);
+        }
+
+        /// Runs a frame `seconds` after the last one, with the touches since, and returns what the
+        /// trackpad sent.
+        fn frame(&mut self, seconds: f64) -> Vec<RemoteCommand> {
+            self.time += seconds;
+            let input = RawInput {
+                screen_rect: Some(Rect::from_min_size(Pos2::ZERO, Vec2::splat(400.))),
+                time: Some(self.time),
+                events: std::mem::take(&mut self.events),
+                ..RawInput::default()
+            };
+
+            let mut commands = Vec::new();
+            let toggle_lock = std::mem::take(&mut self.toggle_lock);
+            let (panel, swipes) = (&mut self.panel, &self.swipes);
+            let _output = self.ctx.run(input, |ctx| {
+                CentralPanel::default().show(ctx, |ui| {
+                    let response = ui.allocate_rect(ui.max_rect(), Sense::click_and_drag());
+                    commands.extend(panel.update(ui, &response, toggle_lock, swipes));
+                });
+            });
+            commands
+        }
+
+        /// Runs frames until egui sees a gesture with the fingers that went down, which takes one
+        /// more than for the pointer, and returns what was sent.
+        fn land(&mut self) -> Vec<RemoteCommand> {
+            let mut commands = self.frame(0.02);
+            commands.extend(self.frame(0.02));
+            commands
+        }
+
+        /// A tap with one finger, which doesn't send anything yet.
+        fn tap(&mut self) {
+            self.down(0, (200., 200.));
+            assert_sent(&self.frame(0.02), "[]");
+            self.up(0, (200., 200.));
+            assert_sent(&self.frame(0.05), "[]");
+        }
+
+        /// Moves one finger that's down across the trackpad, and returns how far the pointer went.
+        fn swipe(&mut self, finger: u64) -> f32 {
+            (1..=5)
+                .flat_map(|step| {
+                    self.to(finger, (200. + step as f32 * 10., 200.));
+                    self.frame(0.02)
+                })
+                .map(|command| match command {
+                    RemoteCommand::TrackpadMove { delta_x, delta_y, .. } => {
+                        assert_eq!(delta_y, 0.);
+                        delta_x
+                    }
+                    command => panic!("sent {command:?} while moving"),
+                })
+                .sum()
+        }
+    }
+
+    fn assert_sent(commands: &[RemoteCommand], expected: &str) {
+        assert_eq!(format!("{commands:?}"), expected);
+    }
+
+    #[test]
+    fn tap_clicks_later() {
+        let mut trackpad = Trackpad::new();
+        trackpad.tap();
+        assert_sent(&trackpad.frame(0.1), "[]");
+        assert_sent(&trackpad.frame(0.2), "[TrackpadClick { button: Left }]");
+        assert_sent(&trackpad.frame(0.1), "[]");
+    }
+
+    #[test]
+    fn moving_moves() {
+        let mut trackpad = Trackpad::new();
+        trackpad.down(0, (200., 200.));
+        trackpad.frame(0.02);
+        assert_eq!(trackpad.swipe(0), 50.);
+        trackpad.up(0, (250., 200.));
+        assert_sent(&trackpad.frame(0.02), "[]");
+        assert_sent(&trackpad.frame(1.), "[]");
+    }
+
+    #[test]
+    fn tap_and_drag() {
+        let mut trackpad = Trackpad::new();
+        trackpad.tap();
+        trackpad.down(0, (200., 200.));
+        assert_sent(&trackpad.frame(0.02), "[TrackpadPress { button: Left }]");
+        assert_eq!(trackpad.swipe(0), 50.);
+        trackpad.up(0, (250., 200.));
+        assert_sent(&trackpad.frame(0.02), "[TrackpadRelease { button: Left }]");
+        assert_sent(&trackpad.frame(1.), "[]");
+    }
+
+    #[test]
+    fn double_tap() {
+        let mut trackpad = Trackpad::new();
+        trackpad.tap();
+        trackpad.down(0, (200., 200.));
+        assert_sent(&trackpad.frame(0.02), "[TrackpadPress { button: Left }]");
+        trackpad.up(0, (200., 200.));
+        assert_sent(
+            &trackpad.frame(0.02),
+            "[TrackpadRelease { button: Left }, TrackpadClick { button: Left }]",
+        );
+        assert_sent(&trackpad.frame(1.), "[]");
+    }
+
+    #[test]
+    fn drag_lock() {
+        let mut trackpad = Trackpad::new();
+        trackpad.toggle_lock = true;
+        trackpad.tap();
+        trackpad.down(0, (200., 200.));
+        assert_sent(&trackpad.frame(0.02), "[TrackpadPress { button: Left }]");
+        trackpad.swipe(0);
+        trackpad.up(0, (250., 200.));
+        assert_sent(&trackpad.frame(0.02), "[]");
+
+        // the button stays down while moving on
+        trackpad.down(0, (200., 200.));
+        trackpad.frame(0.5);
+        assert_eq!(trackpad.swipe(0), 50.);
+        trackpad.up(0, (250., 200.));
+        assert_sent(&trackpad.frame(0.02), "[]");
+        assert!(matches!(trackpad.panel.drag, Drag::Locked));
+    }
+
+    #[test]
+    fn tap_releases_drag_lock() {
+        let mut trackpad = Trackpad::new();
+        trackpad.panel.drag_lock = true;
+        trackpad.panel.drag = Drag::Locked;
+        trackpad.down(0, (200., 200.));
+        trackpad.frame(0.02);
+        trackpad.up(0, (200., 200.));
+        assert_sent(&trackpad.frame(0.02), "[TrackpadRelease { button: Left }]");
+        assert_sent(&trackpad.frame(1.), "[]");
+    }
+
+    #[test]
+    fn turning_drag_lock_off_releases() {
+        let mut trackpad = Trackpad::new();
+        trackpad.panel.drag_lock = true;
+        trackpad.panel.drag = Drag::Locked;
+        trackpad.toggle_lock = true;
+        assert_sent(&trackpad.frame(0.02), "[TrackpadRelease { button: Left }]");
+        assert!(!trackpad.panel.drag_lock);
+    }
+
+    #[test]
+    fn two_finger_tap_right_clicks() {
+        let mut trackpad = Trackpad::new();
+        trackpad.down(0, (150., 200.));
+        trackpad.down(1, (250., 200.));
+        assert_sent(&trackpad.land(), "[]");
+        trackpad.up(1, (250., 200.));
+        trackpad.up(0, (150., 200.));
+        assert_sent(&trackpad.frame(0.05), "[TrackpadClick { button: Right }]");
+        // and the finger that moved the pointer doesn't click too
+        assert_sent(&trackpad.frame(1.), "[]");
+    }
+
+    #[test]
+    fn two_finger_scroll() {
+        let mut trackpad = Trackpad::new();
+        trackpad.down(0, (150., 200.));
+        trackpad.down(1, (250., 200.));
+        trackpad.land();
+
+        let mut scrolled = 0.;
+        for step in 1..=5 {
+            let y = 200. + step as f32 * 10.;
+            trackpad.to(0, (150., y));
+            trackpad.to(1, (250., y));
+            for command in trackpad.frame(0.02) {
+                let RemoteCommand::TrackpadScroll { delta_x, delta_y } = command else {
+                    panic!("sent {command:?} while scrolling");
+                };
+                assert_eq!(delta_x, 0.);
+                scrolled += delta_y;
+            }
+        }
+        // the first 10 points could still have been a tap
+        assert_eq!(scrolled, -40. / POINTS_PER_CLICK);
+
+        trackpad.up(0, (150., 250.));
+        trackpad.frame(0.02);
+        // the finger left behind doesn't move the pointer
+        trackpad.to(1, (300., 250.));
+        assert_sent(&trackpad.frame(0.02), "[]");
+        trackpad.up(1, (300., 250.));
+        assert_sent(&trackpad.frame(0.02), "[]");
+        assert_sent(&trackpad.frame(1.), "[]");
+    }
+
+    #[test]
+    fn two_finger_zoom() {
+        let mut trackpad = Trackpad::new();
+        trackpad.down(0, (150., 200.));
+        trackpad.down(1, (250., 200.));
+        trackpad.land();
+
+        let mut zoomed = 1.;
+        for step in 1..=5 {
+            let spread = 50. + step as f32 * 10.;
+            trackpad.to(0, (200. - spread, 200.));
+            trackpad.to(1, (200. + spread, 200.));
+            for command in trackpad.frame(0.02) {
+                let RemoteCommand::TrackpadZoom { factor } = command else {
+                    panic!("sent {command:?} while zooming");
+                };
+                zoomed *= factor;
+            }
+        }
+        // twice as far apart
+        assert!((zoomed - 2.).abs() < 1e-4, "zoomed by {zoomed}");
+    }
+
+    #[test]
+    fn three_finger_swipe() {
+        let mut trackpad = Trackpad::new();
+        trackpad.swipes.left = Some(RemoteCommand::MediaNext);
+        for finger in 0..3 {
+            trackpad.down(finger, (300., 100. + finger as f32 * 50.));
+        }
+        trackpad.land();
+
+        let mut sent = Vec::new();
+        for step in 1..=10 {
+            for finger in 0..3 {
+                trackpad.to(finger, (300. - step as f32 * 15., 100. + finger as f32 * 50.));
+            }
+            sent.extend(trackpad.frame(0.02));
+        }
+        // once, when the fingers were far enough
+        assert_sent(&sent, "[MediaNext]");
+    }
+}